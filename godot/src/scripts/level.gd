@@ -91,19 +91,28 @@ func _on_player_connect_faces(from: Vector3, _from_face: VoxelWorld.Face, to: Ve
 		printerr('second block is not valid gate, cancelling connection')
 		return
 
-	var from_output_offset = VoxelWorld.FACE_NORMALS[from_block.output_face] * (0.5 + Cable.CABLE_WIDTH() / 2.0)
-	var to_input_offset = VoxelWorld.FACE_NORMALS[to_block.input_face] * (0.5 + Cable.CABLE_WIDTH() / 2.0)
-
 	var success = $CircuitSimulation.connect_blocks(from_coords, to_coords, from_block.block_kind == VoxelWorld.BlockKind.AND, to_block.block_kind == VoxelWorld.BlockKind.AND)
 	if not success:
 		printerr('connection would create an illegal cycle, cancelling connection')
 		return
 
+	add_cable(from_coords, to_coords)
+
+func add_cable(from_coords: Vector3i, to_coords: Vector3i):
+	var from_block = voxel_world.blocks[from_coords]
+	var to_block = voxel_world.blocks[to_coords]
+	var from_output_offset = VoxelWorld.FACE_NORMALS[from_block.output_face] * (0.5 + Cable.CABLE_WIDTH() / 2.0)
+	var to_input_offset = VoxelWorld.FACE_NORMALS[to_block.input_face] * (0.5 + Cable.CABLE_WIDTH() / 2.0)
+
 	var cable_start = voxel_world.coordinate_to_position(from_coords) + from_output_offset
 	var cable_end = voxel_world.coordinate_to_position(to_coords) + to_input_offset
 	var cable = Cable.create(cable_start, cable_end)
 	self.add_child(cable)
-	$CircuitSimulation.register_cable(from_coords, cable)
+	$CircuitSimulation.register_cable(from_coords, to_coords, cable)
+
+# Undo and redo free the cables they remove themselves
+func _on_circuit_simulation_cable_connected(from: Vector3i, to: Vector3i):
+	add_cable(from, to)
 
 func _on_player_reset_position():
 	player.position = initial_player_position
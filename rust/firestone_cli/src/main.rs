@@ -0,0 +1,469 @@
+mod stimulus;
+mod vcd;
+
+use std::{collections::HashMap, env, fs, fs::File, io::BufWriter, process::ExitCode};
+
+use simulation_engine::{
+    ComponentId, SimulationEngine, TimingMode, dot::DotOptions, fault::Fault, testbench::Testbench,
+};
+use stimulus::{Command, Stimulus};
+use vcd::VcdWriter;
+
+const USAGE: &str = "\
+Usage: firestone_cli <CIRCUIT> [OPTIONS]
+
+Runs a circuit saved by the simulation engine without the game.
+
+Options:
+  --ticks <N>               Ticks to run, defaults to 1, or to 10000 with --until-stable
+  --until-stable            Stop at the first tick that changes nothing, fail if there's none
+  --gate-delay <TICKS>      Make every component but delays take TICKS ticks to update
+  --probe [<NAME>=]<SIGNAL> Print the value of SIGNAL at the end, named NAME or SIGNAL,
+                            SIGNAL is `<component>[.<output>]`, by index or label
+  --trace                   Print the probes after every tick
+  --stimulus <FILE>         Drive signals and check expectations listed in FILE, at ticks
+                            counted from the tick the circuit was saved at
+  --vcd <FILE>              Write the probes' waveforms to FILE
+  --dot <FILE>              Write a Graphviz schematic of the circuit and its final values to FILE
+  --testbench <FILE>        Check the test vectors in FILE, after the ticks above run
+  --fault-coverage          With --testbench, also report which stuck-at faults it detects
+  --help                    Print this message
+
+Exits with 1 if an expectation fails or the circuit doesn't stabilize, 2 on other errors.";
+
+#[derive(Default)]
+struct Args {
+    circuit: String,
+    ticks: Option<u64>,
+    until_stable: bool,
+    gate_delay: Option<u32>,
+    probes: Vec<(String, String)>,
+    trace: bool,
+    stimulus: Option<String>,
+    vcd: Option<String>,
+    dot: Option<String>,
+    testbench: Option<String>,
+    fault_coverage: bool,
+    help: bool,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args::default();
+    let mut circuit = None;
+
+    let mut iter = env::args().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().ok_or(format!("missing value for `{arg}`"));
+        match arg.as_str() {
+            "--ticks" => {
+                let ticks = value()?;
+                args.ticks = Some(
+                    ticks
+                        .parse()
+                        .map_err(|_| format!("invalid tick count `{ticks}`"))?,
+                );
+            }
+            "--until-stable" => args.until_stable = true,
+            "--gate-delay" => {
+                let ticks = value()?;
+                args.gate_delay = Some(
+                    ticks
+                        .parse()
+                        .ok()
+                        .filter(|&ticks| ticks > 0)
+                        .ok_or(format!("invalid gate delay `{ticks}`"))?,
+                );
+            }
+            "--probe" => {
+                let probe = value()?;
+                let (name, signal) = probe.split_once('=').unwrap_or((&probe, &probe));
+                args.probes.push((name.to_owned(), signal.to_owned()));
+            }
+            "--trace" => args.trace = true,
+            "--stimulus" => args.stimulus = Some(value()?),
+            "--vcd" => args.vcd = Some(value()?),
+            "--dot" => args.dot = Some(value()?),
+            "--testbench" => args.testbench = Some(value()?),
+            "--fault-coverage" => args.fault_coverage = true,
+            "--help" | "-h" => {
+                args.help = true;
+                return Ok(args);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option `{arg}`\n\n{USAGE}")),
+            _ if circuit.is_none() => circuit = Some(arg),
+            _ => return Err(format!("unexpected argument `{arg}`\n\n{USAGE}")),
+        }
+    }
+
+    args.circuit = circuit.ok_or(USAGE)?;
+    Ok(args)
+}
+
+/// Finds a signal by probe name, or parses it as `<component>[.<output>]`, where the
+/// component is its index in the file or its label.
+fn resolve_signal(
+    signal: &str,
+    probes: &[(String, (ComponentId, usize))],
+    engine: &SimulationEngine,
+    ids: &[ComponentId],
+) -> Result<(ComponentId, usize), String> {
+    if let Some(&(_, resolved)) = probes.iter().find(|(name, _)| name == signal) {
+        return Ok(resolved);
+    }
+    // labels can contain dots, like the paths of the HDL
+    let err = || format!("unknown signal `{signal}`");
+    let (id, output) = match engine.find_by_label(signal) {
+        Some(id) => (id, 0),
+        None => {
+            let (component, output) = match signal.rsplit_once('.') {
+                Some((component, output)) if output.parse::<usize>().is_ok() => (component, output),
+                _ => (signal, "0"),
+            };
+            let output = output.parse().map_err(|_| err())?;
+            let id = match component.parse::<usize>() {
+                Ok(index) => *ids.get(index).ok_or_else(err)?,
+                Err(_) => engine.find_by_label(component).ok_or_else(err)?,
+            };
+            (id, output)
+        }
+    };
+    let outputs = engine.component(id).map_err(|_| err())?.kind.arity().1;
+    if output >= outputs {
+        return Err(err());
+    }
+    Ok((id, output))
+}
+
+/// The label of a component, or its index in the file.
+fn component_name(engine: &SimulationEngine, ids: &[ComponentId], id: ComponentId) -> String {
+    match engine.label(id) {
+        Some(label) => format!("`{label}`"),
+        None => ids
+            .iter()
+            .position(|&other| other == id)
+            .unwrap()
+            .to_string(),
+    }
+}
+
+/// Returns whether all expectations held.
+fn run(args: Args) -> Result<bool, String> {
+    let text = fs::read_to_string(&args.circuit)
+        .map_err(|e| format!("failed to read `{}`: {e}", args.circuit))?;
+    let (mut engine, ids) =
+        SimulationEngine::load(&text).map_err(|e| format!("{}: {e}", args.circuit))?;
+    if let Some(ticks) = args.gate_delay {
+        engine.set_timing_mode(TimingMode::UnitDelay { ticks });
+    }
+    // values can be on their way to the outputs for that many ticks without changing them
+    let quiet_ticks = match engine.timing_mode() {
+        TimingMode::ZeroDelay => 1,
+        TimingMode::UnitDelay { ticks } => ticks,
+    };
+    let mut quiet = 0;
+
+    let mut probes = vec![];
+    for (name, signal) in &args.probes {
+        let resolved = resolve_signal(signal, &probes, &engine, &ids)?;
+        probes.push((name.clone(), resolved));
+    }
+
+    let stimulus = match &args.stimulus {
+        Some(path) => Stimulus::parse(
+            &fs::read_to_string(path).map_err(|e| format!("failed to read `{path}`: {e}"))?,
+        )?,
+        None => Stimulus::default(),
+    };
+
+    // resolve everything up front, so typos fail before running anything
+    let mut commands = vec![];
+    for (&tick, tick_commands) in &stimulus.commands {
+        for command in tick_commands {
+            let (Command::Drive(signal, _) | Command::Release(signal) | Command::Expect(signal, _)) =
+                command;
+            commands.push((
+                tick,
+                command,
+                resolve_signal(signal, &probes, &engine, &ids)?,
+            ));
+        }
+    }
+
+    let mut vcd = match &args.vcd {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!("failed to create `{path}`: {e}"))?;
+            let names: Vec<&str> = probes.iter().map(|(name, _)| name.as_str()).collect();
+            Some(VcdWriter::new(BufWriter::new(file), &names).map_err(|e| e.to_string())?)
+        }
+        None => None,
+    };
+    let probe_values = |engine: &SimulationEngine| -> Vec<bool> {
+        probes
+            .iter()
+            .map(|&(_, (id, output))| engine.is_on_at(id, output))
+            .collect()
+    };
+    if let Some(vcd) = &mut vcd {
+        vcd.sample(engine.current_tick(), &probe_values(&engine))
+            .map_err(|e| e.to_string())?;
+    }
+
+    let max_ticks = args
+        .ticks
+        .unwrap_or(if args.until_stable { 10_000 } else { 1 })
+        .max(stimulus.last_tick().unwrap_or(0));
+    let mut all_passed = true;
+    let mut stable = false;
+    // stimulus ticks count from the tick the circuit was saved at
+    let start_tick = engine.current_tick();
+
+    for step in 1..=max_ticks {
+        let tick = start_tick + step;
+        let tick_commands = commands
+            .iter()
+            .filter(|(command_tick, ..)| *command_tick == step);
+
+        for &(_, command, (id, output)) in tick_commands.clone() {
+            match *command {
+                Command::Drive(_, value) => {
+                    engine
+                        .drive(id, output, value)
+                        .map_err(|e| e.describe(&engine))?;
+                }
+                Command::Release(_) => engine.release(id, output),
+                Command::Expect(..) => {}
+            }
+        }
+
+        engine.run_step();
+
+        for &bus in engine.contentions() {
+            let name = component_name(&engine, &ids, bus);
+            eprintln!("tick {tick}: bus contention on component {name}");
+        }
+        if !engine.unstable().is_empty() {
+            let names: Vec<String> = engine
+                .unstable()
+                .iter()
+                .map(|&id| component_name(&engine, &ids, id))
+                .collect();
+            eprintln!("tick {tick}: loop didn't settle on {}", names.join(", "));
+        }
+
+        for &(_, command, (id, output)) in tick_commands {
+            if let Command::Expect(ref signal, expected) = *command {
+                let value = engine.is_on_at(id, output);
+                if value != expected {
+                    eprintln!(
+                        "tick {tick}: expected `{signal}` to be {}, got {}",
+                        expected as u8, value as u8
+                    );
+                    all_passed = false;
+                }
+            }
+        }
+
+        if args.trace {
+            print_probes(&format!("tick {tick}:"), &probes, &engine);
+        }
+        if let Some(vcd) = &mut vcd {
+            vcd.sample(tick, &probe_values(&engine))
+                .map_err(|e| e.to_string())?;
+        }
+
+        quiet = match engine.last_changes() {
+            [] => quiet + 1,
+            _ => 0,
+        };
+        if args.until_stable
+            && quiet >= quiet_ticks
+            && stimulus
+                .last_tick()
+                .is_none_or(|last_tick| step >= last_tick)
+        {
+            stable = true;
+            break;
+        }
+    }
+
+    if let Some(vcd) = vcd {
+        vcd.finish().map_err(|e| e.to_string())?;
+    }
+
+    print_probes(
+        &format!("tick {}:", engine.current_tick()),
+        &probes,
+        &engine,
+    );
+
+    if let Some(path) = &args.dot {
+        let options = DotOptions {
+            values: true,
+            ..DotOptions::default()
+        };
+        fs::write(path, engine.to_dot(&options))
+            .map_err(|e| format!("failed to write `{path}`: {e}"))?;
+    }
+
+    if args.until_stable && !stable {
+        eprintln!("circuit didn't stabilize after {max_ticks} ticks");
+        return Ok(false);
+    }
+
+    if let Some(path) = &args.testbench {
+        let text = fs::read_to_string(path).map_err(|e| format!("failed to read `{path}`: {e}"))?;
+        let testbench = Testbench::parse(&text).map_err(|e| format!("{path}: {e}"))?;
+        let signals: HashMap<&str, (ComponentId, usize)> = testbench
+            .inputs
+            .iter()
+            .chain(&testbench.outputs)
+            .filter_map(|name| {
+                let signal = resolve_signal(name, &probes, &engine, &ids).ok()?;
+                Some((name.as_str(), signal))
+            })
+            .collect();
+        if args.fault_coverage {
+            let coverage = testbench
+                .fault_coverage(
+                    &engine,
+                    |signal| signals.get(signal).copied(),
+                    &engine.stuck_at_faults(),
+                )
+                .map_err(|e| format!("{path}: {e}"))?;
+            println!("{coverage}");
+            for fault in coverage.undetected() {
+                let Fault::StuckAt { id, output, value } = fault else {
+                    continue;
+                };
+                let name = component_name(&engine, &ids, id);
+                println!("undetected: {name}.{output} stuck at {}", value as u8);
+            }
+        }
+        let report = testbench
+            .run(&mut engine, |signal| signals.get(signal).copied())
+            .map_err(|e| format!("{path}: {e}"))?;
+        println!("{report}");
+        all_passed &= report.passed();
+    }
+
+    Ok(all_passed)
+}
+
+fn print_probes(
+    prefix: &str,
+    probes: &[(String, (ComponentId, usize))],
+    engine: &SimulationEngine,
+) {
+    if probes.is_empty() {
+        return;
+    }
+    let values: Vec<String> = probes
+        .iter()
+        .map(|(name, (id, output))| format!("{name}={}", engine.logic_at(*id, *output)))
+        .collect();
+    println!("{prefix} {}", values.join(" "));
+}
+
+fn main() -> ExitCode {
+    let result = parse_args().and_then(|args| {
+        if args.help {
+            println!("{USAGE}");
+            return Ok(true);
+        }
+        run(args)
+    });
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(1),
+        Err(message) => {
+            eprintln!("{message}");
+            ExitCode::from(2)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use simulation_engine::ComponentKind::*;
+
+    use super::*;
+
+    fn write_temp(name: &str, text: &str) -> String {
+        let path: PathBuf =
+            env::temp_dir().join(format!("firestone_cli_{}_{name}", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path.to_str().unwrap().to_owned()
+    }
+
+    /// A delay feeding a `not`, saved after `ticks` ticks.
+    fn circuit(name: &str, ticks: u64) -> String {
+        let mut engine = SimulationEngine::default();
+        let [delay, not] = engine.add_array_wired([Delay, Not]);
+        engine.set_label(delay, Some("in")).unwrap();
+        engine.set_label(not, Some("out")).unwrap();
+        engine.run_steps(ticks);
+        write_temp(name, &engine.save())
+    }
+
+    #[test]
+    fn test_stimulus_ticks_are_relative() {
+        let stimulus = write_temp(
+            "relative.stim",
+            "1 expect out 1\n2 drive in 1\n3 expect out 0\n",
+        );
+        for (name, ticks) in [("fresh.circuit", 0), ("advanced.circuit", 10)] {
+            let args = Args {
+                circuit: circuit(name, ticks),
+                stimulus: Some(stimulus.clone()),
+                ..Args::default()
+            };
+            assert_eq!(run(args), Ok(true), "{name}");
+        }
+
+        let failing = write_temp("failing.stim", "2 expect out 0\n");
+        let args = Args {
+            circuit: circuit("failing.circuit", 10),
+            stimulus: Some(failing),
+            ..Args::default()
+        };
+        assert_eq!(run(args), Ok(false));
+    }
+
+    #[test]
+    fn test_until_stable_and_vcd() {
+        let vcd = env::temp_dir().join(format!("firestone_cli_{}_out.vcd", std::process::id()));
+        let args = Args {
+            circuit: circuit("stable.circuit", 5),
+            until_stable: true,
+            probes: vec![("out".to_owned(), "out".to_owned())],
+            stimulus: Some(write_temp("stable.stim", "3 drive in 1\n")),
+            vcd: Some(vcd.to_str().unwrap().to_owned()),
+            ..Args::default()
+        };
+        assert_eq!(run(args), Ok(true));
+
+        // sampled from the saved tick, the `not` falls at stimulus tick 3
+        let text = fs::read_to_string(vcd).unwrap();
+        let changes = text.split_once("$enddefinitions $end\n").unwrap().1;
+        assert_eq!(changes, "#5\n1!\n#6\n#7\n#8\n0!\n#9\n");
+    }
+
+    #[test]
+    fn test_unknown_output() {
+        let mut engine = SimulationEngine::default();
+        let adder = engine.add(HalfAdder);
+        engine.set_label(adder, Some("adder")).unwrap();
+        let ids = [adder];
+
+        let resolve = |signal| resolve_signal(signal, &[], &engine, &ids);
+        assert_eq!(resolve("0.1"), Ok((adder, 1)));
+        assert_eq!(resolve("adder.1"), Ok((adder, 1)));
+        assert_eq!(resolve("0.7"), Err("unknown signal `0.7`".to_owned()));
+        assert_eq!(
+            resolve("adder.2"),
+            Err("unknown signal `adder.2`".to_owned())
+        );
+    }
+}
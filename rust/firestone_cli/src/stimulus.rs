@@ -0,0 +1,112 @@
+use std::collections::BTreeMap;
+
+/// A signal as written in the command line and stimulus files, either a probe name or
+/// `<component>[.<output>]`, where `<component>` is the component's position in the circuit file.
+pub type SignalName = String;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Drive(SignalName, bool),
+    Release(SignalName),
+    Expect(SignalName, bool),
+}
+
+/// Commands of a stimulus file, grouped by tick.
+///
+/// Each line has the form `<tick> drive <signal> <0|1>`, `<tick> release <signal>` or
+/// `<tick> expect <signal> <0|1>`. Drives and releases take effect in the given tick, while
+/// expectations are checked after it runs. Ticks start at 1, the first tick run.
+#[derive(Debug, Default)]
+pub struct Stimulus {
+    pub commands: BTreeMap<u64, Vec<Command>>,
+}
+
+impl Stimulus {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut stimulus = Stimulus::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let err = |message: &str| format!("stimulus line {}: {message}", index + 1);
+
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let tick: u64 = words[0]
+                .parse()
+                .ok()
+                .filter(|&tick| tick > 0)
+                .ok_or_else(|| err("expected a tick number from 1"))?;
+            let bit = |word: &str| match word {
+                "0" => Ok(false),
+                "1" => Ok(true),
+                _ => Err(err("expected `0` or `1`")),
+            };
+
+            let command = match words[1..] {
+                ["drive", signal, value] => Command::Drive(signal.to_owned(), bit(value)?),
+                ["release", signal] => Command::Release(signal.to_owned()),
+                ["expect", signal, value] => Command::Expect(signal.to_owned(), bit(value)?),
+                _ => return Err(err("unknown command")),
+            };
+            stimulus.commands.entry(tick).or_default().push(command);
+        }
+
+        Ok(stimulus)
+    }
+
+    pub fn last_tick(&self) -> Option<u64> {
+        self.commands.keys().next_back().copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let stimulus = Stimulus::parse(
+            "
+            # set the carry in, then check it
+            1 drive 3 1
+            1 drive a.1 0 # trailing comment
+            4 expect sum 1
+            5 release 3
+            ",
+        )
+        .unwrap();
+
+        assert_eq!(stimulus.last_tick(), Some(5));
+        assert_eq!(
+            stimulus.commands[&1],
+            [
+                Command::Drive("3".into(), true),
+                Command::Drive("a.1".into(), false)
+            ]
+        );
+        assert_eq!(stimulus.commands[&4], [Command::Expect("sum".into(), true)]);
+        assert_eq!(stimulus.commands[&5], [Command::Release("3".into())]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Stimulus::parse("1 drive a 1\nx drive a 1").unwrap_err(),
+            "stimulus line 2: expected a tick number from 1"
+        );
+        assert_eq!(
+            Stimulus::parse("0 drive a 1").unwrap_err(),
+            "stimulus line 1: expected a tick number from 1"
+        );
+        assert_eq!(
+            Stimulus::parse("1 expect a 2").unwrap_err(),
+            "stimulus line 1: expected `0` or `1`"
+        );
+        assert_eq!(
+            Stimulus::parse("1 toggle a").unwrap_err(),
+            "stimulus line 1: unknown command"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use std::io::{self, Write};
+
+/// Writes waveforms in the Value Change Dump format, one timestep per tick.
+pub struct VcdWriter<W: Write> {
+    out: W,
+    codes: Vec<String>,
+    last_values: Vec<Option<bool>>,
+}
+
+impl<W: Write> VcdWriter<W> {
+    pub fn new(mut out: W, names: &[&str]) -> io::Result<Self> {
+        let codes: Vec<String> = (0..names.len()).map(identifier_code).collect();
+
+        writeln!(out, "$timescale 1 ns $end")?;
+        writeln!(out, "$scope module circuit $end")?;
+        for (name, code) in names.iter().zip(&codes) {
+            writeln!(out, "$var wire 1 {code} {name} $end")?;
+        }
+        writeln!(out, "$upscope $end")?;
+        writeln!(out, "$enddefinitions $end")?;
+
+        Ok(Self {
+            out,
+            last_values: vec![None; codes.len()],
+            codes,
+        })
+    }
+
+    /// Writes the values that changed since the last sample.
+    pub fn sample(&mut self, tick: u64, values: &[bool]) -> io::Result<()> {
+        writeln!(self.out, "#{tick}")?;
+        for ((code, last_value), &value) in self.codes.iter().zip(&mut self.last_values).zip(values)
+        {
+            if *last_value != Some(value) {
+                writeln!(self.out, "{}{code}", value as u8)?;
+                *last_value = Some(value);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Encodes `index` in base 94 with the printable ASCII characters, as VCD expects.
+fn identifier_code(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            break code;
+        }
+        index -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample() {
+        let mut out = vec![];
+        let mut vcd = VcdWriter::new(&mut out, &["a", "b"]).unwrap();
+        vcd.sample(0, &[false, false]).unwrap();
+        vcd.sample(1, &[true, false]).unwrap();
+        vcd.sample(2, &[true, false]).unwrap();
+        vcd.finish().unwrap();
+
+        let text = String::from_utf8(out).unwrap();
+        let (header, changes) = text.split_once("$enddefinitions $end\n").unwrap();
+        assert!(header.contains("$var wire 1 ! a $end\n$var wire 1 \" b $end"));
+        // only changed values are written
+        assert_eq!(changes, "#0\n0!\n0\"\n#1\n1!\n#2\n");
+    }
+
+    #[test]
+    fn test_identifier_code() {
+        assert_eq!(identifier_code(0), "!");
+        assert_eq!(identifier_code(93), "~");
+        assert_eq!(identifier_code(94), "!!");
+        assert_eq!(identifier_code(95), "\"!");
+    }
+}
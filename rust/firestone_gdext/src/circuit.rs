@@ -1,6 +1,11 @@
+use std::time::{Duration, Instant};
+
 use godot::prelude::*;
-use rustc_hash::FxHashMap;
-use simulation_engine::{ComponentId, ComponentKind, SimulationEngine};
+use rustc_hash::{FxHashMap, FxHashSet};
+use simulation_engine::{
+    Batch, ComponentId, ComponentKind, History, IdChange, LoopMode, SimulationEngine, StepError,
+    Target, TimingMode, testbench::Testbench,
+};
 
 use crate::cable::Cable;
 
@@ -9,9 +14,26 @@ use crate::cable::Cable;
 struct CircuitSimulation {
     engine: SimulationEngine,
     blocks: FxHashMap<Vector3i, ComponentId>,
-    cables: FxHashMap<ComponentId, Vec<Gd<Cable>>>,
+    /// Cables by the block they start from, with the position they end at.
+    cables: FxHashMap<ComponentId, Vec<(Vector3i, Gd<Cable>)>>,
+    history: History,
+    /// Positions of the blocks removed by an edit that can still be undone.
+    removed_blocks: FxHashMap<ComponentId, Vector3i>,
     base: Base<Node>,
     elapsed: f32,
+    paused: bool,
+    /// Ignored when `as_fast_as_possible` is set. Rates of 0 or below stop the circuit.
+    #[var]
+    ticks_per_second: f32,
+    /// Run as many ticks as fit in `frame_budget_msec` every frame.
+    #[var]
+    as_fast_as_possible: bool,
+    #[var]
+    frame_budget_msec: f32,
+    /// Ticks that are late by more than this are dropped, so a slow frame doesn't make the
+    /// following ones slower as well. At least one tick runs.
+    #[var]
+    max_catch_up_ticks: u32,
 }
 
 #[godot_api]
@@ -21,29 +43,106 @@ impl INode for CircuitSimulation {
             engine: SimulationEngine::new(),
             blocks: FxHashMap::default(),
             cables: FxHashMap::default(),
+            history: History::new(),
+            removed_blocks: FxHashMap::default(),
             base,
             elapsed: 0.0,
+            paused: false,
+            ticks_per_second: 1.0,
+            as_fast_as_possible: false,
+            frame_budget_msec: 8.0,
+            max_catch_up_ticks: 10,
         }
     }
 
     fn process(&mut self, delta: f32) {
+        if self.paused {
+            return;
+        }
+
+        if self.as_fast_as_possible {
+            let start = Instant::now();
+            let budget = Duration::from_secs_f32(self.frame_budget_msec.max(0.0) / 1000.0);
+            while start.elapsed() < budget {
+                self.tick();
+            }
+            return;
+        }
+
+        // the fields are set from Godot, so they're clamped here
+        let tick_duration = 1.0 / self.ticks_per_second.max(f32::MIN_POSITIVE);
+        let max_catch_up_ticks = self.max_catch_up_ticks.max(1);
         self.elapsed += delta;
-        while self.elapsed > 1.0 {
-            godot_print!("Simulation tick {}", self.engine.current_tick());
-            self.elapsed -= 1.0;
-            self.engine.run_step();
-            for (id, component) in self.engine.components() {
-                for cable in self.cables.get_mut(&id).into_iter().flatten() {
-                    // TODO: remove `0`, consider multi-output gates
-                    cable.bind_mut().update_state(component.state.values()[0]);
-                }
+        let mut ticks = 0;
+        while self.elapsed >= tick_duration {
+            if ticks == max_catch_up_ticks {
+                self.elapsed = 0.0;
+                break;
             }
+            self.elapsed -= tick_duration;
+            self.tick();
+            ticks += 1;
         }
     }
 }
 
 #[godot_api]
 impl CircuitSimulation {
+    #[signal]
+    fn ticked(tick: i64);
+
+    /// A block was removed or added back by an undo or redo.
+    #[signal]
+    fn block_removed(pos: Vector3i);
+    #[signal]
+    fn block_restored(pos: Vector3i);
+
+    /// A cable was added or removed by an undo or redo. Removed cables are freed here, added
+    /// ones are left to the scene to create and register.
+    #[signal]
+    fn cable_connected(from: Vector3i, to: Vector3i);
+    #[signal]
+    fn cable_disconnected(from: Vector3i, to: Vector3i);
+
+    #[func]
+    fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    #[func]
+    fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    #[func]
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Runs a single tick, meant to be used while paused.
+    #[func]
+    fn step(&mut self) {
+        self.tick();
+    }
+
+    fn tick(&mut self) {
+        self.engine.run_step();
+        for &(id, output) in self.engine.last_changes() {
+            // TODO: consider multi-output gates
+            if output != 0 {
+                continue;
+            }
+            for (_, cable) in self.cables.get_mut(&id).into_iter().flatten() {
+                cable.bind_mut().update_state(self.engine.is_on(id));
+            }
+        }
+
+        let tick = self.engine.current_tick() as i64;
+        self.base_mut().emit_signal("ticked", &[tick.to_variant()]);
+    }
+
+    /// Wires two blocks, adding the missing ones as an `And` or a `Not`. Blocks that exist
+    /// keep their kind, whatever the flags say.
     #[func]
     fn connect_blocks(
         &mut self,
@@ -52,46 +151,441 @@ impl CircuitSimulation {
         is_from_and: bool,
         is_to_and: bool,
     ) -> bool {
-        let from_kind = if is_from_and {
+        self.history.begin_group();
+        let from_id = self.block_or_add(from, is_from_and);
+        let to_id = self.block_or_add(to, is_to_and);
+
+        let wired = self
+            .history
+            .wire(&mut self.engine, from_id, to_id, 0, 0)
+            .is_ok();
+        self.history.end_group().expect("the group was just begun");
+        wired
+    }
+
+    fn block_or_add(&mut self, pos: Vector3i, is_and: bool) -> ComponentId {
+        if let Some(&id) = self.blocks.get(&pos) {
+            return id;
+        }
+        let kind = if is_and {
             ComponentKind::And(2)
         } else {
             ComponentKind::Not
         };
+        let id = self.history.add(&mut self.engine, kind).unwrap();
+        self.blocks.insert(pos, id);
+        id
+    }
 
-        let to_kind = if is_to_and {
-            ComponentKind::And(2)
-        } else {
-            ComponentKind::Not
+    /// Removes the cable between two blocks, returns whether there was one.
+    #[func]
+    fn disconnect_blocks(&mut self, from: Vector3i, to: Vector3i) -> bool {
+        let (Some(&from_id), Some(&to_id)) = (self.blocks.get(&from), self.blocks.get(&to)) else {
+            return false;
         };
+        let removed = self
+            .history
+            .unwire(&mut self.engine, from_id, to_id, 0, 0)
+            .unwrap_or(false);
+        if removed {
+            self.free_cables(|parent, end| parent == from_id && end == to);
+        }
+        removed
+    }
 
-        let from_id = self
-            .blocks
-            .get(&from)
-            .copied()
-            .inspect(|id| assert!(self.engine.components().get(id).unwrap().kind == from_kind))
-            .unwrap_or_else(|| {
-                let id = self.engine.add(from_kind);
-                self.blocks.insert(from, id);
-                id
+    #[func]
+    fn remove_block(&mut self, pos: Vector3i) -> bool {
+        let Some(id) = self.blocks.remove(&pos) else {
+            return false;
+        };
+        self.history.remove(&mut self.engine, id).unwrap();
+        self.free_cables(|parent, end| parent == id || end == pos);
+        self.removed_blocks.insert(id, pos);
+        true
+    }
+
+    /// Makes the edits until `end_edit_group` a single undo step.
+    #[func]
+    fn begin_edit_group(&mut self) {
+        self.history.begin_group();
+    }
+
+    /// Returns false if there's no group to end.
+    #[func]
+    fn end_edit_group(&mut self) -> bool {
+        self.history.end_group().is_ok()
+    }
+
+    /// Reverts the last edit, returns whether there was one and it could be reverted. Edits
+    /// can't be reverted while a group is open.
+    #[func]
+    fn undo(&mut self) -> bool {
+        self.undo_or_redo(History::undo)
+    }
+
+    #[func]
+    fn redo(&mut self) -> bool {
+        self.undo_or_redo(History::redo)
+    }
+
+    /// Runs an undo or a redo, and tells the scene about the blocks and cables it changed.
+    fn undo_or_redo(
+        &mut self,
+        step: fn(&mut History, &mut SimulationEngine) -> Result<Option<Vec<IdChange>>, StepError>,
+    ) -> bool {
+        let blocks_before = self.blocks.clone();
+        let cables_before = self.block_cables();
+        let result = step(&mut self.history, &mut self.engine);
+        let stepped = self.finish_step(result);
+
+        let cables_after = self.block_cables();
+        for &(from, to) in cables_before.difference(&cables_after) {
+            let from_id = blocks_before[&from];
+            self.free_cables(|parent, end| parent == from_id && end == to);
+            self.base_mut()
+                .emit_signal("cable_disconnected", &[from.to_variant(), to.to_variant()]);
+        }
+        for &(from, to) in cables_after.difference(&cables_before) {
+            self.base_mut()
+                .emit_signal("cable_connected", &[from.to_variant(), to.to_variant()]);
+        }
+        stepped
+    }
+
+    fn finish_step(&mut self, result: Result<Option<Vec<IdChange>>, StepError>) -> bool {
+        match result {
+            Ok(Some(changes)) => {
+                self.apply_id_changes(changes);
+                true
+            }
+            Ok(None) => false,
+            Err(StepError::Failed { error, changes }) => {
+                godot_warn!("{}", error.describe(&self.engine));
+                // rolling back can add blocks back under new ids
+                self.apply_id_changes(changes);
+                false
+            }
+            Err(error) => {
+                godot_warn!("{error}");
+                false
+            }
+        }
+    }
+
+    fn apply_id_changes(&mut self, changes: Vec<IdChange>) {
+        for change in changes {
+            match change {
+                IdChange::Removed(id) => {
+                    let Some((&pos, _)) = self.blocks.iter().find(|(_, block)| **block == id)
+                    else {
+                        continue;
+                    };
+                    self.blocks.remove(&pos);
+                    self.removed_blocks.insert(id, pos);
+                    self.base_mut()
+                        .emit_signal("block_removed", &[pos.to_variant()]);
+                }
+                IdChange::Restored { old, new } => {
+                    let Some(pos) = self.removed_blocks.remove(&old) else {
+                        continue;
+                    };
+                    self.blocks.insert(pos, new);
+                    self.base_mut()
+                        .emit_signal("block_restored", &[pos.to_variant()]);
+                }
+            }
+        }
+    }
+
+    /// The wires between blocks, as the positions of their ends.
+    fn block_cables(&self) -> FxHashSet<(Vector3i, Vector3i)> {
+        let positions: FxHashMap<ComponentId, Vector3i> =
+            self.blocks.iter().map(|(&pos, &id)| (id, pos)).collect();
+        self.engine
+            .wires()
+            .filter_map(|wire| Some((*positions.get(&wire.parent)?, *positions.get(&wire.child)?)))
+            .collect()
+    }
+
+    /// Frees the registered cables for which `matches(from, to)` holds.
+    fn free_cables(&mut self, mut matches: impl FnMut(ComponentId, Vector3i) -> bool) {
+        for (&from, cables) in &mut self.cables {
+            cables.retain_mut(|(to, cable)| {
+                let matched = matches(from, *to);
+                if matched {
+                    cable.clone().upcast::<Node>().queue_free();
+                }
+                !matched
             });
-        let to_id = self
+        }
+        self.cables.retain(|_, cables| !cables.is_empty());
+    }
+
+    /// Places blocks and the cables between them, all of them or none if any fails.
+    ///
+    /// `blocks` maps positions to kinds like `and(2)`, `cables` lists positions in `from, to`
+    /// pairs, of new or existing blocks. Returns the errors one per line, empty on success.
+    #[func]
+    fn paste_blueprint(&mut self, blocks: Dictionary, cables: Array<Vector3i>) -> GString {
+        let mut batch = Batch::new();
+        let mut pending = FxHashMap::default();
+        for (pos, kind) in blocks.iter_shared() {
+            let (Ok(pos), Ok(kind)) = (pos.try_to::<Vector3i>(), kind.try_to::<GString>()) else {
+                return "blocks must map positions to kinds".into();
+            };
+            if self.blocks.contains_key(&pos) {
+                return format!("{pos} is already taken").into();
+            }
+            match kind.to_string().parse::<ComponentKind>() {
+                Ok(kind) if !kind.is_lut() && !kind.is_custom() => {
+                    pending.insert(pos, batch.add(kind));
+                }
+                _ => return format!("invalid kind at {pos}").into(),
+            }
+        }
+        let cables: Vec<Vector3i> = cables.iter_shared().collect();
+        for pair in cables.chunks(2) {
+            let [from, to] = pair else {
+                return "cables must come in pairs".into();
+            };
+            let target = |pos| match pending.get(pos) {
+                Some(&pending) => Some(Target::Pending(pending)),
+                None => self.blocks.get(pos).map(|&id| Target::Existing(id)),
+            };
+            let (Some(from), Some(to)) = (target(from), target(to)) else {
+                return format!("no block at {from} or {to}").into();
+            };
+            batch.wire(from, to, 0, 0);
+        }
+
+        match self.history.apply(&mut self.engine, &batch) {
+            Ok(ids) => {
+                for (pos, pending) in pending {
+                    self.blocks.insert(pos, ids[pending.index()]);
+                }
+                GString::new()
+            }
+            Err(error) => error.describe(&self.engine).into(),
+        }
+    }
+
+    /// Changes the kind of a block, like `and(3)` or `delay`, keeping the cables that still fit.
+    ///
+    /// Returns how many cables were dropped, or -1 if the kind is invalid or would close a loop
+    /// without delays.
+    #[func]
+    fn replace_block_kind(&mut self, pos: Vector3i, kind: GString) -> i64 {
+        let Some(&id) = self.blocks.get(&pos) else {
+            return -1;
+        };
+        let Ok(kind) = kind.to_string().parse::<ComponentKind>() else {
+            return -1;
+        };
+        if kind.is_lut() || kind.is_custom() {
+            return -1;
+        }
+        match self.history.replace_kind(&mut self.engine, id, kind) {
+            Ok(dropped) => dropped.len() as i64,
+            Err(_) => -1,
+        }
+    }
+
+    /// Runs a testbench on a copy of the circuit, its signals are block coordinates like
+    /// `3,0,-2`.
+    ///
+    /// Returns `passed`, `total`, `failed` and a `summary`, or just an `error`.
+    #[func]
+    fn run_testbench(&self, testbench: GString) -> Dictionary {
+        let mut result = Dictionary::new();
+
+        let testbench = match Testbench::parse(&testbench.to_string()) {
+            Ok(testbench) => testbench,
+            Err(error) => {
+                result.set("error", error.to_string());
+                return result;
+            }
+        };
+
+        let Some((mut copy, ids)) = self.engine.duplicate() else {
+            result.set("error", "circuits with custom components can't be copied");
+            return result;
+        };
+
+        let blocks = &self.blocks;
+        let signal = |name: &str| {
+            let coordinates: Vec<i32> = name
+                .split(',')
+                .map(|coordinate| coordinate.trim().parse().ok())
+                .collect::<Option<_>>()?;
+            let &[x, y, z] = coordinates.as_slice() else {
+                return None;
+            };
+            blocks.get(&Vector3i::new(x, y, z)).map(|id| (ids[id], 0))
+        };
+
+        match testbench.run(&mut copy, signal) {
+            Ok(report) => {
+                result.set("passed", report.passed());
+                result.set("total", report.total as i64);
+                result.set("failed", report.failed as i64);
+                result.set("summary", report.to_string());
+            }
+            Err(error) => result.set("error", error.to_string()),
+        }
+        result
+    }
+
+    /// Counts transitions of every block from now on, for heatmaps and dead logic checks.
+    #[func]
+    fn set_activity_tracking(&mut self, enabled: bool) {
+        self.engine.set_activity_tracking(enabled);
+    }
+
+    #[func]
+    fn reset_activity(&mut self) {
+        self.engine.reset_activity();
+    }
+
+    /// Returns `rising`, `falling` and `ticks_high` of a block, empty if not tracking.
+    #[func]
+    fn activity_at(&self, pos: Vector3i) -> Dictionary {
+        let mut result = Dictionary::new();
+        let Some(toggles) = self
             .blocks
-            .get(&to)
-            .copied()
-            .inspect(|id| assert!(self.engine.components().get(id).unwrap().kind == to_kind))
-            .unwrap_or_else(|| {
-                let id = self.engine.add(to_kind);
-                self.blocks.insert(to, id);
-                id
-            });
+            .get(&pos)
+            .and_then(|&id| self.engine.toggles(id, 0))
+        else {
+            return result;
+        };
+        result.set("rising", toggles.rising as i64);
+        result.set("falling", toggles.falling as i64);
+        result.set("ticks_high", toggles.ticks_high as i64);
+        result
+    }
+
+    /// Logs every change to the circuit, so the run can be reproduced from a bug report.
+    #[func]
+    fn start_recording(&mut self) {
+        self.engine.start_recording();
+    }
+
+    /// Returns the log started by `start_recording`, empty if there's none.
+    #[func]
+    fn stop_recording(&mut self) -> GString {
+        self.engine
+            .stop_recording()
+            .map(|recording| recording.to_string())
+            .unwrap_or_default()
+            .into()
+    }
+
+    /// Names a block, the label shows in its tooltip. An empty label removes it.
+    #[func]
+    fn set_block_label(&mut self, pos: Vector3i, label: GString) -> bool {
+        let Some(&id) = self.blocks.get(&pos) else {
+            return false;
+        };
+        let label = label.to_string();
+        let label = (!label.is_empty()).then_some(label.as_str());
+        self.history.set_label(&mut self.engine, id, label).is_ok()
+    }
 
-        self.engine.wire(from_id, to_id, 0, 0)
+    #[func]
+    fn set_block_metadata(&mut self, pos: Vector3i, key: GString, value: GString) -> bool {
+        let Some(&id) = self.blocks.get(&pos) else {
+            return false;
+        };
+        self.history
+            .set_metadata(&mut self.engine, id, &key.to_string(), &value.to_string())
+            .is_ok()
+    }
+
+    /// The label, kind, value and metadata of a block, one per line, empty if there's no block.
+    #[func]
+    fn block_tooltip(&self, pos: Vector3i) -> GString {
+        let Some(&id) = self.blocks.get(&pos) else {
+            return GString::new();
+        };
+        let component = self.engine.component(id).unwrap();
+
+        let mut lines = vec![];
+        if let Some(label) = component.label() {
+            lines.push(label.to_owned());
+        }
+        let values: Vec<String> = (0..component.kind.arity().1)
+            .map(|output| self.engine.logic_at(id, output).to_string())
+            .collect();
+        lines.push(format!("{} = {}", component.kind, values.join("")));
+        for (key, value) in component.metadata() {
+            lines.push(format!("{key}: {value}"));
+        }
+        lines.join("\n").into()
+    }
+
+    /// Allows cables closing loops without delays, like latches made of cross-coupled blocks.
+    ///
+    /// Returns false if loops can't be forbidden because the circuit has some.
+    #[func]
+    fn set_combinational_loops(&mut self, enabled: bool) -> bool {
+        if !enabled && self.engine.has_loops() {
+            return false;
+        }
+        self.engine.set_loop_mode(match enabled {
+            true => LoopMode::FixedPoint { max_passes: 64 },
+            false => LoopMode::Forbidden,
+        });
+        true
+    }
+
+    /// Makes every block but delays take `ticks` ticks to update, to show signals rippling
+    /// through. 0 makes them update within the tick.
+    #[func]
+    fn set_gate_delay(&mut self, ticks: i64) {
+        self.engine.set_timing_mode(match ticks {
+            ..=0 => TimingMode::ZeroDelay,
+            ticks => TimingMode::UnitDelay {
+                ticks: ticks.min(u32::MAX as i64) as u32,
+            },
+        });
+    }
+
+    /// Positions of the blocks in loops that didn't settle in the last tick.
+    #[func]
+    fn unstable_blocks(&self) -> Array<Vector3i> {
+        self.blocks
+            .iter()
+            .filter(|(_, id)| self.engine.unstable().contains(id))
+            .map(|(&pos, _)| pos)
+            .collect()
+    }
+
+    /// Positions of the blocks wired to an input of the block at `pos`.
+    #[func]
+    fn drivers_of(&self, pos: Vector3i, input: i64) -> Array<Vector3i> {
+        let Some(&id) = self.blocks.get(&pos) else {
+            return Array::new();
+        };
+        let Ok(drivers) = self.engine.drivers(id, input as usize) else {
+            return Array::new();
+        };
+        self.blocks
+            .iter()
+            .filter(|(_, block)| drivers.iter().any(|port| port.id == **block))
+            .map(|(&pos, _)| pos)
+            .collect()
     }
 
-    /// Registers a cable to have its color be updated every tick.
+    /// Registers a cable to have its color be updated every tick, and to be freed with its
+    /// wire.
     #[func]
-    fn register_cable(&mut self, pos: Vector3i, cable: Gd<Cable>) {
-        let component_id = self.blocks[&pos];
-        self.cables.entry(component_id).or_default().push(cable);
+    fn register_cable(&mut self, from: Vector3i, to: Vector3i, mut cable: Gd<Cable>) {
+        let component_id = self.blocks[&from];
+        cable
+            .bind_mut()
+            .update_state(self.engine.is_on(component_id));
+        self.cables
+            .entry(component_id)
+            .or_default()
+            .push((to, cable));
     }
 }
@@ -0,0 +1,219 @@
+use std::ops::Range;
+
+use crate::{ComponentId, SimulationEngine};
+
+/// Transitions of an output, and how long it was on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Toggles {
+    pub rising: u64,
+    pub falling: u64,
+    pub ticks_high: u64,
+}
+
+impl Toggles {
+    pub fn transitions(&self) -> u64 {
+        self.rising + self.falling
+    }
+}
+
+/// Counters kept while tracking is enabled, indexed like the engine signals.
+#[derive(Default)]
+pub(crate) struct Activity {
+    ticks: u64,
+    toggles: Vec<Toggles>,
+}
+
+impl Activity {
+    pub fn record_change(&mut self, signal: usize, was_on: bool, is_on: bool) {
+        self.grow(signal + 1);
+        match (was_on, is_on) {
+            (false, true) => self.toggles[signal].rising += 1,
+            (true, false) => self.toggles[signal].falling += 1,
+            _ => {}
+        }
+    }
+
+    /// Zeroes the counters of signals given to a new component.
+    pub fn clear(&mut self, signals: Range<usize>) {
+        for signal in signals {
+            if let Some(toggles) = self.toggles.get_mut(signal) {
+                *toggles = Toggles::default();
+            }
+        }
+    }
+
+    fn grow(&mut self, signals: usize) {
+        if self.toggles.len() < signals {
+            self.toggles.resize(signals, Toggles::default());
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActivityReport {
+    /// Ticks since tracking was enabled or reset.
+    pub ticks: u64,
+    /// Every component with the toggles of each of its outputs.
+    pub components: Vec<(ComponentId, Vec<Toggles>)>,
+}
+
+impl ActivityReport {
+    /// Components whose outputs never changed, dead logic.
+    pub fn never_toggled(&self) -> impl Iterator<Item = ComponentId> {
+        self.components
+            .iter()
+            .filter(|(_, outputs)| outputs.iter().all(|toggles| toggles.transitions() == 0))
+            .map(|&(id, _)| id)
+    }
+
+    /// The `count` components with the most transitions, most active first.
+    pub fn hot_spots(&self, count: usize) -> Vec<(ComponentId, u64)> {
+        let mut transitions: Vec<(ComponentId, u64)> = self
+            .components
+            .iter()
+            .map(|(id, outputs)| (*id, outputs.iter().map(Toggles::transitions).sum()))
+            .filter(|&(_, transitions)| transitions > 0)
+            .collect();
+        transitions.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        transitions.truncate(count);
+        transitions
+    }
+
+    /// Fraction of outputs that both rose and fell, from 0 to 1.
+    pub fn toggle_coverage(&self) -> f64 {
+        let outputs = self.components.iter().flat_map(|(_, outputs)| outputs);
+        let total = outputs.clone().count();
+        if total == 0 {
+            return 1.0;
+        }
+        let toggled = outputs
+            .filter(|toggles| toggles.rising > 0 && toggles.falling > 0)
+            .count();
+        toggled as f64 / total as f64
+    }
+}
+
+impl SimulationEngine {
+    /// Starts or stops counting output transitions on every tick, stopping discards the counts.
+    pub fn set_activity_tracking(&mut self, enabled: bool) {
+        self.activity = enabled.then(Activity::default);
+    }
+
+    pub fn is_tracking_activity(&self) -> bool {
+        self.activity.is_some()
+    }
+
+    /// Zeroes the counters, if tracking.
+    pub fn reset_activity(&mut self) {
+        if let Some(activity) = &mut self.activity {
+            *activity = Activity::default();
+        }
+    }
+
+    /// Counters of an output, `None` if not tracking or the output doesn't exist.
+    pub fn toggles(&self, id: ComponentId, output: usize) -> Option<Toggles> {
+        let activity = self.activity.as_ref()?;
+        let component = self.nodes.get(id)?;
+        if output >= component.kind.arity().1 {
+            return None;
+        }
+        let signal = component.first_signal + output;
+        Some(activity.toggles.get(signal).copied().unwrap_or_default())
+    }
+
+    /// `None` if not tracking.
+    pub fn activity_report(&self) -> Option<ActivityReport> {
+        let activity = self.activity.as_ref()?;
+        let components = self
+            .components()
+            .map(|(id, component)| {
+                let outputs = (0..component.kind.arity().1)
+                    .map(|output| {
+                        let signal = component.first_signal + output;
+                        activity.toggles.get(signal).copied().unwrap_or_default()
+                    })
+                    .collect();
+                (id, outputs)
+            })
+            .collect();
+        Some(ActivityReport {
+            ticks: activity.ticks,
+            components,
+        })
+    }
+
+    /// Counts the ticks spent high, the transitions are counted as outputs are updated.
+    pub(crate) fn record_activity_tick(&mut self) {
+        let Some(activity) = &mut self.activity else {
+            return;
+        };
+        activity.ticks += 1;
+        activity.grow(self.signals.len());
+        for signal in self.signals.ones() {
+            if !self.unknown[signal] {
+                activity.toggles[signal].ticks_high += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ComponentKind::*, LoopMode, SimulationEngine};
+
+    #[test]
+    fn test_activity() {
+        let mut sim = SimulationEngine::default();
+        let clock = sim.add(Clock {
+            period: 4,
+            duty: 1,
+            phase: 0,
+        });
+        let [not, and] = sim.add_array([Not, And(2)]);
+        sim.wire(clock, and, 0, 0).unwrap();
+        assert!(sim.activity_report().is_none());
+
+        sim.set_activity_tracking(true);
+        sim.run_steps(8);
+        let report = sim.activity_report().unwrap();
+        assert_eq!(report.ticks, 8);
+
+        let clock_toggles = sim.toggles(clock, 0).unwrap();
+        assert_eq!(clock_toggles.rising, 2);
+        assert_eq!(clock_toggles.falling, 1);
+        assert_eq!(clock_toggles.ticks_high, 2);
+        // `not` went on in the first tick and stayed on
+        let not_toggles = sim.toggles(not, 0).unwrap();
+        assert_eq!((not_toggles.rising, not_toggles.ticks_high), (1, 8));
+
+        assert_eq!(report.never_toggled().collect::<Vec<_>>(), [and]);
+        assert_eq!(report.hot_spots(1), [(clock, 3)]);
+        assert_eq!(report.toggle_coverage(), 1.0 / 3.0);
+
+        sim.reset_activity();
+        sim.run_step();
+        assert_eq!(sim.activity_report().unwrap().ticks, 1);
+        assert_eq!(sim.toggles(not, 0).unwrap().rising, 0);
+
+        sim.set_activity_tracking(false);
+        assert!(sim.toggles(not, 0).is_none());
+    }
+
+    #[test]
+    fn test_activity_of_settled_loops() {
+        let mut sim = SimulationEngine::default();
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 4 });
+        // flips on every pass, and back to where it was after the last one
+        let [not] = sim.add_array_wired_loop_of(Not);
+        let [delay, follower] = sim.add_array_wired([Delay, Not]);
+        sim.set_activity_tracking(true);
+        sim.run_step();
+        sim.set_value(delay, true);
+        sim.run_steps(2);
+
+        assert_eq!(sim.unstable(), [not]);
+        assert_eq!(sim.toggles(not, 0).unwrap().transitions(), 0);
+        let follower = sim.toggles(follower, 0).unwrap();
+        assert_eq!((follower.rising, follower.falling), (1, 1));
+    }
+}
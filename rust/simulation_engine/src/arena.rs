@@ -0,0 +1,124 @@
+use std::ops::{Index, IndexMut};
+
+use crate::ComponentId;
+
+/// Dense storage addressed by `ComponentId`s.
+///
+/// Slots of removed values are reused, bumping their generation so the ids that pointed to
+/// the removed value don't alias the new one.
+pub(crate) struct Arena<T> {
+    slots: Vec<Slot<T>>,
+    free: Vec<u32>,
+}
+
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+        }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn insert(&mut self, value: T) -> ComponentId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.value = Some(value);
+                ComponentId::new(index, slot.generation)
+            }
+            None => {
+                let index = u32::try_from(self.slots.len()).expect("too many components");
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                ComponentId::new(index, 0)
+            }
+        }
+    }
+
+    /// The ids the next `count` inserts will return, if nothing is removed in between.
+    pub fn next_ids(&self, count: usize) -> Vec<ComponentId> {
+        let reused = self
+            .free
+            .iter()
+            .rev()
+            .map(|&index| ComponentId::new(index, self.slots[index as usize].generation));
+        let fresh = (self.slots.len()..).map(|index| ComponentId::new(index as u32, 0));
+        reused.chain(fresh).take(count).collect()
+    }
+
+    pub fn remove(&mut self, id: ComponentId) -> Option<T> {
+        let slot = self.slots.get_mut(id.index() as usize)?;
+        if slot.generation != id.generation() {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        self.free.push(id.index());
+        Some(value)
+    }
+
+    pub fn get(&self, id: ComponentId) -> Option<&T> {
+        let slot = self.slots.get(id.index() as usize)?;
+        (slot.generation == id.generation())
+            .then_some(slot.value.as_ref())
+            .flatten()
+    }
+
+    pub fn get_mut(&mut self, id: ComponentId) -> Option<&mut T> {
+        let slot = self.slots.get_mut(id.index() as usize)?;
+        (slot.generation == id.generation())
+            .then_some(slot.value.as_mut())
+            .flatten()
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// The id of the value at a slot, if it's occupied.
+    pub fn id_at(&self, index: u32) -> Option<ComponentId> {
+        let slot = self.slots.get(index as usize)?;
+        slot.value
+            .as_ref()
+            .map(|_| ComponentId::new(index, slot.generation))
+    }
+
+    /// Number of slots, occupied or not.
+    pub fn slot_count(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ComponentId, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            let id = ComponentId::new(index as u32, slot.generation);
+            slot.value.as_ref().map(|value| (id, value))
+        })
+    }
+}
+
+impl<T> Index<ComponentId> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: ComponentId) -> &T {
+        self.get(id).expect("stale or unknown component id")
+    }
+}
+
+impl<T> IndexMut<ComponentId> for Arena<T> {
+    fn index_mut(&mut self, id: ComponentId) -> &mut T {
+        self.get_mut(id).expect("stale or unknown component id")
+    }
+}
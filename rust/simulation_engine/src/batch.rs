@@ -0,0 +1,325 @@
+//! Edits applied all at once or not at all, like pasting a blueprint.
+
+use std::{error::Error, fmt};
+
+use crate::{ComponentId, ComponentKind, EngineError, LoopMode, SimulationEngine};
+
+/// A component added by a [`Batch`], its id is at `pending.index()` in the ids
+/// [`SimulationEngine::apply`] returns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Pending(usize);
+
+impl Pending {
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+/// A component that exists already, or one added earlier in the same batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    Existing(ComponentId),
+    Pending(Pending),
+}
+
+impl From<ComponentId> for Target {
+    fn from(id: ComponentId) -> Self {
+        Target::Existing(id)
+    }
+}
+
+impl From<Pending> for Target {
+    fn from(pending: Pending) -> Self {
+        Target::Pending(pending)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    Add(ComponentKind),
+    Wire {
+        parent: Target,
+        child: Target,
+        parent_output: usize,
+        child_input: usize,
+    },
+    Label(Target, Option<String>),
+    Metadata {
+        target: Target,
+        key: String,
+        value: String,
+    },
+}
+
+/// Edits to validate and apply together with [`SimulationEngine::apply`].
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Batch {
+    edits: Vec<Edit>,
+    added: usize,
+}
+
+impl Batch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Custom components can't be added in a batch.
+    pub fn add(&mut self, kind: ComponentKind) -> Pending {
+        self.edits.push(Edit::Add(kind));
+        self.added += 1;
+        Pending(self.added - 1)
+    }
+
+    pub fn wire(
+        &mut self,
+        parent: impl Into<Target>,
+        child: impl Into<Target>,
+        parent_output: usize,
+        child_input: usize,
+    ) {
+        self.edits.push(Edit::Wire {
+            parent: parent.into(),
+            child: child.into(),
+            parent_output,
+            child_input,
+        });
+    }
+
+    pub fn set_label(&mut self, target: impl Into<Target>, label: Option<&str>) {
+        self.edits
+            .push(Edit::Label(target.into(), label.map(str::to_owned)));
+    }
+
+    pub fn set_metadata(&mut self, target: impl Into<Target>, key: &str, value: &str) {
+        self.edits.push(Edit::Metadata {
+            target: target.into(),
+            key: key.to_owned(),
+            value: value.to_owned(),
+        });
+    }
+
+    pub fn edits(&self) -> &[Edit] {
+        &self.edits
+    }
+
+    pub fn len(&self) -> usize {
+        self.edits.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.edits.is_empty()
+    }
+}
+
+/// Every edit of a batch that failed, by its index in the batch. Components the batch would
+/// have added are named by the id they would have had.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchError {
+    pub errors: Vec<(usize, EngineError)>,
+}
+
+impl BatchError {
+    /// Like `Display`, but names components by their label when they have one.
+    pub fn describe(&self, engine: &SimulationEngine) -> String {
+        self.errors
+            .iter()
+            .map(|(index, error)| format!("edit {index}: {}", error.describe(engine)))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (index, error)) in self.errors.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "edit {index}: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for BatchError {}
+
+impl SimulationEngine {
+    /// Applies every edit of a batch, or none of them if any fails, returning the ids of the
+    /// added components.
+    ///
+    /// The batch is validated as a whole first, wires are checked for cycles along with the
+    /// ones before them in the batch.
+    pub fn apply(&mut self, batch: &Batch) -> Result<Vec<ComponentId>, BatchError> {
+        let ids = self.nodes.next_ids(batch.added);
+        let mut kinds = Vec::with_capacity(batch.added);
+        for edit in &batch.edits {
+            if let &Edit::Add(kind) = edit {
+                kinds.push(kind);
+            }
+        }
+        let resolve = |target: Target| match target {
+            Target::Existing(id) => self.component(id).map(|component| (id, component.kind)),
+            Target::Pending(pending) => {
+                assert!(pending.0 < ids.len(), "pending component of another batch");
+                Ok((ids[pending.0], kinds[pending.0]))
+            }
+        };
+
+        let mut dag = self.tickless_dag.clone();
+        if let Some(last) = ids.iter().map(|id| id.index()).max() {
+            dag.grow(last as usize + 1);
+        }
+        let mut errors = vec![];
+        for (index, edit) in batch.edits.iter().enumerate() {
+            let result = match *edit {
+                Edit::Add(kind) => self.check_kind(kind),
+                Edit::Wire {
+                    parent,
+                    child,
+                    parent_output,
+                    child_input,
+                } => resolve(parent).and_then(|(parent, parent_kind)| {
+                    let (child, child_kind) = resolve(child)?;
+                    if parent_output >= parent_kind.arity().1 {
+                        return Err(EngineError::OutputOutOfBounds {
+                            id: parent,
+                            output: parent_output,
+                        });
+                    }
+                    if child_input >= child_kind.arity().0 {
+                        return Err(EngineError::InputOutOfBounds {
+                            id: child,
+                            input: child_input,
+                        });
+                    }
+                    if !parent_kind.is_delay()
+                        && !child_kind.is_delay()
+                        && self.loop_mode == LoopMode::Forbidden
+                        && !dag.try_add_edge(parent.index(), child.index())
+                    {
+                        return Err(EngineError::CreatesCycle { parent, child });
+                    }
+                    Ok(())
+                }),
+                Edit::Label(target, _) | Edit::Metadata { target, .. } => {
+                    resolve(target).map(|_| ())
+                }
+            };
+            if let Err(error) = result {
+                errors.push((index, error));
+            }
+        }
+        if !errors.is_empty() {
+            return Err(BatchError { errors });
+        }
+
+        let mut added = Vec::with_capacity(batch.added);
+        let target = |added: &[ComponentId], target: Target| match target {
+            Target::Existing(id) => id,
+            Target::Pending(pending) => added[pending.0],
+        };
+        for edit in &batch.edits {
+            match edit {
+                &Edit::Add(kind) => added.push(self.add(kind)),
+                &Edit::Wire {
+                    parent,
+                    child,
+                    parent_output,
+                    child_input,
+                } => self
+                    .wire(
+                        target(&added, parent),
+                        target(&added, child),
+                        parent_output,
+                        child_input,
+                    )
+                    .expect("wires of a batch are validated"),
+                Edit::Label(label_target, label) => self
+                    .set_label(target(&added, *label_target), label.as_deref())
+                    .unwrap(),
+                Edit::Metadata {
+                    target: metadata_target,
+                    key,
+                    value,
+                } => self
+                    .set_metadata(target(&added, *metadata_target), key, value)
+                    .unwrap(),
+            }
+        }
+        debug_assert_eq!(added, ids);
+        Ok(added)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComponentKind::*;
+
+    #[test]
+    fn test_batch() {
+        let mut sim = SimulationEngine::default();
+        let [first, last] = sim.add_array_wired_of(Not);
+        let removed = sim.add(Not);
+        sim.remove(removed).unwrap();
+
+        let mut batch = Batch::new();
+        let and = batch.add(And(2));
+        let delay = batch.add(Delay);
+        batch.wire(last, and, 0, 0);
+        batch.wire(and, delay, 0, 0);
+        batch.wire(delay, first, 0, 0);
+        batch.set_label(and, Some("gate"));
+        // closes a cycle with the wire from `last` to `and` above
+        batch.wire(and, first, 0, 0);
+        batch.wire(removed, and, 0, 1);
+        batch.wire(and, last, 1, 0);
+        batch.set_metadata(removed, "key", "value");
+
+        let error = sim.apply(&batch).unwrap_err();
+        let [and_id, _] = sim.nodes.next_ids(2)[..] else {
+            unreachable!()
+        };
+        assert_eq!(
+            error.errors,
+            [
+                (
+                    6,
+                    EngineError::CreatesCycle {
+                        parent: and_id,
+                        child: first
+                    }
+                ),
+                (7, EngineError::StaleComponent(removed)),
+                (
+                    8,
+                    EngineError::OutputOutOfBounds {
+                        id: and_id,
+                        output: 1
+                    }
+                ),
+                (9, EngineError::StaleComponent(removed)),
+            ]
+        );
+        assert_eq!(
+            error.to_string().lines().next().unwrap(),
+            format!("edit 6: wiring {and_id:?} to {first:?} creates a cycle without delays")
+        );
+        // nothing was applied
+        assert_eq!(sim.components().count(), 2);
+        assert_eq!(sim.incoming_wires(first).count(), 0);
+
+        let mut batch = Batch::new();
+        let and = batch.add(And(2));
+        let delay = batch.add(Delay);
+        batch.wire(last, and, 0, 0);
+        batch.wire(and, delay, 0, 0);
+        batch.wire(delay, first, 0, 0);
+        batch.set_label(and, Some("gate"));
+        let ids = sim.apply(&batch).unwrap();
+        assert_eq!(ids[and.index()], and_id);
+        assert_eq!(sim.find_by_label("gate"), Some(and_id));
+        assert_eq!(sim.loads(ids[delay.index()], 0).unwrap()[0].id, first);
+        assert_eq!(sim.wires().count(), 4);
+    }
+}
@@ -0,0 +1,36 @@
+use crate::{ComponentId, Logic, SimulationEngine};
+
+/// Condition checked after every tick by [`SimulationEngine::run_until_breakpoint`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Breakpoint {
+    /// The output of a component turned on in this tick.
+    BecomesOn { id: ComponentId, output: usize },
+    /// The output of a component turned off in this tick.
+    BecomesOff { id: ComponentId, output: usize },
+    /// The outputs, least significant bit first, read as a number are equal to `value`. Never
+    /// hit by buses wider than 64 bits.
+    BusEquals {
+        bits: Vec<(ComponentId, usize)>,
+        value: u64,
+    },
+    /// The simulation reached this tick.
+    Tick(u64),
+}
+
+impl Breakpoint {
+    /// Never hit on outputs that don't exist, like those of removed components.
+    pub fn is_hit(&self, engine: &SimulationEngine) -> bool {
+        let changed_to = |id, output, on: bool| {
+            engine.last_changes().contains(&(id, output))
+                && engine
+                    .try_logic_at(id, output)
+                    .is_ok_and(|logic| (logic == Logic::One) == on)
+        };
+        match *self {
+            Breakpoint::BecomesOn { id, output } => changed_to(id, output, true),
+            Breakpoint::BecomesOff { id, output } => changed_to(id, output, false),
+            Breakpoint::BusEquals { ref bits, value } => engine.bus_value(bits) == Ok(value),
+            Breakpoint::Tick(tick) => engine.current_tick() >= tick,
+        }
+    }
+}
@@ -1,22 +1,46 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+    fmt,
+    str::FromStr,
+};
+
 use strum::EnumIs;
 
-use crate::State;
+use crate::{CustomId, Edge, TableId};
 
 pub struct Component {
-    pub state: State,
     pub kind: ComponentKind,
+    /// Where the outputs start in the engine signals.
+    pub(crate) first_signal: usize,
+    /// Tick in which a `Pulse` saw its input go high, `None` while the input is low.
+    pub(crate) rising_edge_tick: Option<u64>,
+    pub(crate) incoming: BTreeMap<ComponentId, BTreeSet<Edge>>,
+    pub(crate) outgoing: BTreeMap<ComponentId, BTreeSet<Edge>>,
+    pub(crate) label: Option<String>,
+    pub(crate) metadata: BTreeMap<String, String>,
 }
 
 impl Component {
-    pub fn new(kind: ComponentKind) -> Self {
+    pub(crate) fn new(kind: ComponentKind, first_signal: usize) -> Self {
         Self {
             kind,
-            state: State {
-                values: vec![false; kind.arity().1],
-                version: 0,
-            },
+            first_signal,
+            rising_edge_tick: None,
+            incoming: BTreeMap::new(),
+            outgoing: BTreeMap::new(),
+            label: None,
+            metadata: BTreeMap::new(),
         }
     }
+
+    pub fn label(&self) -> Option<&str> {
+        self.label.as_deref()
+    }
+
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, EnumIs)]
@@ -26,6 +50,30 @@ pub enum ComponentKind {
     HalfAdder,
     FullAdder,
     Delay,
+    /// Outputs on for `duty` ticks out of every `period`, shifted by `phase` ticks.
+    Clock {
+        period: u64,
+        duty: u64,
+        phase: u64,
+    },
+    /// Outputs on for a single tick when its input goes from off to on.
+    Pulse,
+    /// Outputs its data input (`in0`) while enabled (`in1`), `Z` otherwise.
+    TriState,
+    /// Resolves several tri-state drivers, flags a contention if enabled ones disagree.
+    Bus(usize),
+    /// Looks its outputs up in a truth table stored in the engine.
+    Lut {
+        inputs: usize,
+        outputs: usize,
+        table: TableId,
+    },
+    /// Evaluated by a [`crate::CustomComponent`] added with `add_custom`.
+    Custom {
+        id: CustomId,
+        inputs: usize,
+        outputs: usize,
+    },
 }
 
 impl ComponentKind {
@@ -36,34 +84,156 @@ impl ComponentKind {
             ComponentKind::HalfAdder => (2, 2),
             ComponentKind::FullAdder => (3, 2),
             ComponentKind::Delay => (1, 1),
+            ComponentKind::Clock { .. } => (0, 1),
+            ComponentKind::Pulse => (1, 1),
+            ComponentKind::TriState => (2, 1),
+            ComponentKind::Bus(drivers) => (drivers, 1),
+            ComponentKind::Lut {
+                inputs, outputs, ..
+            }
+            | ComponentKind::Custom {
+                inputs, outputs, ..
+            } => (inputs, outputs),
         }
     }
 }
 
-#[derive(Default)]
-pub struct ComponentIdGenerator(pub usize);
+impl fmt::Display for ComponentKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ComponentKind::Not => write!(f, "not"),
+            ComponentKind::And(inputs) => write!(f, "and({inputs})"),
+            ComponentKind::HalfAdder => write!(f, "half_adder"),
+            ComponentKind::FullAdder => write!(f, "full_adder"),
+            ComponentKind::Delay => write!(f, "delay"),
+            ComponentKind::Clock {
+                period,
+                duty,
+                phase,
+            } => write!(f, "clock({period}, {duty}, {phase})"),
+            ComponentKind::Pulse => write!(f, "pulse"),
+            ComponentKind::TriState => write!(f, "tri_state"),
+            ComponentKind::Bus(drivers) => write!(f, "bus({drivers})"),
+            ComponentKind::Lut {
+                inputs,
+                outputs,
+                table,
+            } => write!(f, "lut({inputs}, {outputs}, {table})"),
+            ComponentKind::Custom {
+                id,
+                inputs,
+                outputs,
+            } => write!(f, "custom({id}, {inputs}, {outputs})"),
+        }
+    }
+}
+
+/// Parses the format used by `Display`, like `and(3)` or `clock(4, 1, 0)`.
+/// Inputs, outputs and drivers above this are rejected when parsing a kind, they're all
+/// allocated upfront.
+const MAX_ARITY: u64 = 1 << 16;
+
+impl FromStr for ComponentKind {
+    type Err = ParseKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseKindError(s.to_owned());
+        let size = |n: u64| match n {
+            0..=MAX_ARITY => Ok(n as usize),
+            _ => Err(err()),
+        };
+
+        let (name, args) = match s.trim().split_once('(') {
+            Some((name, args)) => {
+                let args = args.trim_end().strip_suffix(')').ok_or_else(err)?;
+                let args = args
+                    .split(',')
+                    .map(|arg| arg.trim().parse::<u64>().map_err(|_| err()))
+                    .collect::<Result<Vec<u64>, _>>()?;
+                (name.trim_end(), args)
+            }
+            None => (s.trim(), vec![]),
+        };
+
+        let kind = match (name, args.as_slice()) {
+            ("not", []) => ComponentKind::Not,
+            ("and", &[inputs]) => ComponentKind::And(size(inputs)?),
+            ("half_adder", []) => ComponentKind::HalfAdder,
+            ("full_adder", []) => ComponentKind::FullAdder,
+            ("delay", []) => ComponentKind::Delay,
+            ("clock", &[period, duty, phase]) if period != 0 => ComponentKind::Clock {
+                period,
+                duty,
+                phase: phase % period,
+            },
+            ("pulse", []) => ComponentKind::Pulse,
+            ("tri_state", []) => ComponentKind::TriState,
+            ("bus", &[drivers]) => ComponentKind::Bus(size(drivers)?),
+            ("lut", &[inputs, outputs, table]) => ComponentKind::Lut {
+                inputs: size(inputs)?,
+                outputs: size(outputs)?,
+                table: TableId::new(table as usize),
+            },
+            ("custom", &[id, inputs, outputs]) => ComponentKind::Custom {
+                id: CustomId::new(id as usize),
+                inputs: size(inputs)?,
+                outputs: size(outputs)?,
+            },
+            _ => return Err(err()),
+        };
+        Ok(kind)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseKindError(String);
 
-impl ComponentIdGenerator {
-    pub fn next_id(&mut self) -> ComponentId {
-        let id = ComponentId(self.0);
-        self.0 += 1;
-        id
+impl fmt::Display for ParseKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid component kind `{}`", self.0)
     }
 }
 
-#[derive(Default, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
-pub struct ComponentId(usize);
+impl Error for ParseKindError {}
+
+/// A wire from an output of `parent` to an input of `child`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Wire {
+    pub parent: ComponentId,
+    pub child: ComponentId,
+    pub parent_output: usize,
+    pub child_input: usize,
+}
+
+impl Wire {
+    pub(crate) fn new(parent: ComponentId, child: ComponentId, edge: Edge) -> Self {
+        Self {
+            parent,
+            child,
+            parent_output: edge.parent_output,
+            child_input: edge.child_input,
+        }
+    }
+}
+
+/// Identifies a component, stays invalid after the component is removed, even if its slot is
+/// reused by a new one.
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct ComponentId {
+    index: u32,
+    generation: u32,
+}
 
-unsafe impl petgraph::graph::IndexType for ComponentId {
-    fn new(inner: usize) -> Self {
-        ComponentId(inner)
+impl ComponentId {
+    pub(crate) fn new(index: u32, generation: u32) -> Self {
+        Self { index, generation }
     }
 
-    fn index(&self) -> usize {
-        self.0
+    pub(crate) fn index(&self) -> u32 {
+        self.index
     }
 
-    fn max() -> Self {
-        Self(usize::MAX)
+    pub(crate) fn generation(&self) -> u32 {
+        self.generation
     }
 }
@@ -0,0 +1,214 @@
+//! Components defined outside of the engine, like displays, keyboards or random sources.
+
+use std::{any::Any, fmt};
+
+use crate::{ComponentId, ComponentKind, Logic, SimulationEngine};
+
+/// Behavior of a custom component, each instance is owned by the component it was added as.
+///
+/// Circuits with custom components can be saved, but not loaded back, copied or replayed.
+pub trait CustomComponent: Any {
+    /// `(inputs, outputs)`, read once when the component is added.
+    fn arity(&self) -> (usize, usize);
+
+    /// Computes the outputs from the inputs, in the tick the inputs change, like gates do.
+    ///
+    /// Values past the declared outputs are dropped, and missing ones are off.
+    fn evaluate(&self, inputs: &[bool]) -> Vec<bool>;
+
+    /// Called at the end of every tick with the inputs the circuit settled on, to update any
+    /// state `evaluate` depends on.
+    ///
+    /// In [`crate::LogicMode::FourValued`], `X` and `Z` inputs read as off here, while
+    /// `evaluate` isn't called and the outputs are `X`.
+    fn update(&mut self, inputs: &[bool], tick: u64) {
+        let _ = (inputs, tick);
+    }
+}
+
+/// Identifies the instance behind a `ComponentKind::Custom`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CustomId(u32);
+
+impl CustomId {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for CustomId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A custom component instance and the component it belongs to.
+pub(crate) struct Custom {
+    id: ComponentId,
+    device: Box<dyn CustomComponent>,
+}
+
+impl SimulationEngine {
+    pub fn add_custom(&mut self, device: impl CustomComponent) -> ComponentId {
+        let (inputs, outputs) = device.arity();
+        let custom_id = CustomId::new(self.customs.len());
+        self.customs.push(None);
+        let id = self.insert(ComponentKind::Custom {
+            id: custom_id,
+            inputs,
+            outputs,
+        });
+        self.customs[custom_id.index()] = Some(Custom {
+            id,
+            device: Box::new(device),
+        });
+        id
+    }
+
+    /// The instance behind a custom component, if it's a `T`.
+    pub fn custom<T: CustomComponent>(&self, id: ComponentId) -> Option<&T> {
+        let ComponentKind::Custom { id: custom_id, .. } = self.nodes.get(id)?.kind else {
+            return None;
+        };
+        let device: &dyn Any = &*self.customs[custom_id.index()].as_ref()?.device;
+        device.downcast_ref()
+    }
+
+    pub fn custom_mut<T: CustomComponent>(&mut self, id: ComponentId) -> Option<&mut T> {
+        let ComponentKind::Custom { id: custom_id, .. } = self.nodes.get(id)?.kind else {
+            return None;
+        };
+        let device: &mut dyn Any = &mut *self.customs[custom_id.index()].as_mut()?.device;
+        device.downcast_mut()
+    }
+
+    pub(crate) fn eval_custom(&self, custom_id: CustomId, inputs: &[bool]) -> Vec<bool> {
+        let custom = self.customs[custom_id.index()]
+            .as_ref()
+            .expect("custom components keep their instance");
+        let mut outputs = custom.device.evaluate(inputs);
+        outputs.resize(self.nodes[custom.id].kind.arity().1, false);
+        outputs
+    }
+
+    /// Calls `update` on every custom component with its settled inputs.
+    pub(crate) fn update_customs(&mut self) {
+        for index in 0..self.customs.len() {
+            let Some(custom) = &self.customs[index] else {
+                continue;
+            };
+            let id = custom.id;
+            let mut inputs = vec![false; self.nodes[id].kind.arity().0];
+            for (parent, edge) in self.incoming_to(id) {
+                inputs[edge.child_input] |= self.logic_at(parent, edge.parent_output) == Logic::One;
+            }
+            let tick = self.current_tick;
+            let custom = self.customs[index].as_mut().unwrap();
+            custom.device.update(&inputs, tick);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComponentKind::*;
+
+    /// Outputs on for every other tick its input was on.
+    struct Toggle {
+        on: bool,
+    }
+
+    impl CustomComponent for Toggle {
+        fn arity(&self) -> (usize, usize) {
+            (1, 1)
+        }
+
+        fn evaluate(&self, _: &[bool]) -> Vec<bool> {
+            vec![self.on]
+        }
+
+        fn update(&mut self, inputs: &[bool], _: u64) {
+            self.on ^= inputs[0];
+        }
+    }
+
+    struct Xor;
+
+    impl CustomComponent for Xor {
+        fn arity(&self) -> (usize, usize) {
+            (2, 1)
+        }
+
+        fn evaluate(&self, inputs: &[bool]) -> Vec<bool> {
+            vec![inputs[0] != inputs[1]]
+        }
+    }
+
+    /// Outputs the same values whatever the inputs, however many it declares.
+    struct Fixed(Vec<bool>);
+
+    impl CustomComponent for Fixed {
+        fn arity(&self) -> (usize, usize) {
+            (1, 2)
+        }
+
+        fn evaluate(&self, _: &[bool]) -> Vec<bool> {
+            self.0.clone()
+        }
+    }
+
+    #[test]
+    fn test_outputs_fit_the_arity() {
+        let mut sim = SimulationEngine::default();
+        let input = sim.add(Delay);
+        let short = sim.add_custom(Fixed(vec![true]));
+        let long = sim.add_custom(Fixed(vec![true; 3]));
+        sim.wire(input, short, 0, 0).unwrap();
+        sim.wire(input, long, 0, 0).unwrap();
+
+        sim.set_value(input, true);
+        sim.run_step();
+        assert!(sim.is_on_at(short, 0));
+        assert!(sim.is_off_at(short, 1));
+        assert!(sim.is_on_at(long, 1));
+    }
+
+    #[test]
+    fn test_custom_component() {
+        let mut sim = SimulationEngine::default();
+        let [a, b] = sim.add_array_of(Delay);
+        let xor = sim.add_custom(Xor);
+        let toggle = sim.add_custom(Toggle { on: false });
+        sim.wire(a, xor, 0, 0).unwrap();
+        sim.wire(b, xor, 0, 1).unwrap();
+        sim.wire(xor, toggle, 0, 0).unwrap();
+        assert_eq!(sim.component(xor).unwrap().kind.arity(), (2, 1));
+
+        sim.set_value(a, true);
+        sim.run_step();
+        assert!(sim.is_on(xor));
+        assert!(sim.is_off(toggle));
+        sim.run_step();
+        assert!(sim.is_on(toggle));
+        sim.run_step();
+        assert!(sim.is_off(toggle));
+        // turns back on in the next tick
+        assert!(sim.custom::<Toggle>(toggle).unwrap().on);
+
+        sim.custom_mut::<Toggle>(toggle).unwrap().on = false;
+        sim.set_value(b, true);
+        sim.run_step();
+        assert!(sim.is_off(xor));
+        assert!(sim.is_off(toggle));
+        assert!(sim.custom::<Xor>(toggle).is_none());
+
+        sim.remove(toggle).unwrap();
+        assert!(sim.custom::<Toggle>(toggle).is_none());
+        sim.run_step();
+    }
+}
@@ -0,0 +1,59 @@
+//! The wires between components that aren't delays, kept free of cycles.
+
+use petgraph::{
+    Direction::{Incoming, Outgoing},
+    acyclic::Acyclic,
+    data::Build,
+    graph::{DiGraph, NodeIndex},
+};
+
+/// Nodes are the arena slot indexes, every slot is one, even of delays and removed components.
+#[derive(Default, Clone)]
+pub(crate) struct Dag(Acyclic<DiGraph<(), (), u32>>);
+
+fn node(slot: u32) -> NodeIndex {
+    NodeIndex::new(slot as usize)
+}
+
+impl Dag {
+    pub fn slot_count(&self) -> usize {
+        self.0.node_count()
+    }
+
+    /// Adds nodes until there are `slots` of them.
+    pub fn grow(&mut self, slots: usize) {
+        while self.0.node_count() < slots {
+            self.0.add_node(());
+        }
+    }
+
+    /// Returns false, without adding it, if the edge would close a cycle.
+    pub fn try_add_edge(&mut self, parent: u32, child: u32) -> bool {
+        self.0
+            .try_update_edge(node(parent), node(child), ())
+            .is_ok()
+    }
+
+    pub fn remove_edge(&mut self, parent: u32, child: u32) {
+        if let Some(edge) = self.0.find_edge(node(parent), node(child)) {
+            self.0.remove_edge(edge);
+        }
+    }
+
+    pub fn children(&self, slot: u32) -> impl Iterator<Item = u32> {
+        self.0
+            .neighbors_directed(node(slot), Outgoing)
+            .map(|child| child.index() as u32)
+    }
+
+    /// Every slot, parents before their children.
+    pub fn topological_order(&self) -> impl Iterator<Item = u32> {
+        self.0.nodes_iter().map(|slot| slot.index() as u32)
+    }
+
+    pub fn parents(&self, slot: u32) -> impl Iterator<Item = u32> {
+        self.0
+            .neighbors_directed(node(slot), Incoming)
+            .map(|parent| parent.index() as u32)
+    }
+}
@@ -0,0 +1,252 @@
+//! Graphviz schematics of a circuit.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write,
+};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{ComponentId, Logic, SimulationEngine};
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DotOptions {
+    /// Colors components and wires by their current value.
+    pub values: bool,
+    pub clustering: Clustering,
+    /// Components drawn in red, along with the wires between consecutive ones. A missing wire is
+    /// drawn dashed, like the one closing a cycle from [`SimulationEngine::cycle_path`].
+    pub highlight: Vec<ComponentId>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Clustering {
+    #[default]
+    None,
+    /// Groups components connected by wires, delays included.
+    Island,
+    /// Groups components by their longest path of wires without delays from a component
+    /// without parents.
+    Level,
+}
+
+impl SimulationEngine {
+    /// Writes the circuit in the DOT language, with components labeled by their kind and label,
+    /// and wires by the output and input they connect.
+    pub fn to_dot(&self, options: &DotOptions) -> String {
+        let highlighted: FxHashSet<ComponentId> = options.highlight.iter().copied().collect();
+        let highlighted_wires: FxHashSet<(ComponentId, ComponentId)> = options
+            .highlight
+            .array_windows::<2>()
+            .map(|&[parent, child]| (parent, child))
+            .collect();
+
+        let mut out = String::new();
+        writeln!(out, "digraph circuit {{").unwrap();
+        writeln!(out, "    rankdir=LR;").unwrap();
+        writeln!(out, "    node [shape=box, style=filled, fillcolor=white];").unwrap();
+
+        let clusters: BTreeMap<usize, Vec<ComponentId>> = match options.clustering {
+            Clustering::None => BTreeMap::new(),
+            Clustering::Island => group(self.islands()),
+            Clustering::Level => group(self.levels()),
+        };
+        let cluster_name = match options.clustering {
+            Clustering::Island => "island",
+            _ => "level",
+        };
+        for (&cluster, ids) in &clusters {
+            writeln!(out, "    subgraph cluster_{cluster} {{").unwrap();
+            writeln!(out, "        label=\"{cluster_name} {cluster}\";").unwrap();
+            for &id in ids {
+                writeln!(out, "        n{};", id.index()).unwrap();
+            }
+            writeln!(out, "    }}").unwrap();
+        }
+
+        for (id, component) in self.components() {
+            let mut label = component.kind.to_string();
+            if let Some(name) = component.label() {
+                write!(label, "\n{name}").unwrap();
+            }
+            let mut attributes = vec![];
+            if options.values {
+                let values = self.state(id).unwrap().logic_values().to_vec();
+                let text: String = values.iter().map(Logic::to_string).collect();
+                write!(label, "\n= {text}").unwrap();
+                if let Some(&value) = values.first() {
+                    attributes.push(format!("fillcolor={}", color(value)));
+                }
+            }
+            if highlighted.contains(&id) {
+                attributes.push("color=red, penwidth=3".to_owned());
+            }
+            attributes.insert(0, format!("label=\"{}\"", escape(&label)));
+            writeln!(out, "    n{} [{}];", id.index(), attributes.join(", ")).unwrap();
+        }
+
+        for (parent, component) in self.components() {
+            for (&child, edges) in &component.outgoing {
+                for edge in edges {
+                    let mut attributes = vec![format!(
+                        "label=\"out{} -> in{}\"",
+                        edge.parent_output, edge.child_input
+                    )];
+                    if options.values {
+                        let value = self.logic_at(parent, edge.parent_output);
+                        attributes.push(format!("color={}", color(value)));
+                    }
+                    if highlighted_wires.contains(&(parent, child)) {
+                        attributes.push("color=red, penwidth=3".to_owned());
+                    }
+                    writeln!(
+                        out,
+                        "    n{} -> n{} [{}];",
+                        parent.index(),
+                        child.index(),
+                        attributes.join(", ")
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        for &[parent, child] in options.highlight.array_windows::<2>() {
+            let wired = self
+                .component(parent)
+                .is_ok_and(|component| component.outgoing.contains_key(&child));
+            if !wired && self.contains(parent) && self.contains(child) {
+                writeln!(
+                    out,
+                    "    n{} -> n{} [color=red, penwidth=3, style=dashed];",
+                    parent.index(),
+                    child.index()
+                )
+                .unwrap();
+            }
+        }
+
+        writeln!(out, "}}").unwrap();
+        out
+    }
+
+    /// The path of wires without delays from `child` to `parent`, the cycle that wiring `parent`
+    /// to `child` would close, `None` if there's none.
+    pub fn cycle_path(&self, parent: ComponentId, child: ComponentId) -> Option<Vec<ComponentId>> {
+        if !self.contains(parent) || !self.contains(child) {
+            return None;
+        }
+        let mut previous: FxHashMap<u32, u32> = FxHashMap::default();
+        let mut queue = VecDeque::from([child.index()]);
+        while let Some(slot) = queue.pop_front() {
+            if slot == parent.index() {
+                let mut path = vec![slot];
+                while let Some(&before) = previous.get(path.last().unwrap()) {
+                    path.push(before);
+                }
+                path.reverse();
+                return path
+                    .into_iter()
+                    .map(|slot| self.nodes.id_at(slot))
+                    .collect();
+            }
+            for next in self.tickless_dag.children(slot) {
+                if next != child.index() && !previous.contains_key(&next) {
+                    previous.insert(next, slot);
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+}
+
+fn group(of: FxHashMap<ComponentId, usize>) -> BTreeMap<usize, Vec<ComponentId>> {
+    let mut groups: BTreeMap<usize, Vec<ComponentId>> = BTreeMap::new();
+    for (id, group) in of {
+        groups.entry(group).or_default().push(id);
+    }
+    for ids in groups.values_mut() {
+        ids.sort();
+    }
+    groups
+}
+
+fn color(value: Logic) -> &'static str {
+    match value {
+        Logic::Zero => "white",
+        Logic::One => "palegreen",
+        Logic::X => "salmon",
+        Logic::Z => "lightblue",
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ComponentKind::*, EngineError};
+
+    #[test]
+    fn test_to_dot() {
+        let mut sim = SimulationEngine::default();
+        let [_, and, _] = sim.add_array_wired([Not, And(1), Delay]);
+        sim.add(Not);
+        sim.set_label(and, Some("\"gate\"")).unwrap();
+        sim.run_step();
+
+        let dot = sim.to_dot(&DotOptions::default());
+        assert!(dot.starts_with("digraph circuit {\n"));
+        assert!(dot.contains("n1 [label=\"and(1)\\n\\\"gate\\\"\"];"));
+        assert!(dot.contains("n0 -> n1 [label=\"out0 -> in0\"];"));
+        assert!(!dot.contains("cluster"));
+
+        let dot = sim.to_dot(&DotOptions {
+            values: true,
+            clustering: Clustering::Island,
+            ..DotOptions::default()
+        });
+        assert!(dot.contains("n0 [label=\"not\\n= 1\", fillcolor=palegreen];"));
+        assert!(dot.contains("n0 -> n1 [label=\"out0 -> in0\", color=palegreen];"));
+        assert!(dot.contains("label=\"island 0\";\n        n0;\n        n1;\n        n2;\n"));
+        assert!(dot.contains("label=\"island 1\";\n        n3;\n"));
+
+        let dot = sim.to_dot(&DotOptions {
+            clustering: Clustering::Level,
+            ..DotOptions::default()
+        });
+        // the delay breaks the levels
+        assert!(dot.contains("label=\"level 0\";\n        n0;\n        n2;\n        n3;\n"));
+        assert!(dot.contains("label=\"level 1\";\n        n1;\n"));
+    }
+
+    #[test]
+    fn test_cycle_path() {
+        let mut sim = SimulationEngine::default();
+        let [first, middle, last] = sim.add_array_wired_of(Not);
+        assert_eq!(
+            sim.wire(last, first, 0, 0),
+            Err(EngineError::CreatesCycle {
+                parent: last,
+                child: first
+            })
+        );
+
+        let mut path = sim.cycle_path(last, first).unwrap();
+        assert_eq!(path, [first, middle, last]);
+        assert_eq!(sim.cycle_path(first, last), None);
+
+        path.push(first);
+        let dot = sim.to_dot(&DotOptions {
+            highlight: path,
+            ..DotOptions::default()
+        });
+        assert!(dot.contains("n0 -> n1 [label=\"out0 -> in0\", color=red, penwidth=3];"));
+        assert!(dot.contains("n2 -> n0 [color=red, penwidth=3, style=dashed];"));
+    }
+}
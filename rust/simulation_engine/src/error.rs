@@ -0,0 +1,80 @@
+use std::{error::Error, fmt};
+
+use crate::{ComponentId, CustomId, SimulationEngine, TableId};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineError {
+    /// The component was removed, or never belonged to this engine.
+    StaleComponent(ComponentId),
+    /// Wiring these would create a cycle without a `Delay` in it.
+    CreatesCycle {
+        parent: ComponentId,
+        child: ComponentId,
+    },
+    InputOutOfBounds {
+        id: ComponentId,
+        input: usize,
+    },
+    OutputOutOfBounds {
+        id: ComponentId,
+        output: usize,
+    },
+    /// A bus read as a number has more bits than fit in one.
+    BusTooWide {
+        width: usize,
+    },
+    ZeroClockPeriod,
+    /// The lookup table isn't stored, or its size doesn't match the component's.
+    InvalidTable(TableId),
+    /// Custom components are only added with `add_custom`, with their instance.
+    CustomKind(CustomId),
+}
+
+impl EngineError {
+    /// Like `Display`, but names components by their label when they have one.
+    pub fn describe(&self, engine: &SimulationEngine) -> String {
+        let mut message = String::new();
+        self.write(&mut message, |id| engine.describe(id)).unwrap();
+        message
+    }
+
+    fn write(&self, f: &mut impl fmt::Write, name: impl Fn(ComponentId) -> String) -> fmt::Result {
+        match *self {
+            EngineError::StaleComponent(id) => write!(f, "component {} doesn't exist", name(id)),
+            EngineError::CreatesCycle { parent, child } => write!(
+                f,
+                "wiring {} to {} creates a cycle without delays",
+                name(parent),
+                name(child)
+            ),
+            EngineError::InputOutOfBounds { id, input } => {
+                write!(f, "component {} has no input {input}", name(id))
+            }
+            EngineError::OutputOutOfBounds { id, output } => {
+                write!(f, "component {} has no output {output}", name(id))
+            }
+            EngineError::BusTooWide { width } => {
+                write!(f, "bus of {width} bits doesn't fit in 64")
+            }
+            EngineError::ZeroClockPeriod => write!(f, "clock period can't be zero"),
+            EngineError::InvalidTable(table) => {
+                write!(
+                    f,
+                    "lookup table {table} isn't stored or doesn't match its size"
+                )
+            }
+            EngineError::CustomKind(id) => write!(
+                f,
+                "custom component {id} can only be added with its instance"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for EngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, |id| format!("{id:?}"))
+    }
+}
+
+impl Error for EngineError {}
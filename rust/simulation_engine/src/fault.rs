@@ -0,0 +1,396 @@
+//! Faults injected on top of a circuit, without changing it, and how many of them a testbench
+//! detects.
+
+use std::{error::Error, fmt};
+
+use crate::{
+    ComponentId, EngineError, Logic, SimulationEngine,
+    recording::Event,
+    testbench::{Testbench, UnknownSignal},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Fault {
+    /// The output always reads `value`, even if it's driven.
+    StuckAt {
+        id: ComponentId,
+        output: usize,
+        value: bool,
+    },
+    /// The output is inverted during `tick`.
+    Flip {
+        id: ComponentId,
+        output: usize,
+        tick: u64,
+    },
+}
+
+impl Fault {
+    /// The output affected by the fault.
+    pub fn target(&self) -> (ComponentId, usize) {
+        match *self {
+            Fault::StuckAt { id, output, .. } | Fault::Flip { id, output, .. } => (id, output),
+        }
+    }
+
+    pub(crate) fn apply(&self, id: ComponentId, output: usize, tick: u64, value: Logic) -> Logic {
+        if self.target() != (id, output) {
+            return value;
+        }
+        match *self {
+            Fault::StuckAt { value: stuck, .. } => Logic::from(stuck),
+            Fault::Flip {
+                tick: flip_tick, ..
+            } if flip_tick == tick => value.not(),
+            Fault::Flip { .. } => value,
+        }
+    }
+
+    fn with_target(self, id: ComponentId) -> Fault {
+        match self {
+            Fault::StuckAt { output, value, .. } => Fault::StuckAt { id, output, value },
+            Fault::Flip { output, tick, .. } => Fault::Flip { id, output, tick },
+        }
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Fault::StuckAt { id, output, value } => {
+                write!(f, "{id:?}.{output} stuck at {}", value as u8)
+            }
+            Fault::Flip { id, output, tick } => write!(f, "{id:?}.{output} flipped at tick {tick}"),
+        }
+    }
+}
+
+impl SimulationEngine {
+    /// Applies `fault` from the next tick on, until it's removed.
+    pub fn inject_fault(&mut self, fault: Fault) -> Result<(), EngineError> {
+        let (id, output) = fault.target();
+        if output >= self.component(id)?.kind.arity().1 {
+            return Err(EngineError::OutputOutOfBounds { id, output });
+        }
+        self.faults.push(fault);
+        self.record_fault(fault, true);
+        Ok(())
+    }
+
+    pub fn remove_fault(&mut self, fault: Fault) {
+        if self.faults.contains(&fault) {
+            self.faults.retain(|&injected| injected != fault);
+            self.record_fault(fault, false);
+        }
+    }
+
+    pub fn clear_faults(&mut self) {
+        if !self.faults.is_empty() {
+            self.faults.clear();
+            self.record(&[], |_| Event::ClearFaults);
+        }
+    }
+
+    pub fn faults(&self) -> &[Fault] {
+        &self.faults
+    }
+
+    /// Stuck-at-0 and stuck-at-1 for every output of every component.
+    pub fn stuck_at_faults(&self) -> Vec<Fault> {
+        self.components()
+            .flat_map(|(id, component)| {
+                (0..component.kind.arity().1).flat_map(move |output| {
+                    [false, true].map(|value| Fault::StuckAt { id, output, value })
+                })
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultResult {
+    pub fault: Fault,
+    /// Indexes of the vectors that fail only because of the fault.
+    pub detected_by: Vec<usize>,
+}
+
+impl FaultResult {
+    pub fn is_detected(&self) -> bool {
+        !self.detected_by.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultCoverage {
+    pub results: Vec<FaultResult>,
+}
+
+impl FaultCoverage {
+    pub fn detected(&self) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.is_detected())
+            .count()
+    }
+
+    pub fn undetected(&self) -> impl Iterator<Item = Fault> {
+        self.results
+            .iter()
+            .filter(|result| !result.is_detected())
+            .map(|result| result.fault)
+    }
+
+    /// Detected faults out of all of them, from 0 to 1.
+    pub fn ratio(&self) -> f64 {
+        if self.results.is_empty() {
+            return 1.0;
+        }
+        self.detected() as f64 / self.results.len() as f64
+    }
+}
+
+impl fmt::Display for FaultCoverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} faults detected ({:.1}%)",
+            self.detected(),
+            self.results.len(),
+            self.ratio() * 100.0
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CoverageError {
+    UnknownSignal(UnknownSignal),
+    /// A fault targets an output the circuit doesn't have.
+    Fault(EngineError),
+    /// The circuit has custom components, which can't be copied to inject the faults into.
+    CustomComponents,
+}
+
+impl From<UnknownSignal> for CoverageError {
+    fn from(error: UnknownSignal) -> Self {
+        CoverageError::UnknownSignal(error)
+    }
+}
+
+impl From<EngineError> for CoverageError {
+    fn from(error: EngineError) -> Self {
+        CoverageError::Fault(error)
+    }
+}
+
+impl fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoverageError::UnknownSignal(error) => write!(f, "{error}"),
+            CoverageError::Fault(error) => write!(f, "{error}"),
+            CoverageError::CustomComponents => {
+                write!(f, "circuits with custom components can't be faulted")
+            }
+        }
+    }
+}
+
+impl Error for CoverageError {}
+
+impl Testbench {
+    /// Runs the testbench once for each fault, on copies of `engine`, and reports which vectors
+    /// detect each of them.
+    ///
+    /// A vector detects a fault if it fails with it but passes without it.
+    pub fn fault_coverage(
+        &self,
+        engine: &SimulationEngine,
+        signal: impl Fn(&str) -> Option<(ComponentId, usize)>,
+        faults: &[Fault],
+    ) -> Result<FaultCoverage, CoverageError> {
+        let run = |fault: Option<Fault>| -> Result<_, CoverageError> {
+            let (mut copy, copied_id) =
+                engine.duplicate().ok_or(CoverageError::CustomComponents)?;
+            if let Some(fault) = fault {
+                let (id, _) = fault.target();
+                copy.inject_fault(fault.with_target(copied_id[&id]))
+                    .expect("fault targets a component of the engine");
+            }
+            let report = self.run(&mut copy, |name| {
+                signal(name).map(|(id, output)| (copied_id[&id], output))
+            })?;
+            Ok(report)
+        };
+
+        for fault in faults {
+            let (id, output) = fault.target();
+            if output >= engine.component(id)?.kind.arity().1 {
+                return Err(EngineError::OutputOutOfBounds { id, output }.into());
+            }
+        }
+        let fault_free = run(None)?;
+        let mut results = vec![];
+        for &fault in faults {
+            let report = run(Some(fault))?;
+            let detected_by = report
+                .failed_vectors
+                .into_iter()
+                .filter(|vector| !fault_free.failed_vectors.contains(vector))
+                .collect();
+            results.push(FaultResult { fault, detected_by });
+        }
+        Ok(FaultCoverage { results })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CustomComponent, hdl};
+
+    const AND_GATE: &str = "
+        inputs[2] = delay;
+        gate = and(2);
+        inputs[0] -> gate.in0;
+        inputs[1] -> gate.in1;
+    ";
+
+    #[test]
+    fn test_stuck_at() {
+        let mut netlist = hdl::compile(AND_GATE).unwrap();
+        let (gate, _) = netlist.signal("gate").unwrap();
+        let engine = &mut netlist.engine;
+
+        engine
+            .inject_fault(Fault::StuckAt {
+                id: gate,
+                output: 0,
+                value: true,
+            })
+            .unwrap();
+        engine.run_step();
+        assert!(engine.is_on(gate));
+
+        engine.clear_faults();
+        engine.run_step();
+        assert!(engine.is_off(gate));
+        assert!(
+            engine
+                .inject_fault(Fault::StuckAt {
+                    id: gate,
+                    output: 1,
+                    value: true,
+                })
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_flip() {
+        let mut netlist = hdl::compile(AND_GATE).unwrap();
+        let (gate, _) = netlist.signal("gate").unwrap();
+        let engine = &mut netlist.engine;
+
+        engine
+            .inject_fault(Fault::Flip {
+                id: gate,
+                output: 0,
+                tick: 2,
+            })
+            .unwrap();
+        engine.run_step();
+        assert!(engine.is_off(gate));
+        engine.run_step();
+        assert!(engine.is_on(gate));
+        engine.run_step();
+        assert!(engine.is_off(gate));
+    }
+
+    #[test]
+    fn test_fault_coverage() {
+        let netlist = hdl::compile(AND_GATE).unwrap();
+        let (gate, _) = netlist.signal("gate").unwrap();
+        let (input, _) = netlist.signal("inputs[0]").unwrap();
+        let stuck_at = |id, value| Fault::StuckAt {
+            id,
+            output: 0,
+            value,
+        };
+        let faults = [
+            stuck_at(gate, false),
+            stuck_at(gate, true),
+            stuck_at(input, true),
+        ];
+
+        // only checks `1 1`, which can't tell a stuck-at-1 apart
+        let testbench = Testbench::parse(
+            "
+            inputs inputs[0] inputs[1]
+            outputs gate
+            1 1 | 1
+            ",
+        )
+        .unwrap();
+        let coverage = netlist.fault_coverage(&testbench, &faults).unwrap();
+        assert_eq!(coverage.detected(), 1);
+        assert_eq!(coverage.to_string(), "1/3 faults detected (33.3%)");
+        assert_eq!(coverage.results[0].detected_by, [0]);
+
+        let testbench = Testbench::parse(
+            "
+            inputs inputs[0] inputs[1]
+            outputs gate
+            1 1 | 1
+            0 0 | 0
+            0 1 | 0
+            ",
+        )
+        .unwrap();
+        let coverage = netlist.fault_coverage(&testbench, &faults).unwrap();
+        assert_eq!(coverage.detected(), 3);
+        assert_eq!(coverage.results[1].detected_by, [1, 2]);
+        assert_eq!(coverage.results[2].detected_by, [2]);
+        assert_eq!(coverage.undetected().count(), 0);
+
+        // the faults were applied to copies
+        assert!(netlist.engine.faults().is_empty());
+        assert_eq!(netlist.engine.current_tick(), 0);
+    }
+
+    struct Buffer;
+
+    impl CustomComponent for Buffer {
+        fn arity(&self) -> (usize, usize) {
+            (1, 1)
+        }
+
+        fn evaluate(&self, inputs: &[bool]) -> Vec<bool> {
+            inputs.to_vec()
+        }
+    }
+
+    #[test]
+    fn test_fault_coverage_with_custom_components() {
+        let mut netlist = hdl::compile(AND_GATE).unwrap();
+        let buffer = netlist.engine.add_custom(Buffer);
+        let testbench = Testbench::parse(
+            "
+            inputs inputs[0] inputs[1]
+            outputs gate
+            1 1 | 1
+            ",
+        )
+        .unwrap();
+        let faults = netlist.engine.stuck_at_faults();
+        assert_eq!(
+            netlist.fault_coverage(&testbench, &faults),
+            Err(CoverageError::CustomComponents)
+        );
+        netlist.engine.remove(buffer).unwrap();
+        assert_eq!(
+            netlist.fault_coverage(&testbench, &faults),
+            Err(CoverageError::Fault(EngineError::StaleComponent(buffer)))
+        );
+        let faults = netlist.engine.stuck_at_faults();
+        assert!(netlist.fault_coverage(&testbench, &faults).is_ok());
+    }
+}
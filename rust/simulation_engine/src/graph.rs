@@ -0,0 +1,237 @@
+//! Read-only queries about how components are wired.
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{ComponentId, EngineError, SimulationEngine, Wire};
+
+/// An output of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct OutputPort {
+    pub id: ComponentId,
+    pub output: usize,
+}
+
+/// An input of a component.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InputPort {
+    pub id: ComponentId,
+    pub input: usize,
+}
+
+impl Wire {
+    pub fn source(&self) -> OutputPort {
+        OutputPort {
+            id: self.parent,
+            output: self.parent_output,
+        }
+    }
+
+    pub fn sink(&self) -> InputPort {
+        InputPort {
+            id: self.child,
+            input: self.child_input,
+        }
+    }
+}
+
+impl SimulationEngine {
+    /// Every wire of the circuit.
+    pub fn wires(&self) -> impl Iterator<Item = Wire> {
+        self.components()
+            .flat_map(|(parent, _)| self.outgoing_wires(parent))
+    }
+
+    /// Wires into the inputs of a component, empty if it doesn't exist.
+    pub fn incoming_wires(&self, id: ComponentId) -> impl Iterator<Item = Wire> {
+        self.nodes
+            .get(id)
+            .into_iter()
+            .flat_map(move |_| self.incoming_to(id))
+            .map(move |(parent, edge)| Wire::new(parent, id, edge))
+    }
+
+    /// Wires out of the outputs of a component, empty if it doesn't exist.
+    pub fn outgoing_wires(&self, id: ComponentId) -> impl Iterator<Item = Wire> {
+        self.nodes
+            .get(id)
+            .into_iter()
+            .flat_map(move |_| self.outgoing_from(id))
+            .map(move |(child, edge)| Wire::new(id, child, edge))
+    }
+
+    /// The outputs wired to an input, they're OR-ed.
+    pub fn drivers(&self, id: ComponentId, input: usize) -> Result<Vec<OutputPort>, EngineError> {
+        if input >= self.component(id)?.kind.arity().0 {
+            return Err(EngineError::InputOutOfBounds { id, input });
+        }
+        Ok(self.fan_in(id)?.swap_remove(input))
+    }
+
+    /// The inputs an output is wired to.
+    pub fn loads(&self, id: ComponentId, output: usize) -> Result<Vec<InputPort>, EngineError> {
+        if output >= self.component(id)?.kind.arity().1 {
+            return Err(EngineError::OutputOutOfBounds { id, output });
+        }
+        Ok(self.fan_out(id)?.swap_remove(output))
+    }
+
+    /// The drivers of every input, indexed by input.
+    pub fn fan_in(&self, id: ComponentId) -> Result<Vec<Vec<OutputPort>>, EngineError> {
+        let mut drivers = vec![vec![]; self.component(id)?.kind.arity().0];
+        for wire in self.incoming_wires(id) {
+            drivers[wire.child_input].push(wire.source());
+        }
+        Ok(drivers)
+    }
+
+    /// The loads of every output, indexed by output.
+    pub fn fan_out(&self, id: ComponentId) -> Result<Vec<Vec<InputPort>>, EngineError> {
+        let mut loads = vec![vec![]; self.component(id)?.kind.arity().1];
+        for wire in self.outgoing_wires(id) {
+            loads[wire.parent_output].push(wire.sink());
+        }
+        Ok(loads)
+    }
+
+    /// Longest path of wires without delays leading to a component, `0` for delays and
+    /// components without parents.
+    pub fn level(&self, id: ComponentId) -> Result<usize, EngineError> {
+        self.component(id)?;
+        Ok(self.slot_levels()[id.index() as usize])
+    }
+
+    /// The components connected to this one by wires, delays included, itself included.
+    pub fn island(&self, id: ComponentId) -> Result<Vec<ComponentId>, EngineError> {
+        self.component(id)?;
+        let mut island = FxHashSet::from_iter([id]);
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let component = &self.nodes[id];
+            for &next in component.incoming.keys().chain(component.outgoing.keys()) {
+                if island.insert(next) {
+                    stack.push(next);
+                }
+            }
+        }
+        let mut island: Vec<ComponentId> = island.into_iter().collect();
+        island.sort();
+        Ok(island)
+    }
+
+    /// Numbers the groups of components connected by wires.
+    pub(crate) fn islands(&self) -> FxHashMap<ComponentId, usize> {
+        let mut island = FxHashMap::default();
+        let mut count = 0;
+        for (start, _) in self.components() {
+            if island.contains_key(&start) {
+                continue;
+            }
+            let mut stack = vec![start];
+            island.insert(start, count);
+            while let Some(id) = stack.pop() {
+                let component = &self.nodes[id];
+                for &next in component.incoming.keys().chain(component.outgoing.keys()) {
+                    if island.insert(next, count).is_none() {
+                        stack.push(next);
+                    }
+                }
+            }
+            count += 1;
+        }
+        island
+    }
+
+    /// The level of every component.
+    pub(crate) fn levels(&self) -> FxHashMap<ComponentId, usize> {
+        let levels = self.slot_levels();
+        self.components()
+            .map(|(id, _)| (id, levels[id.index() as usize]))
+            .collect()
+    }
+
+    /// The level of every arena slot, computed with parents before their children.
+    fn slot_levels(&self) -> Vec<usize> {
+        let mut levels = vec![0; self.tickless_dag.slot_count()];
+        for slot in self.tickless_dag.topological_order() {
+            levels[slot as usize] = self
+                .tickless_dag
+                .parents(slot)
+                .map(|parent| levels[parent as usize] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComponentKind::*;
+
+    #[test]
+    fn test_graph_queries() {
+        let mut sim = SimulationEngine::default();
+        let [a, b, adder, not, delay, lonely] =
+            sim.add_array([Not, Not, HalfAdder, Not, Delay, Not]);
+        sim.wire(a, adder, 0, 0).unwrap();
+        sim.wire(b, adder, 0, 0).unwrap();
+        sim.wire(adder, not, 1, 0).unwrap();
+        sim.wire(adder, delay, 1, 0).unwrap();
+
+        let port = |id, output| OutputPort { id, output };
+        assert_eq!(sim.drivers(adder, 0), Ok(vec![port(a, 0), port(b, 0)]));
+        assert_eq!(sim.drivers(adder, 1), Ok(vec![]));
+        assert_eq!(
+            sim.drivers(adder, 2),
+            Err(EngineError::InputOutOfBounds {
+                id: adder,
+                input: 2
+            })
+        );
+        assert_eq!(sim.fan_in(adder).unwrap().len(), 2);
+        let loads = sim.loads(adder, 1).unwrap();
+        assert_eq!(
+            loads,
+            [
+                InputPort { id: not, input: 0 },
+                InputPort {
+                    id: delay,
+                    input: 0
+                }
+            ]
+        );
+        assert_eq!(sim.fan_out(adder).unwrap()[0], []);
+
+        assert_eq!(sim.wires().count(), 4);
+        assert!(sim.wires().all(|wire| wire.sink().id != lonely));
+        assert_eq!(
+            sim.incoming_wires(not).next().unwrap().source(),
+            port(adder, 1)
+        );
+
+        assert_eq!(sim.level(a), Ok(0));
+        assert_eq!(sim.level(not), Ok(2));
+        assert_eq!(sim.level(delay), Ok(0));
+        assert_eq!(sim.island(delay), Ok(vec![a, b, adder, not, delay]));
+        assert_eq!(sim.island(lonely), Ok(vec![lonely]));
+
+        sim.remove(lonely).unwrap();
+        assert_eq!(sim.level(lonely), Err(EngineError::StaleComponent(lonely)));
+        assert_eq!(sim.incoming_wires(lonely).count(), 0);
+    }
+
+    #[test]
+    fn test_long_chain() {
+        let mut sim = SimulationEngine::default();
+        let first = sim.add(Not);
+        let mut last = first;
+        for _ in 0..100_000 {
+            let next = sim.add(Not);
+            sim.wire0(last, next);
+            last = next;
+        }
+        assert_eq!(sim.level(last), Ok(100_000));
+        assert_eq!(sim.island(first).unwrap().len(), 100_001);
+    }
+}
@@ -0,0 +1,960 @@
+//! A small textual description language for circuits.
+//!
+//! ```text
+//! # modules list their input and output ports, which can be buses
+//! # a port can have a label and metadata
+//! module nor(a, b) -> (out "result" { active = "high" }) {
+//!     gate = not;
+//!     a -> gate;          # the default ports are `in0` and `out0`
+//!     b -> gate.in0;      # signals driving the same input are OR-ed
+//!     gate.out -> out;
+//! }
+//!
+//! clock = clock(2, 1, 0);
+//! latch = nor;
+//! memory[4] = delay;      # an array of four delays
+//! clock -> latch.a;
+//! latch.out -> memory[0].in;
+//! memory[0..3] -> memory[1..4];
+//! ```
+//!
+//! Components are named by their path, like `memory[2]` or `latch.gate`.
+
+mod parser;
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    error::Error,
+    fmt,
+};
+
+use parser::{Ident, Index, Module, PortDecl, Position, Signal, Statement};
+
+use crate::{
+    ComponentId, ComponentKind, SimulationEngine,
+    fault::{CoverageError, Fault, FaultCoverage},
+    testbench::{TestReport, Testbench, UnknownSignal},
+};
+
+pub struct Netlist {
+    pub engine: SimulationEngine,
+    /// Every component, by its path, which is also its label in the engine.
+    pub components: BTreeMap<String, ComponentId>,
+    /// Every port of a module instance, by its path like `latch.out`.
+    pub ports: BTreeMap<String, Port>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Port {
+    pub bits: PortBits,
+    pub label: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PortBits {
+    /// For each bit, the inputs it drives.
+    Input(Vec<Vec<(ComponentId, usize)>>),
+    /// For each bit, the output driving it, `None` if undriven.
+    Output(Vec<Option<(ComponentId, usize)>>),
+}
+
+impl Netlist {
+    /// Finds an output by its component's path, optionally followed by the port, like
+    /// `adder.carry` or `latch.gate.out0`, or by the path of a module output port, like
+    /// `latch.out` or `register.out[2]`.
+    pub fn signal(&self, name: &str) -> Option<(ComponentId, usize)> {
+        if let Some(&id) = self.components.get(name) {
+            return Some((id, 0));
+        }
+        if let Some(pin) = self.output_port_bit(name) {
+            return Some(pin);
+        }
+        let (path, port) = name.rsplit_once('.')?;
+        let id = *self.components.get(path)?;
+        let kind = self.engine.component(id).ok()?.kind;
+        Some((id, output_port_index(kind, port)?))
+    }
+
+    fn output_port_bit(&self, name: &str) -> Option<(ComponentId, usize)> {
+        let (path, bit) = match name
+            .strip_suffix(']')
+            .and_then(|name| name.rsplit_once('['))
+        {
+            Some((path, bit)) => (path, bit.parse().ok()?),
+            None => (name, 0),
+        };
+        match &self.ports.get(path)?.bits {
+            PortBits::Output(bits) => *bits.get(bit)?,
+            PortBits::Input(_) => None,
+        }
+    }
+
+    /// The first port labeled `label`, with its path.
+    pub fn port_by_label(&self, label: &str) -> Option<(&str, &Port)> {
+        self.ports
+            .iter()
+            .find(|(_, port)| port.label.as_deref() == Some(label))
+            .map(|(path, port)| (path.as_str(), port))
+    }
+}
+
+impl Netlist {
+    /// Runs `testbench`, with its signals resolved by [`Self::signal`].
+    pub fn run_testbench(&mut self, testbench: &Testbench) -> Result<TestReport, UnknownSignal> {
+        let signals: HashMap<&str, (ComponentId, usize)> = testbench
+            .inputs
+            .iter()
+            .chain(&testbench.outputs)
+            .filter_map(|name| Some((name.as_str(), self.signal(name)?)))
+            .collect();
+        testbench.run(&mut self.engine, |name| signals.get(name).copied())
+    }
+
+    /// Fault coverage of `testbench`, with its signals resolved by [`Self::signal`].
+    pub fn fault_coverage(
+        &self,
+        testbench: &Testbench,
+        faults: &[Fault],
+    ) -> Result<FaultCoverage, CoverageError> {
+        testbench.fault_coverage(&self.engine, |name| self.signal(name), faults)
+    }
+}
+
+/// Builds the circuit described by the top-level statements of `source`.
+pub fn compile(source: &str) -> Result<Netlist, HdlError> {
+    let file = parser::parse(source)?;
+
+    let mut modules = HashMap::new();
+    for module in &file.modules {
+        if modules.insert(module.name.name.as_str(), module).is_some() {
+            return Err(HdlError::at(
+                &module.name,
+                &format!("module `{}` is defined twice", module.name.name),
+            ));
+        }
+    }
+
+    let mut elaborator = Elaborator {
+        modules,
+        engine: SimulationEngine::new(),
+        components: BTreeMap::new(),
+        ports: BTreeMap::new(),
+        stack: vec![],
+    };
+    elaborator.elaborate_body(
+        &file.body,
+        &mut Scope::default(),
+        &mut Interface::default(),
+        "",
+    )?;
+
+    Ok(Netlist {
+        engine: elaborator.engine,
+        components: elaborator.components,
+        ports: elaborator.ports,
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HdlError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl HdlError {
+    fn new(position: Position, message: &str) -> Self {
+        Self {
+            line: position.line,
+            column: position.column,
+            message: message.to_owned(),
+        }
+    }
+
+    fn at(ident: &Ident, message: &str) -> Self {
+        Self::new(ident.position, message)
+    }
+}
+
+impl fmt::Display for HdlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl Error for HdlError {}
+
+type Pin = (ComponentId, usize);
+
+/// The ports of an elaborated module instance.
+#[derive(Debug, Default, Clone)]
+struct Interface {
+    /// For each port, and each bit of it, the inputs it drives.
+    inputs: Vec<(String, Vec<Vec<Pin>>)>,
+    /// For each port, and each bit of it, the output driving it.
+    outputs: Vec<(String, Vec<Option<Pin>>)>,
+}
+
+enum Entry {
+    Components {
+        kind: ComponentKind,
+        ids: Vec<ComponentId>,
+        is_array: bool,
+    },
+    Modules {
+        module: String,
+        interfaces: Vec<Interface>,
+        is_array: bool,
+    },
+    /// An input port of the module being elaborated.
+    InputPort { port: usize, width: usize },
+    /// An output port of the module being elaborated.
+    OutputPort { port: usize, width: usize },
+}
+
+#[derive(Default)]
+struct Scope {
+    entries: HashMap<String, Entry>,
+}
+
+enum SourceBit {
+    Pin(Pin),
+    InputPort { port: usize, bit: usize },
+}
+
+enum SinkBit {
+    Pins(Vec<Pin>),
+    OutputPort { port: usize, bit: usize },
+}
+
+struct Elaborator<'a> {
+    modules: HashMap<&'a str, &'a Module>,
+    engine: SimulationEngine,
+    components: BTreeMap<String, ComponentId>,
+    ports: BTreeMap<String, Port>,
+    /// Modules being elaborated, to detect recursive instantiations.
+    stack: Vec<&'a str>,
+}
+
+impl<'a> Elaborator<'a> {
+    fn elaborate_module(
+        &mut self,
+        module: &'a Module,
+        prefix: &str,
+    ) -> Result<Interface, HdlError> {
+        let mut scope = Scope::default();
+        let mut interface = Interface::default();
+
+        let declare = |scope: &mut Scope, port: &PortDecl, entry: Entry| {
+            if scope
+                .entries
+                .insert(port.name.name.clone(), entry)
+                .is_some()
+            {
+                return Err(HdlError::at(
+                    &port.name,
+                    &format!("port `{}` is declared twice", port.name.name),
+                ));
+            }
+            Ok(())
+        };
+        for (index, port) in module.inputs.iter().enumerate() {
+            let width = port.width.unwrap_or(1);
+            declare(&mut scope, port, Entry::InputPort { port: index, width })?;
+            interface
+                .inputs
+                .push((port.name.name.clone(), vec![vec![]; width]));
+        }
+        for (index, port) in module.outputs.iter().enumerate() {
+            let width = port.width.unwrap_or(1);
+            declare(&mut scope, port, Entry::OutputPort { port: index, width })?;
+            interface
+                .outputs
+                .push((port.name.name.clone(), vec![None; width]));
+        }
+
+        self.stack.push(&module.name.name);
+        self.elaborate_body(&module.body, &mut scope, &mut interface, prefix)?;
+        self.stack.pop();
+        Ok(interface)
+    }
+
+    fn elaborate_body(
+        &mut self,
+        body: &'a [Statement],
+        scope: &mut Scope,
+        interface: &mut Interface,
+        prefix: &str,
+    ) -> Result<(), HdlError> {
+        // instances first, so connections can refer to instances declared after them
+        for statement in body {
+            if let Statement::Instance {
+                name,
+                width,
+                kind,
+                args,
+            } = statement
+            {
+                let entry = self.instantiate(name, *width, kind, args, prefix)?;
+                if scope.entries.insert(name.name.clone(), entry).is_some() {
+                    return Err(HdlError::at(
+                        name,
+                        &format!("`{}` is already defined", name.name),
+                    ));
+                }
+            }
+        }
+
+        for statement in body {
+            if let Statement::Connection {
+                source,
+                sinks,
+                position,
+            } = statement
+            {
+                let source_bits = self.resolve_source(source, scope)?;
+                for sink in sinks {
+                    let sink_bits = self.resolve_sink(sink, scope)?;
+                    self.connect(&source_bits, sink_bits, interface, *position)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn instantiate(
+        &mut self,
+        name: &Ident,
+        width: Option<usize>,
+        kind: &Ident,
+        args: &[u64],
+        prefix: &str,
+    ) -> Result<Entry, HdlError> {
+        let element_names: Vec<String> = match width {
+            Some(width) => (0..width)
+                .map(|i| format!("{prefix}{}[{i}]", name.name))
+                .collect(),
+            None => vec![format!("{prefix}{}", name.name)],
+        };
+
+        if let Some(&module) = self.modules.get(kind.name.as_str()) {
+            if !args.is_empty() {
+                return Err(HdlError::at(kind, "modules don't take arguments"));
+            }
+            if self.stack.contains(&kind.name.as_str()) {
+                return Err(HdlError::at(
+                    kind,
+                    &format!("module `{}` instantiates itself", kind.name),
+                ));
+            }
+
+            let interfaces = element_names
+                .iter()
+                .map(|element_name| {
+                    let interface = self.elaborate_module(module, &format!("{element_name}."))?;
+                    self.add_ports(element_name, module, &interface);
+                    Ok(interface)
+                })
+                .collect::<Result<_, _>>()?;
+            return Ok(Entry::Modules {
+                module: kind.name.clone(),
+                interfaces,
+                is_array: width.is_some(),
+            });
+        }
+
+        let text = if args.is_empty() {
+            kind.name.clone()
+        } else {
+            let args: Vec<String> = args.iter().map(u64::to_string).collect();
+            format!("{}({})", kind.name, args.join(", "))
+        };
+        let component_kind: ComponentKind = text.parse().map_err(|_| {
+            HdlError::at(
+                kind,
+                &format!("`{text}` isn't a module nor a valid component kind"),
+            )
+        })?;
+
+        let ids = element_names
+            .into_iter()
+            .map(|element_name| {
+                let id = self
+                    .engine
+                    .try_add(component_kind)
+                    .map_err(|error| HdlError::at(kind, &error.to_string()))?;
+                self.engine.set_label(id, Some(&element_name)).unwrap();
+                self.components.insert(element_name, id);
+                Ok(id)
+            })
+            .collect::<Result<_, HdlError>>()?;
+        Ok(Entry::Components {
+            kind: component_kind,
+            ids,
+            is_array: width.is_some(),
+        })
+    }
+
+    fn add_ports(&mut self, instance: &str, module: &Module, interface: &Interface) {
+        // the interface has the ports in the order they're declared
+        let inputs = module
+            .inputs
+            .iter()
+            .zip(&interface.inputs)
+            .map(|(decl, (_, bits))| (decl, PortBits::Input(bits.clone())));
+        let outputs = module
+            .outputs
+            .iter()
+            .zip(&interface.outputs)
+            .map(|(decl, (_, bits))| (decl, PortBits::Output(bits.clone())));
+        for (decl, bits) in inputs.chain(outputs) {
+            let port = Port {
+                bits,
+                label: decl.label.clone(),
+                metadata: decl.metadata.clone(),
+            };
+            self.ports
+                .insert(format!("{instance}.{}", decl.name.name), port);
+        }
+    }
+
+    fn resolve_source(&self, signal: &Signal, scope: &Scope) -> Result<Vec<SourceBit>, HdlError> {
+        let name = &signal.name;
+        match lookup(scope, name)? {
+            Entry::Components {
+                kind,
+                ids,
+                is_array,
+            } => {
+                let ids = select(ids, signal.index, *is_array, name)?;
+                let output = match &signal.port {
+                    Some((port, index)) => {
+                        no_index(*index, port)?;
+                        output_port(*kind, port)?
+                    }
+                    None => 0,
+                };
+                Ok(ids.iter().map(|&id| SourceBit::Pin((id, output))).collect())
+            }
+            Entry::Modules {
+                module,
+                interfaces,
+                is_array,
+            } => {
+                let (port, port_index) = module_port(signal, module)?;
+                let mut bits = vec![];
+                for interface in select(interfaces, signal.index, *is_array, name)? {
+                    let Some((_, port_bits)) =
+                        interface.outputs.iter().find(|(n, _)| *n == port.name)
+                    else {
+                        return Err(HdlError::at(
+                            port,
+                            &format!("module `{module}` has no output `{}`", port.name),
+                        ));
+                    };
+                    for bit in select(port_bits, port_index, port_bits.len() > 1, port)? {
+                        let Some(pin) = *bit else {
+                            return Err(HdlError::at(
+                                port,
+                                &format!(
+                                    "output `{}` of module `{module}` isn't driven",
+                                    port.name
+                                ),
+                            ));
+                        };
+                        bits.push(SourceBit::Pin(pin));
+                    }
+                }
+                Ok(bits)
+            }
+            &Entry::InputPort { port, width } => {
+                no_port(signal)?;
+                let bits = select_range(width, signal.index, name)?;
+                Ok(bits.map(|bit| SourceBit::InputPort { port, bit }).collect())
+            }
+            Entry::OutputPort { .. } => Err(HdlError::at(
+                name,
+                &format!("`{}` is an output port, it can't be read", name.name),
+            )),
+        }
+    }
+
+    fn resolve_sink(&self, signal: &Signal, scope: &Scope) -> Result<Vec<SinkBit>, HdlError> {
+        let name = &signal.name;
+        match lookup(scope, name)? {
+            Entry::Components {
+                kind,
+                ids,
+                is_array,
+            } => {
+                let ids = select(ids, signal.index, *is_array, name)?;
+                let input = match &signal.port {
+                    Some((port, index)) => {
+                        no_index(*index, port)?;
+                        input_port(*kind, port)?
+                    }
+                    None if kind.arity().0 == 0 => {
+                        return Err(HdlError::at(name, &format!("`{kind}` doesn't have inputs")));
+                    }
+                    None => 0,
+                };
+                Ok(ids
+                    .iter()
+                    .map(|&id| SinkBit::Pins(vec![(id, input)]))
+                    .collect())
+            }
+            Entry::Modules {
+                module,
+                interfaces,
+                is_array,
+            } => {
+                let (port, port_index) = module_port(signal, module)?;
+                let mut bits = vec![];
+                for interface in select(interfaces, signal.index, *is_array, name)? {
+                    let Some((_, port_bits)) =
+                        interface.inputs.iter().find(|(n, _)| *n == port.name)
+                    else {
+                        return Err(HdlError::at(
+                            port,
+                            &format!("module `{module}` has no input `{}`", port.name),
+                        ));
+                    };
+                    for pins in select(port_bits, port_index, port_bits.len() > 1, port)? {
+                        bits.push(SinkBit::Pins(pins.clone()));
+                    }
+                }
+                Ok(bits)
+            }
+            &Entry::OutputPort { port, width } => {
+                no_port(signal)?;
+                let bits = select_range(width, signal.index, name)?;
+                Ok(bits.map(|bit| SinkBit::OutputPort { port, bit }).collect())
+            }
+            Entry::InputPort { .. } => Err(HdlError::at(
+                name,
+                &format!("`{}` is an input port, it can't be driven", name.name),
+            )),
+        }
+    }
+
+    fn connect(
+        &mut self,
+        sources: &[SourceBit],
+        sinks: Vec<SinkBit>,
+        interface: &mut Interface,
+        position: Position,
+    ) -> Result<(), HdlError> {
+        if sources.len() != sinks.len() && sources.len() != 1 {
+            return Err(HdlError::new(
+                position,
+                &format!(
+                    "width mismatch, source has {} bits but sink has {}",
+                    sources.len(),
+                    sinks.len(),
+                ),
+            ));
+        }
+
+        for (i, sink) in sinks.into_iter().enumerate() {
+            let source = &sources[i % sources.len()];
+            match (source, sink) {
+                (&SourceBit::Pin((parent, output)), SinkBit::Pins(pins)) => {
+                    for (child, input) in pins {
+                        if self.engine.wire(parent, child, output, input).is_err() {
+                            return Err(HdlError::new(
+                                position,
+                                "connection creates a cycle without delays",
+                            ));
+                        }
+                    }
+                }
+                (&SourceBit::InputPort { port, bit }, SinkBit::Pins(pins)) => {
+                    interface.inputs[port].1[bit].extend(pins);
+                }
+                (&SourceBit::Pin(pin), SinkBit::OutputPort { port, bit }) => {
+                    let driver = &mut interface.outputs[port].1[bit];
+                    if driver.is_some() {
+                        return Err(HdlError::new(
+                            position,
+                            &format!(
+                                "output port `{}` is driven twice",
+                                interface.outputs[port].0
+                            ),
+                        ));
+                    }
+                    *driver = Some(pin);
+                }
+                (SourceBit::InputPort { .. }, SinkBit::OutputPort { .. }) => {
+                    return Err(HdlError::new(
+                        position,
+                        "input ports can't be connected straight to output ports",
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn lookup<'s>(scope: &'s Scope, name: &Ident) -> Result<&'s Entry, HdlError> {
+    scope
+        .entries
+        .get(&name.name)
+        .ok_or_else(|| HdlError::at(name, &format!("`{}` isn't defined", name.name)))
+}
+
+/// Selects the elements of an array, or the only one of a non-array.
+fn select<'t, T>(
+    items: &'t [T],
+    index: Option<Index>,
+    is_array: bool,
+    name: &Ident,
+) -> Result<&'t [T], HdlError> {
+    if index.is_some() && !is_array {
+        return Err(HdlError::at(
+            name,
+            &format!("`{}` isn't an array", name.name),
+        ));
+    }
+    let range = select_range(items.len(), index, name)?;
+    Ok(&items[range])
+}
+
+fn select_range(
+    len: usize,
+    index: Option<Index>,
+    name: &Ident,
+) -> Result<std::ops::Range<usize>, HdlError> {
+    let range = match index {
+        None => 0..len,
+        Some(Index::Single(i)) => i..i + 1,
+        Some(Index::Range(start, end)) => start..end,
+    };
+    if range.end > len {
+        return Err(HdlError::at(
+            name,
+            &format!("index out of bounds, `{}` has {len} bits", name.name),
+        ));
+    }
+    Ok(range)
+}
+
+fn module_port<'s>(
+    signal: &'s Signal,
+    module: &str,
+) -> Result<(&'s Ident, Option<Index>), HdlError> {
+    match &signal.port {
+        Some((port, index)) => Ok((port, *index)),
+        None => Err(HdlError::at(
+            &signal.name,
+            &format!(
+                "`{}` is an instance of module `{module}`, a port must be given",
+                signal.name.name
+            ),
+        )),
+    }
+}
+
+fn no_port(signal: &Signal) -> Result<(), HdlError> {
+    match &signal.port {
+        Some((port, _)) => Err(HdlError::at(
+            port,
+            &format!("`{}` is a port, it has no ports", signal.name.name),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn no_index(index: Option<Index>, port: &Ident) -> Result<(), HdlError> {
+    match index {
+        Some(_) => Err(HdlError::at(
+            port,
+            "ports of components are single bits, they can't be indexed",
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Parses `in` or `in<N>`.
+fn input_port(kind: ComponentKind, port: &Ident) -> Result<usize, HdlError> {
+    let input = numbered_port(&port.name, "in");
+    match input {
+        Some(input) if input < kind.arity().0 => Ok(input),
+        _ => Err(HdlError::at(
+            port,
+            &format!("`{kind}` has no input `{}`", port.name),
+        )),
+    }
+}
+
+fn output_port(kind: ComponentKind, port: &Ident) -> Result<usize, HdlError> {
+    output_port_index(kind, &port.name)
+        .ok_or_else(|| HdlError::at(port, &format!("`{kind}` has no output `{}`", port.name)))
+}
+
+/// Parses `out` or `out<N>`, or `sum` and `carry` for adders.
+fn output_port_index(kind: ComponentKind, name: &str) -> Option<usize> {
+    let is_adder = matches!(kind, ComponentKind::HalfAdder | ComponentKind::FullAdder);
+    let output = match name {
+        "sum" if is_adder => 0,
+        "carry" if is_adder => 1,
+        name => numbered_port(name, "out")?,
+    };
+    (output < kind.arity().1).then_some(output)
+}
+
+fn numbered_port(name: &str, prefix: &str) -> Option<usize> {
+    match name.strip_prefix(prefix)? {
+        "" => Some(0),
+        number => number.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn compile_err(source: &str) -> String {
+        compile(source).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn test_half_adder_from_gates() {
+        let source = "
+            module nor(a, b) -> (out) {
+                gate = not;
+                a -> gate;
+                b -> gate.in0;
+                gate.out -> out;
+            }
+
+            # xor(a, b) = nor(and(a, b), nor(a, b))
+            module half_adder(a, b) -> (sum, carry) {
+                both = and(2);
+                neither = nor;
+                sum_gate = nor;
+                a -> both.in0, neither.a;
+                b -> both.in1, neither.b;
+                both -> sum_gate.a, carry;
+                neither.out -> sum_gate.b;
+                sum_gate.out -> sum;
+            }
+
+            inputs[2] = delay;
+            adder = half_adder;
+            inputs[0] -> adder.a;
+            inputs[1] -> adder.b;
+        ";
+        let Netlist {
+            mut engine,
+            components,
+            ..
+        } = compile(source).unwrap();
+
+        assert_eq!(components.len(), 5);
+        assert!(components.contains_key("adder.neither.gate"));
+
+        let [a, b] = [components["inputs[0]"], components["inputs[1]"]];
+        let sum = components["adder.sum_gate.gate"];
+        let carry = components["adder.both"];
+
+        for (a_value, b_value) in [(false, false), (true, false), (false, true), (true, true)] {
+            engine.drive(a, 0, a_value).unwrap();
+            engine.drive(b, 0, b_value).unwrap();
+            engine.run_step();
+            assert_eq!(engine.is_on(sum), a_value ^ b_value);
+            assert_eq!(engine.is_on(carry), a_value && b_value);
+        }
+    }
+
+    #[test]
+    fn test_buses() {
+        let source = "
+            module register(in[4]) -> (out[4]) {
+                bits[4] = delay;
+                in -> bits;
+                bits -> out;
+            }
+
+            counter_bits[4] = delay;
+            first = register;
+            second = register;
+            adders[4] = half_adder;
+            one = not;
+
+            counter_bits -> adders.in0;
+            one -> adders[0].in1;
+            adders[0..3].carry -> adders[1..4].in1;
+            adders.sum -> counter_bits, first.in;
+            first.out[0..4] -> second.in;
+        ";
+        let Netlist {
+            mut engine,
+            components,
+            ..
+        } = compile(source).unwrap();
+
+        let counter: Vec<_> = (0..4)
+            .map(|i| (components[&format!("counter_bits[{i}]")], 0))
+            .collect();
+        let second: Vec<_> = (0..4)
+            .map(|i| (components[&format!("second.bits[{i}]")], 0))
+            .collect();
+
+        for tick in 1..40 {
+            engine.run_step();
+            assert_eq!(engine.bus_value(&counter).unwrap(), (tick - 1) % 16);
+            assert_eq!(
+                engine.bus_value(&second).unwrap(),
+                (tick + 14) % 16 * (tick >= 3) as u64
+            );
+        }
+    }
+
+    #[test]
+    fn test_labels_and_ports() {
+        let source = r#"
+            module register(in[2]) -> (out[2] "result #1" { unit = "volts", doc = "http://x" }) {
+                bits[2] = delay;
+                in -> bits;
+                bits -> out;
+            }
+
+            inputs[2] = delay; # a comment with a "quote
+            first = register; // another one
+            inputs -> first.in;
+        "#;
+        let netlist = compile(source).unwrap();
+        let bit = netlist.components["first.bits[1]"];
+        assert_eq!(netlist.engine.label(bit), Some("first.bits[1]"));
+        assert_eq!(netlist.engine.find_by_path("first").count(), 2);
+
+        assert_eq!(netlist.signal("first.out[1]"), Some((bit, 0)));
+        assert_eq!(netlist.signal("first.out[2]"), None);
+        assert_eq!(netlist.signal("first.in"), None);
+        let PortBits::Input(bits) = &netlist.ports["first.in"].bits else {
+            panic!("`in` is an input port");
+        };
+        assert_eq!(bits[1], [(bit, 0)]);
+
+        assert_eq!(netlist.ports["first.in"].label, None);
+        let (path, port) = netlist.port_by_label("result #1").unwrap();
+        assert_eq!(
+            (path, port.metadata["unit"].as_str()),
+            ("first.out", "volts")
+        );
+        assert_eq!(port.metadata["doc"], "http://x");
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            compile_err("a = not"),
+            "1:8: expected `;`, found end of file"
+        );
+        assert_eq!(
+            compile_err("a = not;\n  a -> ;"),
+            "2:8: expected a name, found `;`"
+        );
+        assert_eq!(
+            compile_err("a = not; a $ b;"),
+            "1:12: unexpected character `$`"
+        );
+        assert_eq!(
+            compile_err("a[0..2] = not;"),
+            "1:1: expected an instance name"
+        );
+        assert_eq!(
+            compile_err("a = not;\na;"),
+            "2:2: expected `=` or `->`, found `;`"
+        );
+        assert_eq!(
+            compile_err("module m() { a = not;"),
+            "1:22: expected `}`, found end of file"
+        );
+        assert_eq!(
+            compile_err("a[18446744073709551615] = not;"),
+            "1:3: `18446744073709551615` is over the limit of 65536 bits"
+        );
+        assert_eq!(
+            compile_err("a = not;\nb = not;\na[0..100000] -> b;"),
+            "3:6: `100000` is over the limit of 65536 bits"
+        );
+        assert_eq!(
+            compile_err("module m(a \"in) {}"),
+            "1:12: text isn't closed with `\"`"
+        );
+        assert_eq!(
+            compile_err("module m(a { x = \"1\", x = \"2\" }) {}"),
+            "1:23: key `x` is given twice"
+        );
+        assert_eq!(
+            compile_err("module m(a { x = 1 }) {}"),
+            "1:18: expected a quoted text, found `1`"
+        );
+    }
+
+    #[test]
+    fn test_semantic_errors() {
+        assert_eq!(
+            compile_err("a = nand;"),
+            "1:5: `nand` isn't a module nor a valid component kind"
+        );
+        assert_eq!(
+            compile_err("a = and(99999999999);"),
+            "1:5: `and(99999999999)` isn't a module nor a valid component kind"
+        );
+        assert_eq!(
+            compile_err("a = lut(1, 1, 0);"),
+            "1:5: lookup table 0 isn't stored or doesn't match its size"
+        );
+        assert_eq!(compile_err("a = not;\na -> b;"), "2:6: `b` isn't defined");
+        assert_eq!(
+            compile_err("a = not;\na = not;"),
+            "2:1: `a` is already defined"
+        );
+        assert_eq!(
+            compile_err("a = not;\nb = and(2);\na -> b.in2;"),
+            "3:8: `and(2)` has no input `in2`"
+        );
+        assert_eq!(
+            compile_err("a = not;\nb = clock(2, 1, 0);\na.carry -> b;"),
+            "3:3: `not` has no output `carry`"
+        );
+        assert_eq!(
+            compile_err("a = not;\na -> a;"),
+            "2:1: connection creates a cycle without delays"
+        );
+        assert_eq!(
+            compile_err("a[2] = not;\nb[3] = delay;\na -> b;"),
+            "3:1: width mismatch, source has 2 bits but sink has 3"
+        );
+        assert_eq!(
+            compile_err("a[2] = not;\nb = delay;\na[2] -> b;"),
+            "3:1: index out of bounds, `a` has 2 bits"
+        );
+        assert_eq!(
+            compile_err("a = not;\nb = delay;\na[0] -> b;"),
+            "3:1: `a` isn't an array"
+        );
+        assert_eq!(
+            compile_err("module m(x) -> (y) { x -> y; }\nn = m;"),
+            "1:22: input ports can't be connected straight to output ports"
+        );
+        assert_eq!(
+            compile_err("module m() -> (y) { }\nn = m;\nd = delay;\nn.y -> d;"),
+            "4:3: output `y` of module `m` isn't driven"
+        );
+        assert_eq!(
+            compile_err("module m() { n = m; }\nn = m;"),
+            "1:18: module `m` instantiates itself"
+        );
+        assert_eq!(
+            compile_err("module m(x) { }\nn = m;\nd = delay;\nd -> n;"),
+            "4:6: `n` is an instance of module `m`, a port must be given"
+        );
+    }
+}
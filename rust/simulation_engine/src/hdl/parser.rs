@@ -0,0 +1,442 @@
+use std::collections::BTreeMap;
+
+use super::HdlError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Ident(String),
+    Number(u64),
+    /// Text between double quotes, which it can't contain.
+    Text(String),
+    Symbol(&'static str),
+    Eof,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    position: Position,
+}
+
+/// Widths and indices above this are rejected, arrays are expanded bit by bit.
+const MAX_WIDTH: u64 = 1 << 16;
+
+// longer symbols first, so `->` isn't lexed as `-`
+const SYMBOLS: [&str; 12] = ["->", "..", "(", ")", "[", "]", "{", "}", ",", ";", ".", "="];
+
+fn tokenize(source: &str) -> Result<Vec<Token>, HdlError> {
+    let mut tokens = vec![];
+
+    for (line_index, line) in source.lines().enumerate() {
+        let mut rest = line;
+
+        loop {
+            rest = rest.trim_start();
+            let position = Position {
+                line: line_index + 1,
+                column: line.len() - rest.len() + 1,
+            };
+            // comments run to the end of the line, texts are skipped whole so they can have
+            // `#` and `//` in them
+            let Some(first) = rest.chars().next() else {
+                break;
+            };
+            if first == '#' || rest.starts_with("//") {
+                break;
+            }
+
+            let (kind, length) = if first.is_ascii_alphabetic() || first == '_' {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                (TokenKind::Ident(rest[..length].to_owned()), length)
+            } else if first.is_ascii_digit() {
+                let length = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let number = rest[..length]
+                    .parse()
+                    .map_err(|_| HdlError::new(position, "number is too big"))?;
+                (TokenKind::Number(number), length)
+            } else if first == '"' {
+                let length = rest[1..]
+                    .find('"')
+                    .ok_or_else(|| HdlError::new(position, "text isn't closed with `\"`"))?;
+                (TokenKind::Text(rest[1..length + 1].to_owned()), length + 2)
+            } else if let Some(symbol) = SYMBOLS.iter().find(|symbol| rest.starts_with(**symbol)) {
+                (TokenKind::Symbol(symbol), symbol.len())
+            } else {
+                return Err(HdlError::new(
+                    position,
+                    &format!("unexpected character `{first}`"),
+                ));
+            };
+
+            tokens.push(Token { kind, position });
+            rest = &rest[length..];
+        }
+    }
+
+    let last_line = source.lines().count().max(1);
+    tokens.push(Token {
+        kind: TokenKind::Eof,
+        position: Position {
+            line: last_line,
+            column: source.lines().last().map_or(0, str::len) + 1,
+        },
+    });
+    Ok(tokens)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    pub name: String,
+    pub position: Position,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Index {
+    Single(usize),
+    /// Exclusive range.
+    Range(usize, usize),
+}
+
+/// A port declaration like `a`, `data[8]` or `sum[4] "result" { unit = "volts" }`.
+#[derive(Debug, Clone)]
+pub struct PortDecl {
+    pub name: Ident,
+    pub width: Option<usize>,
+    pub label: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: Ident,
+    pub inputs: Vec<PortDecl>,
+    pub outputs: Vec<PortDecl>,
+    pub body: Vec<Statement>,
+}
+
+/// A reference to bits, like `gate`, `gate.out1`, `regs[0..4].in0` or `adder.sum[2]`.
+#[derive(Debug, Clone)]
+pub struct Signal {
+    pub name: Ident,
+    pub index: Option<Index>,
+    pub port: Option<(Ident, Option<Index>)>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    /// `name = kind;` or `name[width] = kind;`, where the kind's arguments are kept as
+    /// written to be parsed by `ComponentKind::from_str`.
+    Instance {
+        name: Ident,
+        width: Option<usize>,
+        kind: Ident,
+        args: Vec<u64>,
+    },
+    /// `source -> sink, sink;`
+    Connection {
+        source: Signal,
+        sinks: Vec<Signal>,
+        position: Position,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct File {
+    pub modules: Vec<Module>,
+    pub body: Vec<Statement>,
+}
+
+pub fn parse(source: &str) -> Result<File, HdlError> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        cursor: 0,
+    };
+
+    let mut file = File::default();
+    while parser.peek().kind != TokenKind::Eof {
+        if parser.peek().kind == TokenKind::Ident("module".to_owned()) {
+            file.modules.push(parser.module()?);
+        } else {
+            file.body.push(parser.statement()?);
+        }
+    }
+    Ok(file)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    cursor: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.cursor]
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self.tokens[self.cursor].clone();
+        if token.kind != TokenKind::Eof {
+            self.cursor += 1;
+        }
+        token
+    }
+
+    fn error(&self, expected: &str) -> HdlError {
+        let token = self.peek();
+        let found = match &token.kind {
+            TokenKind::Ident(name) => format!("`{name}`"),
+            TokenKind::Number(number) => format!("`{number}`"),
+            TokenKind::Text(text) => format!("`\"{text}\"`"),
+            TokenKind::Symbol(symbol) => format!("`{symbol}`"),
+            TokenKind::Eof => "end of file".to_owned(),
+        };
+        HdlError::new(
+            token.position,
+            &format!("expected {expected}, found {found}"),
+        )
+    }
+
+    fn is_symbol(&self, symbol: &str) -> bool {
+        self.peek().kind == TokenKind::Symbol(symbol_ref(symbol))
+    }
+
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let is_symbol = self.is_symbol(symbol);
+        if is_symbol {
+            self.next();
+        }
+        is_symbol
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<(), HdlError> {
+        if self.eat_symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("`{symbol}`")))
+        }
+    }
+
+    fn ident(&mut self) -> Result<Ident, HdlError> {
+        match self.peek().kind.clone() {
+            TokenKind::Ident(name) => Ok(Ident {
+                name,
+                position: self.next().position,
+            }),
+            _ => Err(self.error("a name")),
+        }
+    }
+
+    fn text(&mut self) -> Result<String, HdlError> {
+        match self.peek().kind.clone() {
+            TokenKind::Text(text) => {
+                self.next();
+                Ok(text)
+            }
+            _ => Err(self.error("a quoted text")),
+        }
+    }
+
+    fn number(&mut self) -> Result<u64, HdlError> {
+        match self.peek().kind {
+            TokenKind::Number(number) => {
+                self.next();
+                Ok(number)
+            }
+            _ => Err(self.error("a number")),
+        }
+    }
+
+    /// A width or an index, at most `MAX_WIDTH`.
+    fn bounded_number(&mut self) -> Result<usize, HdlError> {
+        let position = self.peek().position;
+        match self.number()? {
+            number if number > MAX_WIDTH => Err(HdlError::new(
+                position,
+                &format!("`{number}` is over the limit of {MAX_WIDTH} bits"),
+            )),
+            number => Ok(number as usize),
+        }
+    }
+
+    fn module(&mut self) -> Result<Module, HdlError> {
+        self.next(); // `module`
+        let name = self.ident()?;
+
+        self.expect_symbol("(")?;
+        let inputs = self.port_decls(")")?;
+        let outputs = if self.eat_symbol("->") {
+            self.expect_symbol("(")?;
+            self.port_decls(")")?
+        } else {
+            vec![]
+        };
+
+        self.expect_symbol("{")?;
+        let mut body = vec![];
+        while !self.eat_symbol("}") {
+            if self.peek().kind == TokenKind::Eof {
+                return Err(self.error("`}`"));
+            }
+            body.push(self.statement()?);
+        }
+
+        Ok(Module {
+            name,
+            inputs,
+            outputs,
+            body,
+        })
+    }
+
+    fn port_decls(&mut self, closing: &str) -> Result<Vec<PortDecl>, HdlError> {
+        let mut ports = vec![];
+        while !self.eat_symbol(closing) {
+            if !ports.is_empty() {
+                self.expect_symbol(",")?;
+            }
+            let name = self.ident()?;
+            let width = self.width()?;
+            let label = match self.peek().kind {
+                TokenKind::Text(_) => Some(self.text()?),
+                _ => None,
+            };
+            let metadata = self.metadata()?;
+            ports.push(PortDecl {
+                name,
+                width,
+                label,
+                metadata,
+            });
+        }
+        Ok(ports)
+    }
+
+    /// `{ key = "value", ... }`, or nothing.
+    fn metadata(&mut self) -> Result<BTreeMap<String, String>, HdlError> {
+        let mut metadata = BTreeMap::new();
+        if !self.eat_symbol("{") {
+            return Ok(metadata);
+        }
+        while !self.eat_symbol("}") {
+            if !metadata.is_empty() {
+                self.expect_symbol(",")?;
+            }
+            let key = self.ident()?;
+            self.expect_symbol("=")?;
+            let value = self.text()?;
+            if metadata.insert(key.name.clone(), value).is_some() {
+                return Err(HdlError::at(
+                    &key,
+                    &format!("key `{}` is given twice", key.name),
+                ));
+            }
+        }
+        Ok(metadata)
+    }
+
+    fn width(&mut self) -> Result<Option<usize>, HdlError> {
+        if !self.eat_symbol("[") {
+            return Ok(None);
+        }
+        let width = self.bounded_number()?;
+        self.expect_symbol("]")?;
+        Ok(Some(width))
+    }
+
+    fn statement(&mut self) -> Result<Statement, HdlError> {
+        let position = self.peek().position;
+        let signal = self.signal()?;
+
+        if self.eat_symbol("=") {
+            let (None | Some(Index::Single(_)), None) = (signal.index, &signal.port) else {
+                return Err(HdlError::new(position, "expected an instance name"));
+            };
+            let width = match signal.index {
+                Some(Index::Single(width)) => Some(width),
+                _ => None,
+            };
+
+            let kind = self.ident()?;
+            let mut args = vec![];
+            if self.eat_symbol("(") {
+                while !self.eat_symbol(")") {
+                    if !args.is_empty() {
+                        self.expect_symbol(",")?;
+                    }
+                    args.push(self.number()?);
+                }
+            }
+            self.expect_symbol(";")?;
+
+            return Ok(Statement::Instance {
+                name: signal.name,
+                width,
+                kind,
+                args,
+            });
+        }
+
+        if !self.eat_symbol("->") {
+            return Err(self.error("`=` or `->`"));
+        }
+        let mut sinks = vec![self.signal()?];
+        while self.eat_symbol(",") {
+            sinks.push(self.signal()?);
+        }
+        self.expect_symbol(";")?;
+
+        Ok(Statement::Connection {
+            source: signal,
+            sinks,
+            position,
+        })
+    }
+
+    fn signal(&mut self) -> Result<Signal, HdlError> {
+        let name = self.ident()?;
+        let index = self.index()?;
+        let port = if self.eat_symbol(".") {
+            let port = self.ident()?;
+            Some((port, self.index()?))
+        } else {
+            None
+        };
+        Ok(Signal { name, index, port })
+    }
+
+    fn index(&mut self) -> Result<Option<Index>, HdlError> {
+        if !self.eat_symbol("[") {
+            return Ok(None);
+        }
+        let position = self.peek().position;
+        let start = self.bounded_number()?;
+        let index = if self.eat_symbol("..") {
+            let end = self.bounded_number()?;
+            if end <= start {
+                return Err(HdlError::new(position, "range is empty"));
+            }
+            Index::Range(start, end)
+        } else {
+            Index::Single(start)
+        };
+        self.expect_symbol("]")?;
+        Ok(Some(index))
+    }
+}
+
+fn symbol_ref(symbol: &str) -> &'static str {
+    SYMBOLS
+        .iter()
+        .find(|known| **known == symbol)
+        .expect("unknown symbol")
+}
@@ -0,0 +1,822 @@
+//! Undo and redo of structural edits, labels and metadata.
+
+use std::{collections::BTreeMap, error::Error, fmt};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Batch, BatchError, ComponentId, ComponentKind, Edit, EngineError, SimulationEngine, Target,
+    Wire,
+};
+
+const ROLLBACK_MESSAGE: &str = "rolling back restores edits that were valid";
+
+/// A structural edit, with what it takes to invert it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Command {
+    Add(ComponentId, ComponentKind),
+    Wire(Wire),
+    Unwire(Wire),
+    Remove {
+        id: ComponentId,
+        kind: ComponentKind,
+        label: Option<String>,
+        metadata: BTreeMap<String, String>,
+        wires: Vec<Wire>,
+    },
+    Replace {
+        id: ComponentId,
+        old: ComponentKind,
+        new: ComponentKind,
+        dropped: Vec<Wire>,
+    },
+    Label {
+        id: ComponentId,
+        old: Option<String>,
+        new: Option<String>,
+    },
+    /// `None` when the key has no value.
+    Metadata {
+        id: ComponentId,
+        key: String,
+        old: Option<String>,
+        new: Option<String>,
+    },
+}
+
+impl Command {
+    fn rename(&mut self, old: ComponentId, new: ComponentId) {
+        let rename = |id: &mut ComponentId| {
+            if *id == old {
+                *id = new;
+            }
+        };
+        let rename_wire = |wire: &mut Wire| {
+            rename(&mut wire.parent);
+            rename(&mut wire.child);
+        };
+        match self {
+            Command::Add(id, _) => rename(id),
+            Command::Wire(wire) | Command::Unwire(wire) => rename_wire(wire),
+            Command::Remove { id, wires, .. } => {
+                rename(id);
+                wires.iter_mut().for_each(rename_wire);
+            }
+            Command::Replace { id, dropped, .. } => {
+                rename(id);
+                dropped.iter_mut().for_each(rename_wire);
+            }
+            Command::Label { id, .. } | Command::Metadata { id, .. } => rename(id),
+        }
+    }
+}
+
+/// How an undo or redo changed component ids, for callers that keep ids around.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdChange {
+    /// The component was removed, by undoing its add or redoing its removal.
+    Removed(ComponentId),
+    /// A removed component is back under a new id.
+    Restored { old: ComponentId, new: ComponentId },
+}
+
+/// Undo and redo for the structural edits, labels and metadata made through it.
+///
+/// Every structural edit to the components in the history has to go through it, so the
+/// recorded edits can be inverted. Values aren't restored, components added back start off.
+#[derive(Debug, Default, Clone)]
+pub struct History {
+    undo: Vec<Vec<Command>>,
+    redo: Vec<Vec<Command>>,
+    /// Edits of the group in progress, `None` when not grouping.
+    group: Option<Vec<Command>>,
+    depth: usize,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(
+        &mut self,
+        engine: &mut SimulationEngine,
+        kind: ComponentKind,
+    ) -> Result<ComponentId, EngineError> {
+        let id = engine.try_add(kind)?;
+        self.push(Command::Add(id, kind));
+        Ok(id)
+    }
+
+    pub fn wire(
+        &mut self,
+        engine: &mut SimulationEngine,
+        parent: ComponentId,
+        child: ComponentId,
+        parent_output: usize,
+        child_input: usize,
+    ) -> Result<(), EngineError> {
+        let wire = Wire {
+            parent,
+            child,
+            parent_output,
+            child_input,
+        };
+        let existed = engine.outgoing_wires(parent).any(|other| other == wire);
+        engine.wire(parent, child, parent_output, child_input)?;
+        if !existed {
+            self.push(Command::Wire(wire));
+        }
+        Ok(())
+    }
+
+    pub fn unwire(
+        &mut self,
+        engine: &mut SimulationEngine,
+        parent: ComponentId,
+        child: ComponentId,
+        parent_output: usize,
+        child_input: usize,
+    ) -> Result<bool, EngineError> {
+        let removed = engine.unwire(parent, child, parent_output, child_input)?;
+        if removed {
+            self.push(Command::Unwire(Wire {
+                parent,
+                child,
+                parent_output,
+                child_input,
+            }));
+        }
+        Ok(removed)
+    }
+
+    /// Removing a custom component can't be undone, so it clears the history.
+    pub fn remove(
+        &mut self,
+        engine: &mut SimulationEngine,
+        id: ComponentId,
+    ) -> Result<ComponentKind, EngineError> {
+        let component = engine.component(id)?;
+        let label = component.label().map(str::to_owned);
+        let metadata = component.metadata().clone();
+        let mut wires: Vec<Wire> = engine.incoming_wires(id).collect();
+        wires.extend(engine.outgoing_wires(id).filter(|wire| wire.child != id));
+        let kind = engine.remove(id)?;
+        if kind.is_custom() {
+            self.clear();
+        } else {
+            self.push(Command::Remove {
+                id,
+                kind,
+                label,
+                metadata,
+                wires,
+            });
+        }
+        Ok(kind)
+    }
+
+    /// Replacing the kind of a custom component can't be undone, so it clears the history.
+    pub fn replace_kind(
+        &mut self,
+        engine: &mut SimulationEngine,
+        id: ComponentId,
+        kind: ComponentKind,
+    ) -> Result<Vec<Wire>, EngineError> {
+        let old = engine.component(id)?.kind;
+        let dropped = engine.replace_kind(id, kind)?;
+        if old.is_custom() {
+            self.clear();
+        } else {
+            self.push(Command::Replace {
+                id,
+                old,
+                new: kind,
+                dropped: dropped.clone(),
+            });
+        }
+        Ok(dropped)
+    }
+
+    /// Names a component, see [`SimulationEngine::set_label`].
+    pub fn set_label(
+        &mut self,
+        engine: &mut SimulationEngine,
+        id: ComponentId,
+        label: Option<&str>,
+    ) -> Result<(), EngineError> {
+        let old = engine.component(id)?.label().map(str::to_owned);
+        engine.set_label(id, label)?;
+        self.push(Command::Label {
+            id,
+            old,
+            new: label.map(str::to_owned),
+        });
+        Ok(())
+    }
+
+    pub fn set_metadata(
+        &mut self,
+        engine: &mut SimulationEngine,
+        id: ComponentId,
+        key: &str,
+        value: &str,
+    ) -> Result<(), EngineError> {
+        let old = engine.component(id)?.metadata().get(key).cloned();
+        engine.set_metadata(id, key, value)?;
+        self.push(Command::Metadata {
+            id,
+            key: key.to_owned(),
+            old,
+            new: Some(value.to_owned()),
+        });
+        Ok(())
+    }
+
+    pub fn remove_metadata(
+        &mut self,
+        engine: &mut SimulationEngine,
+        id: ComponentId,
+        key: &str,
+    ) -> Result<Option<String>, EngineError> {
+        engine.component(id)?;
+        let old = engine.remove_metadata(id, key);
+        if old.is_some() {
+            self.push(Command::Metadata {
+                id,
+                key: key.to_owned(),
+                old: old.clone(),
+                new: None,
+            });
+        }
+        Ok(old)
+    }
+
+    /// Applies a batch as one undo step.
+    pub fn apply(
+        &mut self,
+        engine: &mut SimulationEngine,
+        batch: &Batch,
+    ) -> Result<Vec<ComponentId>, BatchError> {
+        let existing: Vec<bool> = (batch.edits().iter())
+            .map(|edit| match *edit {
+                Edit::Wire {
+                    parent: Target::Existing(parent),
+                    child: Target::Existing(child),
+                    parent_output,
+                    child_input,
+                } => engine.outgoing_wires(parent).any(|wire| {
+                    wire == Wire {
+                        parent,
+                        child,
+                        parent_output,
+                        child_input,
+                    }
+                }),
+                _ => false,
+            })
+            .collect();
+        // labels and metadata of the existing targets, updated edit by edit below
+        let mut labels = FxHashMap::default();
+        let mut metadata = FxHashMap::default();
+        for edit in batch.edits() {
+            match edit {
+                &Edit::Label(Target::Existing(id), _) => {
+                    let label = engine.label(id).map(str::to_owned);
+                    labels.entry(id).or_insert(label);
+                }
+                Edit::Metadata {
+                    target: Target::Existing(id),
+                    key,
+                    ..
+                } => {
+                    let value = (engine.component(*id).ok())
+                        .and_then(|component| component.metadata().get(key).cloned());
+                    metadata.entry((*id, key.clone())).or_insert(value);
+                }
+                _ => {}
+            }
+        }
+        let ids = engine.apply(batch)?;
+
+        let id = |target| match target {
+            Target::Existing(id) => id,
+            Target::Pending(pending) => ids[pending.index()],
+        };
+        let mut added = ids.iter();
+        self.begin_group();
+        for (edit, existed) in batch.edits().iter().zip(existing) {
+            match *edit {
+                Edit::Add(kind) => self.push(Command::Add(*added.next().unwrap(), kind)),
+                Edit::Wire {
+                    parent,
+                    child,
+                    parent_output,
+                    child_input,
+                } if !existed => self.push(Command::Wire(Wire {
+                    parent: id(parent),
+                    child: id(child),
+                    parent_output,
+                    child_input,
+                })),
+                Edit::Label(target, ref label) => {
+                    let id = id(target);
+                    let old = labels.insert(id, label.clone()).flatten();
+                    self.push(Command::Label {
+                        id,
+                        old,
+                        new: label.clone(),
+                    });
+                }
+                Edit::Metadata {
+                    target,
+                    ref key,
+                    ref value,
+                } => {
+                    let id = id(target);
+                    let old = metadata
+                        .insert((id, key.clone()), Some(value.clone()))
+                        .flatten();
+                    self.push(Command::Metadata {
+                        id,
+                        key: key.clone(),
+                        old,
+                        new: Some(value.clone()),
+                    });
+                }
+                _ => {}
+            }
+        }
+        self.end_group().expect("the group was just begun");
+        Ok(ids)
+    }
+
+    /// Groups the edits until the matching [`History::end_group`] into one undo step, groups
+    /// can be nested.
+    pub fn begin_group(&mut self) {
+        self.depth += 1;
+        self.group.get_or_insert_default();
+    }
+
+    /// Fails with [`StepError::NoGroup`] without a matching [`History::begin_group`].
+    pub fn end_group(&mut self) -> Result<(), StepError> {
+        if self.depth == 0 {
+            return Err(StepError::NoGroup);
+        }
+        self.depth -= 1;
+        if self.depth == 0 {
+            let group = self.group.take().unwrap();
+            if !group.is_empty() {
+                self.undo.push(group);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        if let Some(group) = &mut self.group {
+            group.clear();
+        }
+    }
+
+    /// Inverts the last step, `None` if there's nothing to undo.
+    ///
+    /// If an inverse edit fails, like rewiring a loop after loops were forbidden, or because
+    /// the components were edited without the history, the step is rolled back and stays the
+    /// one to undo. Fails with [`StepError::GroupInProgress`] while a group is open.
+    pub fn undo(
+        &mut self,
+        engine: &mut SimulationEngine,
+    ) -> Result<Option<Vec<IdChange>>, StepError> {
+        if self.depth > 0 {
+            return Err(StepError::GroupInProgress);
+        }
+        let Some(mut step) = self.undo.pop() else {
+            return Ok(None);
+        };
+        let mut changes = vec![];
+        for index in (0..step.len()).rev() {
+            match Self::invert(engine, &step[index], &mut changes) {
+                Ok(rename) => self.apply_rename(&mut step, rename),
+                Err(error) => {
+                    for index in index + 1..step.len() {
+                        let rename = Self::perform(engine, &step[index], &mut changes)
+                            .expect(ROLLBACK_MESSAGE);
+                        self.apply_rename(&mut step, rename);
+                    }
+                    self.undo.push(step);
+                    return Err(StepError::Failed { error, changes });
+                }
+            }
+        }
+        self.redo.push(step);
+        Ok(Some(changes))
+    }
+
+    /// Applies the last undone step again, `None` if there's nothing to redo.
+    ///
+    /// Fails like [`History::undo`], the step stays the one to redo then.
+    pub fn redo(
+        &mut self,
+        engine: &mut SimulationEngine,
+    ) -> Result<Option<Vec<IdChange>>, StepError> {
+        if self.depth > 0 {
+            return Err(StepError::GroupInProgress);
+        }
+        let Some(mut step) = self.redo.pop() else {
+            return Ok(None);
+        };
+        let mut changes = vec![];
+        for index in 0..step.len() {
+            match Self::perform(engine, &step[index], &mut changes) {
+                Ok(rename) => self.apply_rename(&mut step, rename),
+                Err(error) => {
+                    for index in (0..index).rev() {
+                        let rename = Self::invert(engine, &step[index], &mut changes)
+                            .expect(ROLLBACK_MESSAGE);
+                        self.apply_rename(&mut step, rename);
+                    }
+                    self.redo.push(step);
+                    return Err(StepError::Failed { error, changes });
+                }
+            }
+        }
+        self.undo.push(step);
+        Ok(Some(changes))
+    }
+
+    fn push(&mut self, command: Command) {
+        self.redo.clear();
+        match &mut self.group {
+            Some(group) => group.push(command),
+            None => self.undo.push(vec![command]),
+        }
+    }
+
+    /// Renames a component added back by `perform` or `invert` everywhere in the history.
+    fn apply_rename(&mut self, step: &mut [Command], rename: Option<(ComponentId, ComponentId)>) {
+        let Some((old, new)) = rename else {
+            return;
+        };
+        for command in (step.iter_mut())
+            .chain(self.undo.iter_mut().flatten())
+            .chain(self.redo.iter_mut().flatten())
+        {
+            command.rename(old, new);
+        }
+    }
+
+    /// Applies a command again, returns the id it renamed. Does nothing if it fails.
+    fn perform(
+        engine: &mut SimulationEngine,
+        command: &Command,
+        changes: &mut Vec<IdChange>,
+    ) -> Result<Option<(ComponentId, ComponentId)>, EngineError> {
+        match command {
+            &Command::Add(old, kind) => {
+                let new = engine.try_add(kind)?;
+                changes.push(IdChange::Restored { old, new });
+                return Ok(Some((old, new)));
+            }
+            Command::Wire(wire) => engine.wire(
+                wire.parent,
+                wire.child,
+                wire.parent_output,
+                wire.child_input,
+            )?,
+            Command::Unwire(wire) => {
+                engine.unwire(
+                    wire.parent,
+                    wire.child,
+                    wire.parent_output,
+                    wire.child_input,
+                )?;
+            }
+            &Command::Remove { id, .. } => {
+                engine.remove(id)?;
+                changes.push(IdChange::Removed(id));
+            }
+            &Command::Replace { id, new, .. } => {
+                engine.replace_kind(id, new)?;
+            }
+            Command::Label { id, new, .. } => engine.set_label(*id, new.as_deref())?,
+            Command::Metadata { id, key, new, .. } => {
+                Self::restore_metadata(engine, *id, key, new.as_deref())?
+            }
+        }
+        Ok(None)
+    }
+
+    /// Inverts a command, returns the id it renamed. Does nothing if it fails.
+    fn invert(
+        engine: &mut SimulationEngine,
+        command: &Command,
+        changes: &mut Vec<IdChange>,
+    ) -> Result<Option<(ComponentId, ComponentId)>, EngineError> {
+        let rewire = |engine: &mut SimulationEngine,
+                      wires: &[Wire],
+                      rename: &dyn Fn(ComponentId) -> ComponentId| {
+            wires.iter().try_for_each(|wire| {
+                engine.wire(
+                    rename(wire.parent),
+                    rename(wire.child),
+                    wire.parent_output,
+                    wire.child_input,
+                )
+            })
+        };
+        match command {
+            &Command::Add(id, _) => {
+                engine.remove(id)?;
+                changes.push(IdChange::Removed(id));
+            }
+            Command::Wire(wire) => {
+                engine.unwire(
+                    wire.parent,
+                    wire.child,
+                    wire.parent_output,
+                    wire.child_input,
+                )?;
+            }
+            Command::Unwire(wire) => engine.wire(
+                wire.parent,
+                wire.child,
+                wire.parent_output,
+                wire.child_input,
+            )?,
+            Command::Remove {
+                id: old,
+                kind,
+                label,
+                metadata,
+                wires,
+            } => {
+                let (old, new) = (*old, engine.try_add(*kind)?);
+                engine.set_label(new, label.as_deref()).unwrap();
+                for (key, value) in metadata {
+                    engine.set_metadata(new, key, value).unwrap();
+                }
+                let rename = |id| if id == old { new } else { id };
+                if let Err(error) = rewire(engine, wires, &rename) {
+                    // every wire touches it, so they go with it
+                    engine.remove(new).unwrap();
+                    return Err(error);
+                }
+                changes.push(IdChange::Restored { old, new });
+                return Ok(Some((old, new)));
+            }
+            Command::Replace {
+                id,
+                old,
+                new,
+                dropped,
+            } => {
+                engine.replace_kind(*id, *old)?;
+                if let Err(error) = rewire(engine, dropped, &|id| id) {
+                    // drops the wires rewired so far, they don't fit the new kind
+                    engine.replace_kind(*id, *new).expect(ROLLBACK_MESSAGE);
+                    return Err(error);
+                }
+            }
+            Command::Label { id, old, .. } => engine.set_label(*id, old.as_deref())?,
+            Command::Metadata { id, key, old, .. } => {
+                Self::restore_metadata(engine, *id, key, old.as_deref())?
+            }
+        }
+        Ok(None)
+    }
+
+    /// Sets the value of a key, or removes it for `None`.
+    fn restore_metadata(
+        engine: &mut SimulationEngine,
+        id: ComponentId,
+        key: &str,
+        value: Option<&str>,
+    ) -> Result<(), EngineError> {
+        match value {
+            Some(value) => engine.set_metadata(id, key, value),
+            None => {
+                engine.component(id)?;
+                engine.remove_metadata(id, key);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepError {
+    /// An edit of the step failed, and the step was rolled back.
+    Failed {
+        error: EngineError,
+        /// Rolling back adds back what it had removed, under new ids.
+        changes: Vec<IdChange>,
+    },
+    /// Undo and redo wait for the open group to end.
+    GroupInProgress,
+    /// A group was ended without being begun.
+    NoGroup,
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::Failed { error, .. } => write!(f, "{error}"),
+            StepError::GroupInProgress => write!(f, "an edit group is still open"),
+            StepError::NoGroup => write!(f, "no edit group to end"),
+        }
+    }
+}
+
+impl Error for StepError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ComponentKind::*, LoopMode};
+
+    #[test]
+    fn test_undo_redo() {
+        let mut sim = SimulationEngine::default();
+        let mut history = History::new();
+        let not = history.add(&mut sim, Not).unwrap();
+        let and = history.add(&mut sim, And(2)).unwrap();
+        history.wire(&mut sim, not, and, 0, 1).unwrap();
+        sim.set_label(and, Some("gate")).unwrap();
+        let saved = sim.save();
+
+        let removed = and;
+        history.begin_group();
+        history.remove(&mut sim, and).unwrap();
+        let delay = history.add(&mut sim, Delay).unwrap();
+        history.wire(&mut sim, not, delay, 0, 0).unwrap();
+        history.end_group().unwrap();
+        assert_eq!(sim.component_count(), 2);
+
+        let changes = history.undo(&mut sim).unwrap().unwrap();
+        assert_eq!(changes[0], IdChange::Removed(delay));
+        let IdChange::Restored { old, new: and } = changes[1] else {
+            panic!("{changes:?}")
+        };
+        assert_eq!(old, removed);
+        assert_eq!(sim.save(), saved);
+        assert_eq!(sim.label(and), Some("gate"));
+
+        history.redo(&mut sim).unwrap();
+        assert!(!sim.contains(and));
+        assert_eq!(sim.component_count(), 2);
+        history.undo(&mut sim).unwrap();
+        assert_eq!(sim.save(), saved);
+
+        // undoing restores a dropped wire
+        let and = sim.find_by_label("gate").unwrap();
+        assert_eq!(
+            history.replace_kind(&mut sim, and, And(1)).unwrap().len(),
+            1
+        );
+        history.undo(&mut sim).unwrap();
+        assert_eq!(sim.save(), saved);
+
+        history.unwire(&mut sim, not, and, 0, 1).unwrap();
+        assert_eq!(sim.wires().count(), 0);
+        // a new edit drops the redo steps
+        assert!(!history.can_redo());
+        history.undo(&mut sim).unwrap();
+        assert_eq!(sim.save(), saved);
+
+        while history.can_undo() {
+            history.undo(&mut sim).unwrap();
+        }
+        assert_eq!(sim.component_count(), 0);
+        assert_eq!(history.undo(&mut sim), Ok(None));
+        history.redo(&mut sim).unwrap();
+        history.redo(&mut sim).unwrap();
+        history.redo(&mut sim).unwrap();
+        assert_eq!(sim.wires().count(), 1);
+
+        // the redone adds have new ids
+        let wire = sim.wires().next().unwrap();
+        let (not, and) = (wire.parent, wire.child);
+        let mut batch = Batch::new();
+        let delay = batch.add(Delay);
+        batch.wire(not, and, 0, 1);
+        batch.wire(not, delay, 0, 0);
+        batch.wire(delay, not, 0, 0);
+        let ids = history.apply(&mut sim, &batch).unwrap();
+        assert_eq!(sim.wires().count(), 3);
+        history.undo(&mut sim).unwrap();
+        assert!(!sim.contains(ids[delay.index()]));
+        // the wire that existed before the batch stays
+        assert_eq!(sim.wires().count(), 1);
+    }
+
+    #[test]
+    fn test_failed_undo_is_rolled_back() {
+        let mut sim = SimulationEngine::default();
+        let mut history = History::new();
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 4 });
+        let [first, second] = sim.add_array_wired_loop([Not, Not]);
+        history.begin_group();
+        history.remove(&mut sim, second).unwrap();
+        let delay = history.add(&mut sim, Delay).unwrap();
+        history.end_group().unwrap();
+        sim.set_loop_mode(LoopMode::Forbidden);
+        let saved = sim.save();
+
+        // adding `second` back would close the loop again
+        let Err(StepError::Failed { error, changes }) = history.undo(&mut sim) else {
+            panic!("the undo should fail");
+        };
+        assert!(matches!(error, EngineError::CreatesCycle { .. }));
+        assert_eq!(sim.save(), saved);
+        // `delay` was removed and added back by the rollback
+        let [IdChange::Removed(removed), IdChange::Restored { old, new }] = changes[..] else {
+            panic!("{changes:?}");
+        };
+        assert_eq!((removed, old), (delay, delay));
+        assert!(sim.contains(new));
+        assert!(history.can_undo());
+        assert!(!history.can_redo());
+
+        // undoable again once loops are allowed
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 4 });
+        history.undo(&mut sim).unwrap().unwrap();
+        assert!(!sim.contains(new));
+        assert_eq!(sim.wires().count(), 2);
+        assert!(sim.contains(first));
+    }
+
+    #[test]
+    fn test_labels_and_metadata() {
+        let mut sim = SimulationEngine::default();
+        let mut history = History::new();
+        let not = history.add(&mut sim, Not).unwrap();
+        history.set_label(&mut sim, not, Some("first")).unwrap();
+        history.set_metadata(&mut sim, not, "color", "red").unwrap();
+        let saved = sim.save();
+
+        let mut batch = Batch::new();
+        let and = batch.add(And(2));
+        batch.set_label(and, Some("gate"));
+        batch.set_label(not, Some("second"));
+        batch.set_label(not, Some("third"));
+        batch.set_metadata(not, "color", "blue");
+        history.apply(&mut sim, &batch).unwrap();
+        history.remove_metadata(&mut sim, not, "color").unwrap();
+        history.undo(&mut sim).unwrap().unwrap();
+        assert_eq!(sim.component(not).unwrap().metadata()["color"], "blue");
+        history.undo(&mut sim).unwrap().unwrap();
+        assert_eq!(sim.save(), saved);
+
+        // redoing the add brings its label back
+        history.redo(&mut sim).unwrap().unwrap();
+        let and = sim.find_by_label("gate").unwrap();
+        assert_eq!(sim.label(not), Some("third"));
+
+        // the label and metadata stay through a replace, and come back with a removal
+        history
+            .set_metadata(&mut sim, and, "unit", "volts")
+            .unwrap();
+        history.replace_kind(&mut sim, and, Not).unwrap();
+        history.undo(&mut sim).unwrap().unwrap();
+        history.remove(&mut sim, and).unwrap();
+        let changes = history.undo(&mut sim).unwrap().unwrap();
+        let [IdChange::Restored { new: and, .. }] = changes[..] else {
+            panic!("{changes:?}");
+        };
+        assert_eq!(sim.label(and), Some("gate"));
+        assert_eq!(sim.component(and).unwrap().metadata()["unit"], "volts");
+        assert_eq!(sim.component(and).unwrap().kind, And(2));
+
+        history.undo(&mut sim).unwrap().unwrap();
+        history.set_label(&mut sim, and, None).unwrap();
+        history.undo(&mut sim).unwrap().unwrap();
+        assert_eq!(sim.label(and), Some("gate"));
+    }
+
+    #[test]
+    fn test_unbalanced_groups() {
+        let mut sim = SimulationEngine::default();
+        let mut history = History::new();
+        assert_eq!(history.end_group(), Err(StepError::NoGroup));
+
+        history.begin_group();
+        history.add(&mut sim, Not).unwrap();
+        assert_eq!(history.undo(&mut sim), Err(StepError::GroupInProgress));
+        assert_eq!(history.redo(&mut sim), Err(StepError::GroupInProgress));
+        history.end_group().unwrap();
+        assert_eq!(history.end_group(), Err(StepError::NoGroup));
+        history.undo(&mut sim).unwrap().unwrap();
+        assert_eq!(sim.component_count(), 0);
+    }
+}
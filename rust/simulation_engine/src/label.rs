@@ -0,0 +1,171 @@
+use crate::{ComponentId, EngineError, SimulationEngine, recording::Event};
+
+impl SimulationEngine {
+    /// Names a component, labels don't need to be unique. `None` removes the label.
+    pub fn set_label(&mut self, id: ComponentId, label: Option<&str>) -> Result<(), EngineError> {
+        self.component(id)?;
+        self.nodes[id].label = label.map(str::to_owned);
+        self.record(&[id], |numbers| Event::Label {
+            component: numbers[0],
+            label: label.map(str::to_owned),
+        });
+        Ok(())
+    }
+
+    pub fn label(&self, id: ComponentId) -> Option<&str> {
+        self.nodes.get(id)?.label()
+    }
+
+    /// Attaches a value to a component, replacing the one under the same key.
+    pub fn set_metadata(
+        &mut self,
+        id: ComponentId,
+        key: &str,
+        value: &str,
+    ) -> Result<(), EngineError> {
+        self.component(id)?;
+        self.nodes[id]
+            .metadata
+            .insert(key.to_owned(), value.to_owned());
+        self.record(&[id], |numbers| Event::Metadata {
+            component: numbers[0],
+            key: key.to_owned(),
+            value: value.to_owned(),
+        });
+        Ok(())
+    }
+
+    pub fn remove_metadata(&mut self, id: ComponentId, key: &str) -> Option<String> {
+        let value = self.nodes.get_mut(id)?.metadata.remove(key)?;
+        self.record(&[id], |numbers| Event::RemoveMetadata {
+            component: numbers[0],
+            key: key.to_owned(),
+        });
+        Some(value)
+    }
+
+    /// The first component labeled `label`.
+    pub fn find_by_label(&self, label: &str) -> Option<ComponentId> {
+        self.components()
+            .find(|(_, component)| component.label() == Some(label))
+            .map(|(id, _)| id)
+    }
+
+    /// Components whose label is `path` or starts with it followed by `.` or `[`, like the
+    /// components of a module instance named by the HDL.
+    pub fn find_by_path<'a>(&'a self, path: &'a str) -> impl Iterator<Item = ComponentId> + 'a {
+        self.components()
+            .filter(move |(_, component)| {
+                component.label().is_some_and(|label| {
+                    label
+                        .strip_prefix(path)
+                        .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+                })
+            })
+            .map(|(id, _)| id)
+    }
+
+    /// The label of a component in backticks, or its id if it has none.
+    pub fn describe(&self, id: ComponentId) -> String {
+        match self.label(id) {
+            Some(label) => format!("`{label}`"),
+            None => format!("{id:?}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ComponentKind::*, EngineError, SimulationEngine};
+
+    #[test]
+    fn test_labels() {
+        let mut sim = SimulationEngine::default();
+        let [clock, gate, other] = sim.add_array([
+            Clock {
+                period: 2,
+                duty: 1,
+                phase: 0,
+            },
+            Not,
+            Not,
+        ]);
+        sim.set_label(clock, Some("clock")).unwrap();
+        sim.set_label(gate, Some("latch.gate")).unwrap();
+        sim.set_label(other, Some("latches[1].gate")).unwrap();
+        sim.set_metadata(gate, "color", "red").unwrap();
+
+        assert_eq!(sim.find_by_label("latch.gate"), Some(gate));
+        assert_eq!(sim.find_by_label("latch"), None);
+        assert_eq!(sim.find_by_path("latch").collect::<Vec<_>>(), [gate]);
+        assert_eq!(sim.find_by_path("latches").collect::<Vec<_>>(), [other]);
+        assert_eq!(sim.component(gate).unwrap().metadata()["color"], "red");
+        assert_eq!(sim.remove_metadata(gate, "color").as_deref(), Some("red"));
+
+        let error = sim.wire(clock, gate, 0, 1).unwrap_err();
+        assert_eq!(
+            error.describe(&sim),
+            "component `latch.gate` has no input 1"
+        );
+        assert_eq!(sim.describe(other), "`latches[1].gate`");
+
+        sim.set_metadata(clock, "note", "drives the latch").unwrap();
+        let (copy, ids) = SimulationEngine::load(&sim.save()).unwrap();
+        assert_eq!(copy.label(ids[1]), Some("latch.gate"));
+        assert_eq!(
+            copy.component(ids[0]).unwrap().metadata()["note"],
+            "drives the latch"
+        );
+
+        sim.set_label(gate, None).unwrap();
+        assert_eq!(sim.find_by_label("latch.gate"), None);
+        sim.remove(clock).unwrap();
+        assert_eq!(
+            sim.set_label(clock, Some("gone")),
+            Err(EngineError::StaleComponent(clock))
+        );
+    }
+
+    #[test]
+    fn test_save_quotes_labels_and_metadata() {
+        let mut sim = SimulationEngine::default();
+        let [first, second] = sim.add_array_of(Not);
+        sim.set_label(first, Some("  line\nbreak \"quoted\" \\ "))
+            .unwrap();
+        sim.set_label(second, Some("")).unwrap();
+        sim.set_metadata(first, "empty", "").unwrap();
+        sim.set_metadata(first, "key with spaces", " value\r\n")
+            .unwrap();
+
+        let saved = sim.save();
+        assert_eq!(
+            saved
+                .lines()
+                .filter(|line| line.starts_with("label"))
+                .count(),
+            2
+        );
+        let (copy, ids) = SimulationEngine::load(&saved).unwrap();
+        assert_eq!(copy.save(), saved);
+        assert_eq!(copy.label(ids[0]), sim.label(first));
+        assert_eq!(copy.label(ids[1]), Some(""));
+        assert_eq!(
+            copy.component(ids[0]).unwrap().metadata(),
+            sim.component(first).unwrap().metadata()
+        );
+
+        // unquoted, as written before
+        let text = "firestone-circuit 1\ncomponent not\nlabel 0 a b\nmeta 0 key some value";
+        let (old, ids) = SimulationEngine::load(text).unwrap();
+        assert_eq!(old.label(ids[0]), Some("a b"));
+        assert_eq!(
+            old.component(ids[0]).unwrap().metadata()["key"],
+            "some value"
+        );
+
+        let error = SimulationEngine::load("firestone-circuit 1\ncomponent not\nlabel 0 \"a")
+            .err()
+            .unwrap();
+        assert_eq!(error.line, 3);
+    }
+}
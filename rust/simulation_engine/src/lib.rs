@@ -1,21 +1,59 @@
 #![feature(array_windows)]
 #![allow(irrefutable_let_patterns)]
 
+mod activity;
+mod arena;
+mod batch;
+mod breakpoint;
 mod component;
+mod custom;
+mod dag;
+pub mod dot;
+mod error;
+pub mod fault;
+mod graph;
+pub mod hdl;
+mod history;
+mod label;
+mod logic;
+mod loops;
+pub mod lut;
+mod observer;
+pub mod recording;
+mod save;
+pub mod stdlib;
+pub mod testbench;
+mod timing;
 
 use std::{
     array,
-    collections::{BTreeMap, BTreeSet},
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    mem,
+    ops::Range,
 };
 
-use component::{Component, ComponentIdGenerator};
-pub use component::{ComponentId, ComponentKind};
-use petgraph::{
-    acyclic::Acyclic,
-    data::Build,
-    prelude::{Directed, Direction::Outgoing, GraphMap},
-};
-use rustc_hash::FxHashMap;
+use activity::Activity;
+pub use activity::{ActivityReport, Toggles};
+use arena::Arena;
+pub use batch::{Batch, BatchError, Edit, Pending, Target};
+pub use breakpoint::Breakpoint;
+pub use component::{Component, ComponentId, ComponentKind, ParseKindError, Wire};
+use custom::Custom;
+pub use custom::{CustomComponent, CustomId};
+use dag::Dag;
+pub use error::EngineError;
+use fault::Fault;
+use fixedbitset::FixedBitSet;
+pub use graph::{InputPort, OutputPort};
+pub use history::{History, IdChange, StepError};
+pub use logic::{Logic, LogicMode};
+pub use loops::LoopMode;
+pub use lut::{TableId, TruthTable};
+pub use observer::{Observer, ObserverId};
+use recording::{Event, Recorder};
+use rustc_hash::{FxHashMap, FxHashSet};
+pub use save::LoadError;
+pub use timing::TimingMode;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 struct Edge {
@@ -27,12 +65,43 @@ struct Edge {
 
 #[derive(Default)]
 pub struct SimulationEngine {
-    nodes: FxHashMap<ComponentId, Component>,
-    incoming_edges: FxHashMap<ComponentId, BTreeMap<ComponentId, BTreeSet<Edge>>>,
-    outgoing_edges: FxHashMap<ComponentId, BTreeMap<ComponentId, BTreeSet<Edge>>>,
-    tickless_dag: Acyclic<GraphMap<ComponentId, (), Directed>>,
-    id_gen: ComponentIdGenerator,
+    nodes: Arena<Component>,
+    /// Edges between components that aren't delays.
+    tickless_dag: Dag,
+    /// Edges that would close a cycle in `tickless_dag`, with `LoopMode::FixedPoint`.
+    feedback: FxHashSet<(u32, u32)>,
+    loop_mode: LoopMode,
+    /// Outputs of all components, each component owns a range starting at its `first_signal`.
+    signals: FixedBitSet,
+    /// Ranges of signals no component owns, from their start to their end, reused by new ones.
+    free_signals: BTreeMap<usize, usize>,
+    /// Signals that are `X` (if unset in `signals`) or `Z` (if set), only tri-states and buses
+    /// set them in `LogicMode::TwoValued`.
+    unknown: FixedBitSet,
+    logic_mode: LogicMode,
+    timing_mode: TimingMode,
+    /// Values computed with `TimingMode::UnitDelay` that haven't reached the outputs yet,
+    /// oldest first.
+    in_flight: FxHashMap<ComponentId, VecDeque<Vec<Logic>>>,
+    /// Arena slots already evaluated in the current wave.
+    evaluated: FixedBitSet,
     current_tick: u64,
+    changes: Vec<(ComponentId, usize)>,
+    /// Buses whose enabled drivers disagreed in the last tick.
+    contentions: Vec<ComponentId>,
+    /// Components still changing when the last tick ran out of passes.
+    unstable: Vec<ComponentId>,
+    driven: FxHashMap<(ComponentId, usize), bool>,
+    /// Truth tables of the `Lut` components, indexed by `TableId`.
+    tables: Vec<TruthTable>,
+    /// Instances of the `Custom` components, indexed by `CustomId`, `None` once removed.
+    customs: Vec<Option<Custom>>,
+    faults: Vec<Fault>,
+    activity: Option<Activity>,
+    recorder: Option<Box<Recorder>>,
+    observers: FxHashMap<ObserverId, Box<dyn Observer>>,
+    subscriptions: FxHashMap<ComponentId, Vec<ObserverId>>,
+    next_observer_id: ObserverId,
 }
 
 impl SimulationEngine {
@@ -44,17 +113,287 @@ impl SimulationEngine {
         self.current_tick
     }
 
+    /// Panics if the kind is invalid, see [`Self::try_add`].
     pub fn add(&mut self, kind: ComponentKind) -> ComponentId {
-        let component_id = self.id_gen.next_id();
-        if !kind.is_delay() {
-            self.tickless_dag.add_node(component_id);
-        }
-        self.nodes.insert(component_id, Component::new(kind));
+        self.try_add(kind).unwrap_or_else(|error| panic!("{error}"))
+    }
+
+    /// Fails for clocks with a period of zero, lookup tables without their table, and custom
+    /// components, which are added with [`Self::add_custom`].
+    pub fn try_add(&mut self, kind: ComponentKind) -> Result<ComponentId, EngineError> {
+        self.check_kind(kind)?;
+        Ok(self.insert(kind))
+    }
+
+    /// Adds a component whose kind is valid.
+    fn insert(&mut self, kind: ComponentKind) -> ComponentId {
+        let first_signal = self.allocate_signals(kind.arity().1);
+        let component_id = self.nodes.insert(Component::new(kind, first_signal));
+        self.evaluated.grow(self.nodes.slot_count());
+        self.tickless_dag.grow(self.nodes.slot_count());
+        self.record_add(component_id, kind);
         component_id
     }
 
-    pub fn components(&self) -> &FxHashMap<ComponentId, Component> {
-        &self.nodes
+    fn check_kind(&self, kind: ComponentKind) -> Result<(), EngineError> {
+        match kind {
+            ComponentKind::Clock { period: 0, .. } => Err(EngineError::ZeroClockPeriod),
+            ComponentKind::Lut {
+                inputs,
+                outputs,
+                table,
+            } => match self.table(table) {
+                Some(stored) if (stored.inputs(), stored.outputs()) == (inputs, outputs) => Ok(()),
+                _ => Err(EngineError::InvalidTable(table)),
+            },
+            ComponentKind::Custom { id, .. } => Err(EngineError::CustomKind(id)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the first of `outputs` new signals, reusing freed ones when they fit.
+    fn allocate_signals(&mut self, outputs: usize) -> usize {
+        let free = self
+            .free_signals
+            .iter()
+            .map(|(&start, &end)| (start, end))
+            .find(|&(start, end)| end - start >= outputs);
+        let first_signal = match free {
+            Some((start, end)) => {
+                self.free_signals.remove(&start);
+                if start + outputs < end {
+                    self.free_signals.insert(start + outputs, end);
+                }
+                start
+            }
+            None => {
+                let first_signal = self.signals.len();
+                self.signals.grow(first_signal + outputs);
+                self.unknown.grow(first_signal + outputs);
+                first_signal
+            }
+        };
+        let signals = first_signal..first_signal + outputs;
+        // `X` as uninitialized
+        let initial = match self.logic_mode {
+            LogicMode::TwoValued => Logic::Zero,
+            LogicMode::FourValued => Logic::X,
+        };
+        for signal in signals.clone() {
+            self.set_signal(signal, initial);
+        }
+        if let Some(activity) = &mut self.activity {
+            activity.clear(signals);
+        }
+        first_signal
+    }
+
+    /// Makes signals available again, merging them with the free ones around.
+    fn free_signals(&mut self, signals: Range<usize>) {
+        if signals.is_empty() {
+            return;
+        }
+        let (mut start, mut end) = (signals.start, signals.end);
+        if let Some(after) = self.free_signals.remove(&end) {
+            end = after;
+        }
+        let before = self.free_signals.range(..start).next_back();
+        if let Some((&before, _)) = before.filter(|&(_, &before_end)| before_end == start) {
+            self.free_signals.remove(&before);
+            start = before;
+        }
+        self.free_signals.insert(start, end);
+    }
+
+    /// Removes a component and all of its wires.
+    pub fn remove(&mut self, id: ComponentId) -> Result<ComponentKind, EngineError> {
+        let component = self
+            .nodes
+            .remove(id)
+            .ok_or(EngineError::StaleComponent(id))?;
+
+        for parent in component.incoming.keys() {
+            if let Some(parent) = self.nodes.get_mut(*parent) {
+                parent.outgoing.remove(&id);
+            }
+        }
+        for child in component.outgoing.keys() {
+            if let Some(child) = self.nodes.get_mut(*child) {
+                child.incoming.remove(&id);
+            }
+        }
+
+        let dag_edges: Vec<(u32, u32)> = self.dag_edges_of(id.index()).collect();
+        self.remove_dag_edges(&dag_edges);
+        self.feedback
+            .retain(|&(parent, child)| parent != id.index() && child != id.index());
+
+        self.driven.retain(|&(driven_id, _), _| driven_id != id);
+        self.faults.retain(|fault| fault.target().0 != id);
+        self.changes.retain(|&(changed_id, _)| changed_id != id);
+        self.subscriptions.remove(&id);
+        self.in_flight.remove(&id);
+        if let ComponentKind::Custom { id: custom_id, .. } = component.kind {
+            self.customs[custom_id.index()] = None;
+        }
+        self.free_signals(
+            component.first_signal..component.first_signal + component.kind.arity().1,
+        );
+        self.record(&[id], |numbers| Event::Remove(numbers[0]));
+        Ok(component.kind)
+    }
+
+    /// Changes the kind of a component, keeping the wires that fit its new ports and the values
+    /// of the outputs it keeps.
+    ///
+    /// Returns the wires that were dropped. Fails without changing anything if the component
+    /// stops being a delay and its wires would then form a cycle without delays.
+    pub fn replace_kind(
+        &mut self,
+        id: ComponentId,
+        kind: ComponentKind,
+    ) -> Result<Vec<Wire>, EngineError> {
+        let old_kind = self.component(id)?.kind;
+        self.check_kind(kind)?;
+        let (inputs, outputs) = kind.arity();
+        let old_outputs = old_kind.arity().1;
+        // read while the component still has its old arity, added outputs start uninitialized
+        let old_values: Vec<Logic> = (0..old_outputs.min(outputs))
+            .map(|output| self.logic_at(id, output))
+            .collect();
+
+        let wires: BTreeSet<Wire> = self
+            .incoming_to(id)
+            .map(|(parent, edge)| Wire::new(parent, id, edge))
+            .chain(
+                self.outgoing_from(id)
+                    .map(|(child, edge)| Wire::new(id, child, edge)),
+            )
+            .collect();
+        let (kept, dropped): (Vec<Wire>, Vec<Wire>) = wires.into_iter().partition(|wire| {
+            (wire.parent != id || wire.parent_output < outputs)
+                && (wire.child != id || wire.child_input < inputs)
+        });
+
+        let kind_of = |other: ComponentId| match other == id {
+            true => kind,
+            false => self.nodes[other].kind,
+        };
+        let edges: Vec<(Wire, Edge)> = kept
+            .iter()
+            .map(|&wire| {
+                let edge = Edge {
+                    parent_kind: kind_of(wire.parent),
+                    child_kind: kind_of(wire.child),
+                    parent_output: wire.parent_output,
+                    child_input: wire.child_input,
+                };
+                (wire, edge)
+            })
+            .collect();
+        let dag_edges: BTreeSet<(u32, u32)> = edges
+            .iter()
+            .filter(|(_, edge)| !edge.parent_kind.is_delay() && !edge.child_kind.is_delay())
+            .map(|(wire, _)| (wire.parent.index(), wire.child.index()))
+            .collect();
+        if !old_kind.is_delay() {
+            let old_edges: Vec<(u32, u32)> = self.dag_edges_of(id.index()).collect();
+            self.remove_dag_edges(&old_edges);
+        }
+        // loops aren't forbidden if there are any, so this can't be rolled back
+        self.feedback
+            .retain(|&(parent, child)| parent != id.index() && child != id.index());
+        for (added, &(parent, child)) in dag_edges.iter().enumerate() {
+            if !self.tickless_dag.try_add_edge(parent, child) {
+                if self.loop_mode != LoopMode::Forbidden {
+                    self.feedback.insert((parent, child));
+                    continue;
+                }
+                let added: Vec<(u32, u32)> = dag_edges.iter().take(added).copied().collect();
+                self.remove_dag_edges(&added);
+                let wire = kept
+                    .iter()
+                    .find(|wire| (wire.parent.index(), wire.child.index()) == (parent, child))
+                    .unwrap();
+                return Err(EngineError::CreatesCycle {
+                    parent: wire.parent,
+                    child: wire.child,
+                });
+            }
+        }
+
+        // every wire touching the component stores its kind, so they're all rewired
+        let neighbors: BTreeSet<ComponentId> = (self.nodes[id].incoming.keys())
+            .chain(self.nodes[id].outgoing.keys())
+            .copied()
+            .collect();
+        for neighbor in neighbors {
+            self.nodes[neighbor].incoming.remove(&id);
+            self.nodes[neighbor].outgoing.remove(&id);
+        }
+        let component = &mut self.nodes[id];
+        component.incoming.clear();
+        component.outgoing.clear();
+        component.kind = kind;
+        component.rising_edge_tick = None;
+        for (wire, edge) in edges {
+            self.nodes[wire.parent]
+                .outgoing
+                .entry(wire.child)
+                .or_default()
+                .insert(edge);
+            self.nodes[wire.child]
+                .incoming
+                .entry(wire.parent)
+                .or_default()
+                .insert(edge);
+        }
+
+        let old_first_signal = self.nodes[id].first_signal;
+        if outputs > old_outputs {
+            let first_signal = self.allocate_signals(outputs);
+            for (output, value) in old_values.into_iter().enumerate() {
+                self.set_signal(first_signal + output, value);
+            }
+            self.nodes[id].first_signal = first_signal;
+            self.free_signals(old_first_signal..old_first_signal + old_outputs);
+        } else {
+            self.free_signals(old_first_signal + outputs..old_first_signal + old_outputs);
+        }
+        self.driven
+            .retain(|&(driven_id, output), _| driven_id != id || output < outputs);
+        self.faults.retain(|fault| {
+            let (fault_id, output) = fault.target();
+            fault_id != id || output < outputs
+        });
+        self.changes
+            .retain(|&(changed_id, output)| changed_id != id || output < outputs);
+        self.in_flight.remove(&id);
+        if let ComponentKind::Custom { id: custom_id, .. } = old_kind {
+            self.customs[custom_id.index()] = None;
+        }
+
+        self.record(&[id], |numbers| Event::Replace {
+            component: numbers[0],
+            kind,
+        });
+        Ok(dropped)
+    }
+
+    pub fn contains(&self, id: ComponentId) -> bool {
+        self.nodes.contains(id)
+    }
+
+    pub fn component(&self, id: ComponentId) -> Result<&Component, EngineError> {
+        self.nodes.get(id).ok_or(EngineError::StaleComponent(id))
+    }
+
+    pub fn components(&self) -> impl Iterator<Item = (ComponentId, &Component)> {
+        self.nodes.iter()
+    }
+
+    pub fn component_count(&self) -> usize {
+        self.nodes.len()
     }
 
     pub fn add_array<const N: usize>(&mut self, array: [ComponentKind; N]) -> [ComponentId; N] {
@@ -109,26 +448,45 @@ impl SimulationEngine {
         components
     }
 
+    /// Wires the first output of `parent` to the first input of `child`, panics on failure.
     pub fn wire0(&mut self, parent: ComponentId, child: ComponentId) {
-        self.wire(parent, child, 0, 0);
+        self.wire(parent, child, 0, 0)
+            .unwrap_or_else(|error| panic!("{error}"));
     }
 
-    /// Returns whether is succeeded
     pub fn wire(
         &mut self,
         parent: ComponentId,
         child: ComponentId,
         parent_output: usize,
         child_input: usize,
-    ) -> bool {
-        let parent_kind = self.nodes.get(&parent).expect("unexpected parent").kind;
-        let child_kind = self.nodes.get(&child).expect("unexpected parent").kind;
-
-        if !parent_kind.is_delay() && !child_kind.is_delay() {
-            let result = self.tickless_dag.try_update_edge(parent, child, ());
-            if result.is_err() {
-                return false;
+    ) -> Result<(), EngineError> {
+        let parent_kind = self.component(parent)?.kind;
+        let child_kind = self.component(child)?.kind;
+
+        if parent_output >= parent_kind.arity().1 {
+            return Err(EngineError::OutputOutOfBounds {
+                id: parent,
+                output: parent_output,
+            });
+        }
+        if child_input >= child_kind.arity().0 {
+            return Err(EngineError::InputOutOfBounds {
+                id: child,
+                input: child_input,
+            });
+        }
+
+        if !parent_kind.is_delay()
+            && !child_kind.is_delay()
+            && !self
+                .tickless_dag
+                .try_add_edge(parent.index(), child.index())
+        {
+            if self.loop_mode == LoopMode::Forbidden {
+                return Err(EngineError::CreatesCycle { parent, child });
             }
+            self.feedback.insert((parent.index(), child.index()));
         }
 
         let edge = Edge {
@@ -138,106 +496,528 @@ impl SimulationEngine {
             child_input,
         };
 
-        self.outgoing_edges
-            .entry(parent)
-            .or_default()
+        self.nodes[parent]
+            .outgoing
             .entry(child)
             .or_default()
             .insert(edge);
-        self.incoming_edges
-            .entry(child)
-            .or_default()
+        self.nodes[child]
+            .incoming
             .entry(parent)
             .or_default()
             .insert(edge);
-        true
+        self.record(&[parent, child], |numbers| Event::Wire {
+            parent: numbers[0],
+            child: numbers[1],
+            parent_output,
+            child_input,
+        });
+        Ok(())
+    }
+
+    /// Removes a wire, returns whether it existed.
+    pub fn unwire(
+        &mut self,
+        parent: ComponentId,
+        child: ComponentId,
+        parent_output: usize,
+        child_input: usize,
+    ) -> Result<bool, EngineError> {
+        self.component(parent)?;
+        self.component(child)?;
+        let Some(edges) = self.nodes[parent].outgoing.get_mut(&child) else {
+            return Ok(false);
+        };
+        let Some(&edge) = edges
+            .iter()
+            .find(|edge| edge.parent_output == parent_output && edge.child_input == child_input)
+        else {
+            return Ok(false);
+        };
+        edges.remove(&edge);
+        let last = edges.is_empty();
+        if last {
+            self.nodes[parent].outgoing.remove(&child);
+            self.nodes[child].incoming.remove(&parent);
+        } else {
+            let incoming = self.nodes[child].incoming.get_mut(&parent).unwrap();
+            incoming.remove(&edge);
+        }
+        // the DAG has a single edge for all the wires between two components
+        if last && !edge.parent_kind.is_delay() && !edge.child_kind.is_delay() {
+            let pair = (parent.index(), child.index());
+            if !self.feedback.remove(&pair) {
+                self.remove_dag_edges(&[pair]);
+            }
+        }
+        self.record(&[parent, child], |numbers| Event::Unwire {
+            parent: numbers[0],
+            child: numbers[1],
+            parent_output,
+            child_input,
+        });
+        Ok(true)
+    }
+
+    fn dag_edges_of(&self, index: u32) -> impl Iterator<Item = (u32, u32)> {
+        self.tickless_dag
+            .children(index)
+            .map(move |child| (index, child))
+            .chain(
+                self.tickless_dag
+                    .parents(index)
+                    .map(move |parent| (parent, index)),
+            )
+    }
+
+    fn remove_dag_edges(&mut self, edges: &[(u32, u32)]) {
+        for &(parent, child) in edges {
+            self.tickless_dag.remove_edge(parent, child);
+        }
+    }
+
+    /// Outputs, as `(component, output)`, whose value changed in the last tick.
+    pub fn last_changes(&self) -> &[(ComponentId, usize)] {
+        &self.changes
+    }
+
+    /// Buses that had enabled drivers disagreeing in the last tick, their output is `X`.
+    pub fn contentions(&self) -> &[ComponentId] {
+        &self.contentions
+    }
+
+    /// Calls `observer` whenever an output of one of `components` changes.
+    pub fn subscribe(
+        &mut self,
+        components: impl IntoIterator<Item = ComponentId>,
+        observer: impl Observer + 'static,
+    ) -> ObserverId {
+        let observer_id = self.next_observer_id.next();
+        for component_id in components {
+            self.subscriptions
+                .entry(component_id)
+                .or_default()
+                .push(observer_id);
+        }
+        self.observers.insert(observer_id, Box::new(observer));
+        observer_id
+    }
+
+    pub fn unsubscribe(&mut self, observer_id: ObserverId) {
+        self.observers.remove(&observer_id);
+        self.subscriptions.retain(|_, observers| {
+            observers.retain(|&id| id != observer_id);
+            !observers.is_empty()
+        });
     }
 
     pub fn run_step(&mut self) {
+        let leaves = self.subgraph_leaves().collect::<Vec<ComponentId>>();
+        let delays = self.delays().collect::<Vec<ComponentId>>();
+        self.step(&leaves, &delays);
+    }
+
+    /// Runs `n` ticks, `last_changes` only reports the ones of the last tick.
+    pub fn run_steps(&mut self, n: u64) {
+        let leaves = self.subgraph_leaves().collect::<Vec<ComponentId>>();
+        let delays = self.delays().collect::<Vec<ComponentId>>();
+        for _ in 0..n {
+            self.step(&leaves, &delays);
+        }
+    }
+
+    /// Runs up to `max_ticks` ticks, stopping at the first tick after which `predicate` holds.
+    ///
+    /// Returns the tick it stopped at, or `None` if `predicate` never held.
+    pub fn run_until(
+        &mut self,
+        max_ticks: u64,
+        mut predicate: impl FnMut(&Self) -> bool,
+    ) -> Option<u64> {
+        let leaves = self.subgraph_leaves().collect::<Vec<ComponentId>>();
+        let delays = self.delays().collect::<Vec<ComponentId>>();
+        for _ in 0..max_ticks {
+            self.step(&leaves, &delays);
+            if predicate(self) {
+                return Some(self.current_tick);
+            }
+        }
+        None
+    }
+
+    /// Runs up to `max_ticks` ticks, stopping at the first tick in which a breakpoint is hit.
+    ///
+    /// Returns the index of the first breakpoint hit, or `None` if none was.
+    pub fn run_until_breakpoint(
+        &mut self,
+        breakpoints: &[Breakpoint],
+        max_ticks: u64,
+    ) -> Option<usize> {
+        let mut hit = None;
+        self.run_until(max_ticks, |engine| {
+            hit = breakpoints
+                .iter()
+                .position(|breakpoint| breakpoint.is_hit(engine));
+            hit.is_some()
+        });
+        hit
+    }
+
+    /// Reads the outputs, least significant bit first, as a number, of at most 64 bits.
+    pub fn bus_value(&self, bits: &[(ComponentId, usize)]) -> Result<u64, EngineError> {
+        if bits.len() > u64::BITS as usize {
+            return Err(EngineError::BusTooWide { width: bits.len() });
+        }
+        bits.iter()
+            .enumerate()
+            .try_fold(0, |value, (bit, &(id, output))| {
+                let on = self.try_logic_at(id, output)? == Logic::One;
+                Ok(value | (on as u64) << bit)
+            })
+    }
+
+    fn step(&mut self, leaves: &[ComponentId], delays: &[ComponentId]) {
+        let gate_delay = match self.timing_mode {
+            TimingMode::ZeroDelay => None,
+            TimingMode::UnitDelay { ticks } => Some(ticks),
+        };
+        // passes over loops can change a signal back and forth, so only the settled values are
+        // compared with the ones before, and counted as activity
+        let before = (self.has_loops() && gate_delay.is_none())
+            .then(|| (self.signals.clone(), self.unknown.clone()));
+        let activity = before.as_ref().and_then(|_| self.activity.take());
         self.current_tick += 1;
-        let version = self.current_tick;
+        self.changes.clear();
+        self.contentions.clear();
+        self.evaluated.clear();
+
+        // with gate delays, every component reads the values of the tick before
+        let gate_values = gate_delay.map(|ticks| (self.evaluate_gates(), ticks));
 
         // tick it, propagate all the delay states
         if let mut edits = vec![] {
-            for (&delay_id, node) in self.nodes.iter() {
-                if !node.kind.is_delay() {
-                    continue;
-                }
-
+            for &delay_id in delays {
+                // without a parent, a delay keeps its last value
+                let mut value = self.logic_at(delay_id, 0);
                 for (parent_id, edge) in self.incoming_to(delay_id) {
-                    edits.push((
-                        delay_id,
-                        edge.child_input,
-                        self.nodes[&parent_id].state.values[edge.parent_output],
-                    ));
+                    value = self.logic_at(parent_id, edge.parent_output);
                 }
+                edits.push((delay_id, self.apply_overrides(delay_id, 0, value)));
             }
 
-            for (delay_id, child_input, parent_output_value) in edits {
-                self.nodes.get_mut(&delay_id).unwrap().state.values[child_input] =
-                    parent_output_value;
+            for (delay_id, value) in edits {
+                self.update_output(delay_id, 0, value);
             }
         }
 
-        // for all leaves, eval with recursion
-        if let leaves = self.subgraph_leaves().collect::<Vec<ComponentId>>() {
-            for leaf in leaves {
-                assert!(!self.nodes[&leaf].kind.is_delay());
-                self.recursive_eval_and_update(leaf, version);
+        if let Some((values, ticks)) = gate_values {
+            self.propagate_gates(values, ticks);
+        } else {
+            // for all leaves, eval with recursion
+            self.settle(leaves);
+        }
+        if let Some((signals, unknown)) = before {
+            let mut seen = FxHashSet::default();
+            let changes = mem::take(&mut self.changes);
+            self.changes = changes
+                .into_iter()
+                .filter(|&(id, output)| {
+                    let signal = self.nodes[id].first_signal + output;
+                    seen.insert((id, output))
+                        && (self.signals[signal] != signals[signal]
+                            || self.unknown[signal] != unknown[signal])
+                })
+                .collect();
+            if let Some(mut activity) = activity {
+                for &(id, output) in &self.changes {
+                    let signal = self.nodes[id].first_signal + output;
+                    let was_on = signals[signal] && !unknown[signal];
+                    let is_on = self.signals[signal] && !self.unknown[signal];
+                    activity.record_change(signal, was_on, is_on);
+                }
+                self.activity = Some(activity);
+            }
+        }
+        self.update_customs();
+
+        self.record_activity_tick();
+        self.record_tick();
+        self.notify_observers();
+    }
+
+    fn notify_observers(&mut self) {
+        for &(id, output) in &self.changes {
+            let value = self.signal(id, output);
+            for observer_id in self.subscriptions.get(&id).into_iter().flatten() {
+                if let Some(observer) = self.observers.get_mut(observer_id) {
+                    observer.on_change(self.current_tick, id, output, value);
+                }
             }
         }
     }
 
-    fn recursive_eval_and_update(&mut self, id: ComponentId, version: u64) -> Vec<bool> {
-        let component = &self.nodes[&id];
-        let component_kind = component.kind;
+    fn recursive_eval_and_update(&mut self, id: ComponentId) {
+        let slot = id.index() as usize;
+        if self.evaluated.put(slot) {
+            return;
+        }
+        let incoming_edges: Vec<(ComponentId, Edge)> = self.incoming_to(id).collect();
+        for &(parent_id, edge) in &incoming_edges {
+            if !edge.parent_kind.is_delay() {
+                self.recursive_eval_and_update(parent_id);
+            }
+        }
 
-        let values = if component.state.version == version {
-            component.state.values.clone()
+        let mut values = self.evaluate(id, incoming_edges);
+        if !self.driven.is_empty() || !self.faults.is_empty() {
+            for (output, value) in values.iter_mut().enumerate() {
+                *value = self.apply_overrides(id, output, *value);
+            }
+        }
+
+        for (output, value) in values.into_iter().enumerate() {
+            self.update_output(id, output, value);
+        }
+    }
+
+    /// The outputs of a component for the current values of its parents.
+    fn evaluate(
+        &mut self,
+        id: ComponentId,
+        incoming_edges: Vec<(ComponentId, Edge)>,
+    ) -> Vec<Logic> {
+        let component_kind = self.nodes[id].kind;
+
+        // tri-states and buses carry `Z` in both modes
+        let is_two_valued = self.logic_mode == LogicMode::TwoValued
+            && !matches!(
+                component_kind,
+                ComponentKind::TriState | ComponentKind::Bus(_)
+            );
+
+        if is_two_valued {
+            let mut inputs = vec![false; component_kind.arity().0];
+            for (parent_id, edge) in incoming_edges {
+                inputs[edge.child_input] |= self.signal(parent_id, edge.parent_output);
+            }
+            self.eval_two_valued(id, inputs)
+                .into_iter()
+                .map(Logic::from)
+                .collect()
         } else {
-            let inputs = {
-                let mut inputs = vec![false; component_kind.arity().0];
-                let incoming_edges: Vec<(ComponentId, Edge)> = self.incoming_to(id).collect();
-                for (parent_id, edge) in incoming_edges {
-                    let parent_component = &self.nodes[&parent_id];
-
-                    let parent_eval = match parent_component.kind {
-                        ComponentKind::Delay => parent_component.state.values[edge.parent_output],
-                        _ => self.recursive_eval_and_update(parent_id, version)[edge.parent_output],
-                    };
-                    inputs[edge.child_input] |= parent_eval;
-                }
-                inputs
+            let unconnected = match (component_kind, self.logic_mode) {
+                (ComponentKind::Bus(_), _) => Logic::Z,
+                (_, LogicMode::TwoValued) => Logic::Zero,
+                (_, LogicMode::FourValued) => Logic::X,
             };
+            // `None` while unconnected
+            let mut inputs = vec![None; component_kind.arity().0];
+            for (parent_id, edge) in incoming_edges {
+                let value = self.logic_at(parent_id, edge.parent_output);
+                let input = &mut inputs[edge.child_input];
+                *input = Some(input.map_or(value, |input: Logic| input.wired_or(value)));
+            }
+            let inputs = inputs
+                .into_iter()
+                .map(|input| input.unwrap_or(unconnected))
+                .collect();
+            self.eval_four_valued(id, inputs)
+        }
+    }
 
-            match component_kind {
-                ComponentKind::Not => vec![inputs.into_iter().all(|x| !x)],
-                ComponentKind::And(_) => vec![inputs.into_iter().all(|x| x)],
-                ComponentKind::HalfAdder => {
-                    let sum = inputs[0] as u8 + inputs[1] as u8;
-                    vec![sum & 1 != 0, sum & 2 != 0]
+    fn eval_two_valued(&mut self, id: ComponentId, inputs: Vec<bool>) -> Vec<bool> {
+        match self.nodes[id].kind {
+            kind @ (ComponentKind::Not
+            | ComponentKind::And(_)
+            | ComponentKind::HalfAdder
+            | ComponentKind::FullAdder
+            | ComponentKind::Lut { .. }) => self.eval_combinational(kind, &inputs),
+            ComponentKind::Custom { id, .. } => self.eval_custom(id, &inputs),
+            ComponentKind::Clock {
+                period,
+                duty,
+                phase,
+            } => {
+                // widened, as the phase of a kind built in code can be anything
+                let shifted = (self.current_tick as u128 + phase as u128) % period as u128;
+                vec![shifted < duty as u128]
+            }
+            ComponentKind::Pulse => {
+                let node = &mut self.nodes[id];
+                if !inputs[0] {
+                    node.rising_edge_tick = None;
+                } else if node.rising_edge_tick.is_none() {
+                    node.rising_edge_tick = Some(self.current_tick);
                 }
-                ComponentKind::FullAdder => {
-                    let sum = inputs[0] as u8 + inputs[1] as u8 + inputs[2] as u8;
-                    vec![sum & 1 != 0, sum & 2 != 0]
+                vec![node.rising_edge_tick == Some(self.current_tick)]
+            }
+            ComponentKind::Delay | ComponentKind::TriState | ComponentKind::Bus(_) => {
+                unreachable!()
+            }
+        }
+    }
+
+    /// Evaluates components whose outputs only depend on their inputs.
+    fn eval_combinational(&self, kind: ComponentKind, inputs: &[bool]) -> Vec<bool> {
+        match kind {
+            ComponentKind::Not => vec![inputs.iter().all(|&x| !x)],
+            ComponentKind::And(_) => vec![inputs.iter().all(|&x| x)],
+            ComponentKind::HalfAdder => {
+                let sum = inputs[0] as u8 + inputs[1] as u8;
+                vec![sum & 1 != 0, sum & 2 != 0]
+            }
+            ComponentKind::FullAdder => {
+                let sum = inputs[0] as u8 + inputs[1] as u8 + inputs[2] as u8;
+                vec![sum & 1 != 0, sum & 2 != 0]
+            }
+            ComponentKind::Lut { table, .. } => self.tables[table.index()].eval(inputs),
+            _ => unreachable!("{kind} isn't combinational"),
+        }
+    }
+
+    /// Unknown inputs make the outputs unknown, unless the known ones decide them.
+    fn eval_four_valued(&mut self, id: ComponentId, inputs: Vec<Logic>) -> Vec<Logic> {
+        let kind = self.nodes[id].kind;
+        match kind {
+            ComponentKind::Not => vec![inputs[0].not()],
+            ComponentKind::And(_) => vec![Logic::and(inputs)],
+            ComponentKind::HalfAdder
+            | ComponentKind::FullAdder
+            | ComponentKind::Pulse
+            | ComponentKind::Custom { .. } => {
+                match inputs.iter().map(|input| input.to_bool()).collect() {
+                    Some(inputs) => self
+                        .eval_two_valued(id, inputs)
+                        .into_iter()
+                        .map(Logic::from)
+                        .collect(),
+                    None => vec![Logic::X; kind.arity().1],
                 }
-                ComponentKind::Delay => unreachable!(),
             }
+            ComponentKind::Lut { table, .. } => self.tables[table.index()].eval_logic(&inputs),
+            ComponentKind::Clock { .. } => self
+                .eval_two_valued(id, vec![])
+                .into_iter()
+                .map(Logic::from)
+                .collect(),
+            ComponentKind::TriState => match inputs[1] {
+                Logic::Zero => vec![Logic::Z],
+                Logic::One if inputs[0].is_known() => vec![inputs[0]],
+                _ => vec![Logic::X],
+            },
+            ComponentKind::Bus(_) => {
+                let mut value = Logic::Z;
+                for input in inputs {
+                    value = match (value, input) {
+                        (value, Logic::Z) => value,
+                        (Logic::Z, input) => input,
+                        (Logic::Zero, Logic::One) | (Logic::One, Logic::Zero) => {
+                            self.contentions.push(id);
+                            Logic::X
+                        }
+                        (Logic::X, _) | (_, Logic::X) => Logic::X,
+                        (value, _) => value,
+                    };
+                }
+                vec![value]
+            }
+            ComponentKind::Delay => unreachable!(),
+        }
+    }
+
+    /// Drives, and then faults, take precedence over the evaluated value.
+    fn apply_overrides(&self, id: ComponentId, output: usize, value: Logic) -> Logic {
+        let mut value = match self.driven.get(&(id, output)) {
+            Some(&driven) => Logic::from(driven),
+            None => value,
         };
+        for fault in &self.faults {
+            value = fault.apply(id, output, self.current_tick, value);
+        }
+        value
+    }
+
+    fn update_output(&mut self, id: ComponentId, output: usize, value: Logic) {
+        let signal = self.nodes[id].first_signal + output;
+        let was_on = self.signals[signal] && !self.unknown[signal];
+        if self.set_signal(signal, value) {
+            self.changes.push((id, output));
+            if let Some(activity) = &mut self.activity {
+                activity.record_change(signal, was_on, value == Logic::One);
+            }
+        }
+    }
 
-        self.nodes.get_mut(&id).unwrap().state = State {
-            values: values.clone(),
-            version,
+    /// Returns whether the signal changed.
+    fn set_signal(&mut self, signal: usize, value: Logic) -> bool {
+        let (unknown, bit) = match value {
+            Logic::Zero => (false, false),
+            Logic::One => (false, true),
+            Logic::X => (true, false),
+            Logic::Z => (true, true),
         };
-        values
+        let changed = self.signals[signal] != bit || self.unknown[signal] != unknown;
+        self.signals.set(signal, bit);
+        self.unknown.set(signal, unknown);
+        changed
     }
 
-    fn get_state(&self, id: ComponentId) -> &State {
-        &self.nodes.get(&id).expect("didn't find node").state
+    fn signal(&self, id: ComponentId, output: usize) -> bool {
+        self.logic_at(id, output) == Logic::One
+    }
+
+    /// The value of an output, which is never `X` or `Z` in [`LogicMode::TwoValued`].
+    ///
+    /// Panics if the output doesn't exist, see [`Self::try_logic_at`].
+    pub fn logic_at(&self, id: ComponentId, output: usize) -> Logic {
+        self.try_logic_at(id, output).expect("output doesn't exist")
+    }
+
+    pub fn try_logic_at(&self, id: ComponentId, output: usize) -> Result<Logic, EngineError> {
+        let component = self.component(id)?;
+        if output >= component.kind.arity().1 {
+            return Err(EngineError::OutputOutOfBounds { id, output });
+        }
+        let signal = component.first_signal + output;
+        Ok(match (self.unknown[signal], self.signals[signal]) {
+            (false, false) => Logic::Zero,
+            (false, true) => Logic::One,
+            (true, false) => Logic::X,
+            (true, true) => Logic::Z,
+        })
+    }
+
+    pub fn logic_mode(&self) -> LogicMode {
+        self.logic_mode
+    }
+
+    /// In [`LogicMode::FourValued`], components added afterwards start as `X` and unconnected
+    /// inputs read as `X`. Switching back to [`LogicMode::TwoValued`] turns `X` and `Z` into `0`.
+    pub fn set_logic_mode(&mut self, mode: LogicMode) {
+        if mode == LogicMode::TwoValued {
+            self.signals.difference_with(&self.unknown);
+            self.unknown.clear();
+        }
+        self.logic_mode = mode;
+        self.record(&[], |_| Event::LogicMode(mode));
+    }
+
+    pub fn state(&self, id: ComponentId) -> Result<State, EngineError> {
+        let outputs = self.component(id)?.kind.arity().1;
+        let logic_values: Vec<Logic> = (0..outputs)
+            .map(|output| self.logic_at(id, output))
+            .collect();
+        Ok(State {
+            values: logic_values
+                .iter()
+                .map(|&value| value == Logic::One)
+                .collect(),
+            logic_values,
+        })
     }
 
     pub fn is_on(&self, id: ComponentId) -> bool {
-        self.get_state(id).values[0]
+        self.signal(id, 0)
     }
 
     pub fn is_off(&self, id: ComponentId) -> bool {
@@ -245,53 +1025,102 @@ impl SimulationEngine {
     }
 
     pub fn is_on_at(&self, id: ComponentId, index: usize) -> bool {
-        self.get_state(id).values[index]
+        self.signal(id, index)
     }
 
     pub fn is_off_at(&self, id: ComponentId, index: usize) -> bool {
         !self.is_on_at(id, index)
     }
 
+    /// Forces an output to `value`, from the next tick on, until it's released.
+    pub fn drive(
+        &mut self,
+        id: ComponentId,
+        output: usize,
+        value: bool,
+    ) -> Result<(), EngineError> {
+        if output >= self.component(id)?.kind.arity().1 {
+            return Err(EngineError::OutputOutOfBounds { id, output });
+        }
+        self.driven.insert((id, output), value);
+        self.record(&[id], |numbers| Event::Drive {
+            component: numbers[0],
+            output,
+            value,
+        });
+        Ok(())
+    }
+
+    /// The value an output is driven to, `None` if it isn't driven.
+    pub fn driven_value(&self, id: ComponentId, output: usize) -> Option<bool> {
+        self.driven.get(&(id, output)).copied()
+    }
+
+    pub fn release(&mut self, id: ComponentId, output: usize) {
+        if self.driven.remove(&(id, output)).is_some() {
+            self.record(&[id], |numbers| Event::Release {
+                component: numbers[0],
+                output,
+            });
+        }
+    }
+
     #[cfg(test)]
     pub fn set_value(&mut self, id: ComponentId, value: bool) {
-        self.nodes
-            .get_mut(&id)
-            .expect("didn't find node")
-            .state
-            .values
-            .insert(0, value);
+        self.update_output(id, 0, Logic::from(value));
+        self.record(&[id], |numbers| Event::SetValue {
+            component: numbers[0],
+            value,
+        });
     }
 
     fn incoming_to(&self, id: ComponentId) -> impl Iterator<Item = (ComponentId, Edge)> {
-        self.incoming_edges
-            .get(&id)
-            .into_iter()
-            .flatten()
+        self.nodes[id]
+            .incoming
+            .iter()
+            .flat_map(|(&component_id, edges)| edges.iter().map(move |&edge| (component_id, edge)))
+    }
+
+    fn outgoing_from(&self, id: ComponentId) -> impl Iterator<Item = (ComponentId, Edge)> {
+        self.nodes[id]
+            .outgoing
+            .iter()
             .flat_map(|(&component_id, edges)| edges.iter().map(move |&edge| (component_id, edge)))
     }
 
+    fn delays(&self) -> impl Iterator<Item = ComponentId> {
+        self.nodes
+            .iter()
+            .filter(|(_, node)| node.kind.is_delay())
+            .map(|(id, _)| id)
+    }
+
     fn subgraph_leaves(&self) -> impl Iterator<Item = ComponentId> {
-        let is_leaf = |&node: &ComponentId| {
-            self.tickless_dag
-                .edges_directed(node, Outgoing)
-                .next()
-                .is_none()
-        };
+        let is_leaf = |&index: &u32| self.tickless_dag.children(index).next().is_none();
 
-        self.tickless_dag.nodes().filter(is_leaf)
+        (0..self.tickless_dag.slot_count() as u32)
+            .filter(is_leaf)
+            .filter_map(|index| self.nodes.id_at(index))
+            .filter(|&id| !self.nodes[id].kind.is_delay())
     }
 }
 
-#[derive(Debug)]
+/// The outputs of a component, copied out of the engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct State {
     values: Vec<bool>,
-    version: u64,
+    logic_values: Vec<Logic>,
 }
 
 impl State {
+    /// `X` and `Z` read as off.
     pub fn values(&self) -> &[bool] {
         &self.values
     }
+
+    pub fn logic_values(&self) -> &[Logic] {
+        &self.logic_values
+    }
 }
 
 #[cfg(test)]
@@ -315,6 +1144,20 @@ mod tests {
         sim.wire0(u, v);
     }
 
+    #[test]
+    fn test_cycles_with_loops() {
+        let mut sim = SimulationEngine::default();
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 4 });
+        let not = sim.add(Not);
+        sim.wire0(not, not);
+        let [v, u] = sim.add_array_of(Not);
+        sim.wire0(v, u);
+        sim.wire0(u, v);
+        sim.run_step();
+        assert_eq!(sim.unstable(), [not]);
+        assert_ne!(sim.is_on(u), sim.is_on(v));
+    }
+
     #[test]
     fn test_disconnected_components() {
         let mut sim = SimulationEngine::default();
@@ -445,18 +1288,18 @@ mod tests {
         assert!(sim.is_off(and));
 
         let (mut sim, not, and) = new_sim();
-        sim.wire(not, and, 0, 0);
+        sim.wire(not, and, 0, 0).unwrap();
         sim.run_step();
         assert!(sim.is_off(and));
 
         let (mut sim, not, and) = new_sim();
-        sim.wire(not, and, 0, 1);
+        sim.wire(not, and, 0, 1).unwrap();
         sim.run_step();
         assert!(sim.is_off(and));
 
         let (mut sim, not, and) = new_sim();
-        sim.wire(not, and, 0, 0);
-        sim.wire(not, and, 0, 1);
+        sim.wire(not, and, 0, 0).unwrap();
+        sim.wire(not, and, 0, 1).unwrap();
         sim.run_step();
         assert!(sim.is_on(and));
     }
@@ -475,20 +1318,20 @@ mod tests {
         assert!(sim.is_off_at(half_adder, 1));
 
         let (mut sim, not, half_adder) = new_sim();
-        sim.wire(not, half_adder, 0, 0);
+        sim.wire(not, half_adder, 0, 0).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(half_adder, 0));
         assert!(sim.is_off_at(half_adder, 1));
 
         let (mut sim, not, half_adder) = new_sim();
-        sim.wire(not, half_adder, 0, 1);
+        sim.wire(not, half_adder, 0, 1).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(half_adder, 0));
         assert!(sim.is_off_at(half_adder, 1));
 
         let (mut sim, not, half_adder) = new_sim();
-        sim.wire(not, half_adder, 0, 0);
-        sim.wire(not, half_adder, 0, 1);
+        sim.wire(not, half_adder, 0, 0).unwrap();
+        sim.wire(not, half_adder, 0, 1).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(half_adder, 1));
         assert!(sim.is_off_at(half_adder, 0));
@@ -508,19 +1351,19 @@ mod tests {
         assert!(sim.is_off_at(full_adder, 1));
 
         let (mut sim, not, full_adder) = new_sim();
-        sim.wire(not, full_adder, 0, 0);
+        sim.wire(not, full_adder, 0, 0).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(full_adder, 0));
         assert!(sim.is_off_at(full_adder, 1));
 
         let (mut sim, not, full_adder) = new_sim();
-        sim.wire(not, full_adder, 0, 1);
+        sim.wire(not, full_adder, 0, 1).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(full_adder, 0));
         assert!(sim.is_off_at(full_adder, 1));
 
         let (mut sim, not, full_adder) = new_sim();
-        sim.wire(not, full_adder, 0, 2);
+        sim.wire(not, full_adder, 0, 2).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(full_adder, 0));
         assert!(sim.is_off_at(full_adder, 1));
@@ -529,19 +1372,675 @@ mod tests {
 
         for (first, second) in two_inputs_combinations {
             let (mut sim, not, full_adder) = new_sim();
-            sim.wire(not, full_adder, 0, first);
-            sim.wire(not, full_adder, 0, second);
+            sim.wire(not, full_adder, 0, first).unwrap();
+            sim.wire(not, full_adder, 0, second).unwrap();
             sim.run_step();
             assert!(sim.is_off_at(full_adder, 0));
             assert!(sim.is_on_at(full_adder, 1));
         }
 
         let (mut sim, not, full_adder) = new_sim();
-        sim.wire(not, full_adder, 0, 0);
-        sim.wire(not, full_adder, 0, 1);
-        sim.wire(not, full_adder, 0, 2);
+        sim.wire(not, full_adder, 0, 0).unwrap();
+        sim.wire(not, full_adder, 0, 1).unwrap();
+        sim.wire(not, full_adder, 0, 2).unwrap();
         sim.run_step();
         assert!(sim.is_on_at(full_adder, 1));
         assert!(sim.is_on_at(full_adder, 1));
     }
+
+    #[test]
+    fn test_last_changes() {
+        let mut sim = SimulationEngine::default();
+        let [not, delay] = sim.add_array_wired_loop([Not, Delay]);
+        let and = sim.add(And(2));
+
+        sim.run_step();
+        assert_eq!(sim.last_changes(), [(not, 0)]);
+        sim.run_step();
+        assert_eq!(sim.last_changes().len(), 2);
+        assert!(sim.last_changes().contains(&(not, 0)));
+        assert!(sim.last_changes().contains(&(delay, 0)));
+        assert!(!sim.last_changes().contains(&(and, 0)));
+    }
+
+    #[test]
+    fn test_observer() {
+        use std::{cell::RefCell, rc::Rc};
+
+        let mut sim = SimulationEngine::default();
+        let [not, delay] = sim.add_array_wired_loop([Not, Delay]);
+
+        let seen = Rc::new(RefCell::new(vec![]));
+        let observer_id = sim.subscribe([delay], {
+            let seen = Rc::clone(&seen);
+            move |tick, id, output, value| seen.borrow_mut().push((tick, id, output, value))
+        });
+
+        for _ in 0..4 {
+            sim.run_step();
+        }
+        assert_eq!(
+            *seen.borrow(),
+            [
+                (2, delay, 0, true),
+                (3, delay, 0, false),
+                (4, delay, 0, true)
+            ]
+        );
+
+        sim.unsubscribe(observer_id);
+        sim.run_step();
+        assert_eq!(seen.borrow().len(), 3);
+        assert!(sim.last_changes().contains(&(not, 0)));
+    }
+
+    #[test]
+    fn test_run_steps() {
+        let mut sim = SimulationEngine::default();
+        let [first, second, third] = sim.add_array_wired_loop_of(Delay);
+        sim.set_value(first, true);
+
+        sim.run_steps(302);
+        assert_eq!(sim.current_tick(), 302);
+        assert!(sim.is_off(first));
+        assert!(sim.is_off(second));
+        assert!(sim.is_on(third));
+    }
+
+    #[test]
+    fn test_run_until() {
+        let mut sim = SimulationEngine::default();
+        let clock = sim.add(Clock {
+            period: 10,
+            duty: 1,
+            phase: 3,
+        });
+
+        assert_eq!(sim.run_until(100, |sim| sim.is_on(clock)), Some(7));
+        assert_eq!(sim.run_until(100, |sim| sim.is_on(clock)), Some(17));
+        assert_eq!(sim.run_until(5, |sim| sim.is_on(clock)), None);
+        assert_eq!(sim.current_tick(), 22);
+    }
+
+    #[test]
+    fn test_run_until_breakpoint() {
+        let mut sim = SimulationEngine::default();
+        let [not, delay] = sim.add_array_wired_loop([Not, Delay]);
+        let [bit_0, bit_1] = sim.add_array([
+            Clock {
+                period: 4,
+                duty: 2,
+                phase: 0,
+            },
+            Clock {
+                period: 8,
+                duty: 4,
+                phase: 0,
+            },
+        ]);
+
+        let bus = vec![(bit_0, 0), (bit_1, 0)];
+        let breakpoints = [
+            Breakpoint::Tick(20),
+            Breakpoint::BusEquals {
+                bits: bus.clone(),
+                value: 0,
+            },
+        ];
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 100), Some(1));
+        assert_eq!(sim.current_tick(), 6);
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 100), Some(1));
+        assert_eq!(sim.current_tick(), 7);
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 100), Some(1));
+        assert_eq!(sim.current_tick(), 14);
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 100), Some(1));
+        assert_eq!(sim.current_tick(), 15);
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 2), None);
+        assert_eq!(sim.current_tick(), 17);
+        assert_eq!(sim.bus_value(&bus).unwrap(), 3);
+        assert_eq!(
+            sim.bus_value(&[(bit_0, 0); 65]),
+            Err(EngineError::BusTooWide { width: 65 })
+        );
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 100), Some(0));
+        assert_eq!(sim.current_tick(), 20);
+
+        let breakpoints = [
+            Breakpoint::BecomesOn { id: not, output: 0 },
+            Breakpoint::BecomesOff {
+                id: delay,
+                output: 0,
+            },
+        ];
+        assert_eq!(sim.run_until_breakpoint(&breakpoints, 100), Some(0));
+        assert_eq!(sim.current_tick(), 21);
+        assert_eq!(sim.run_until_breakpoint(&breakpoints[1..], 100), Some(0));
+        assert_eq!(sim.current_tick(), 23);
+    }
+
+    #[test]
+    fn test_drive() {
+        let mut sim = SimulationEngine::default();
+        let [not, and] = sim.add_array([Not, And(2)]);
+        let [delay_1, delay_2] = sim.add_array_wired_of(Delay);
+        sim.wire(not, and, 0, 0).unwrap();
+        sim.wire(delay_1, and, 0, 1).unwrap();
+
+        sim.drive(delay_1, 0, true).unwrap();
+        sim.run_step();
+        assert!(sim.is_on(delay_1));
+        assert!(sim.is_on(and));
+        assert_eq!(sim.last_changes().len(), 3);
+
+        sim.drive(not, 0, false).unwrap();
+        sim.run_step();
+        assert!(sim.is_off(not));
+        assert!(sim.is_off(and));
+        assert!(sim.is_on(delay_2));
+
+        // without a parent, a delay keeps its last value
+        sim.release(not, 0);
+        sim.release(delay_1, 0);
+        sim.run_step();
+        assert!(sim.is_on(not));
+        assert!(sim.is_on(delay_1));
+        assert!(sim.is_on(and));
+    }
+
+    #[test]
+    fn test_wire_errors() {
+        let mut sim = SimulationEngine::default();
+        let [not, and] = sim.add_array([Not, And(2)]);
+        let clock = sim.add(Clock {
+            period: 2,
+            duty: 1,
+            phase: 0,
+        });
+
+        assert_eq!(
+            sim.wire(not, and, 1, 0),
+            Err(EngineError::OutputOutOfBounds { id: not, output: 1 })
+        );
+        assert_eq!(
+            sim.wire(not, and, 0, 2),
+            Err(EngineError::InputOutOfBounds { id: and, input: 2 })
+        );
+        assert_eq!(
+            sim.wire(not, clock, 0, 0),
+            Err(EngineError::InputOutOfBounds {
+                id: clock,
+                input: 0
+            })
+        );
+        sim.wire(not, and, 0, 0).unwrap();
+        assert_eq!(
+            sim.wire(and, not, 0, 0),
+            Err(EngineError::CreatesCycle {
+                parent: and,
+                child: not
+            })
+        );
+        assert_eq!(
+            sim.drive(and, 1, true),
+            Err(EngineError::OutputOutOfBounds { id: and, output: 1 })
+        );
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut sim = SimulationEngine::default();
+        let [a, b, c] = sim.add_array([Not, Not, Not]);
+        sim.wire0(a, b);
+        sim.wire0(b, c);
+        sim.run_step();
+        assert!(sim.is_on(c));
+
+        assert_eq!(sim.remove(b), Ok(Not));
+        assert!(!sim.contains(b));
+        assert_eq!(sim.component_count(), 2);
+        assert_eq!(sim.remove(b), Err(EngineError::StaleComponent(b)));
+        assert_eq!(sim.wire(a, b, 0, 0), Err(EngineError::StaleComponent(b)));
+        assert_eq!(sim.drive(b, 0, true), Err(EngineError::StaleComponent(b)));
+
+        // `c` lost its parent, and the removed edges don't block new ones
+        sim.run_step();
+        assert!(sim.is_on(c));
+        sim.wire0(c, a);
+        sim.run_step();
+        assert!(sim.is_off(a));
+    }
+
+    #[test]
+    fn test_replace_kind() {
+        let mut sim = SimulationEngine::default();
+        let [a, b, gate, out] = sim.add_array([Delay, Delay, And(2), Not]);
+        sim.wire(a, gate, 0, 0).unwrap();
+        sim.wire(b, gate, 0, 1).unwrap();
+        sim.wire0(gate, out);
+        sim.set_value(a, true);
+        sim.start_recording();
+
+        assert_eq!(sim.replace_kind(gate, And(3)), Ok(vec![]));
+        sim.wire(a, gate, 0, 2).unwrap();
+        sim.drive(b, 0, true).unwrap();
+        sim.run_step();
+        assert!(sim.is_off(out));
+
+        // `in1` and `in2` are gone
+        let dropped = sim.replace_kind(gate, Not).unwrap();
+        assert_eq!(
+            dropped,
+            [
+                Wire {
+                    parent: a,
+                    child: gate,
+                    parent_output: 0,
+                    child_input: 2
+                },
+                Wire {
+                    parent: b,
+                    child: gate,
+                    parent_output: 0,
+                    child_input: 1
+                },
+            ]
+        );
+        sim.run_step();
+        assert!(sim.is_off(gate));
+        assert!(sim.is_on(out));
+
+        // new outputs start off, the kept ones keep their value
+        sim.replace_kind(out, HalfAdder).unwrap();
+        assert_eq!(sim.state(out).unwrap().values(), [true, false]);
+        sim.wire(out, a, 1, 0).unwrap();
+        assert_eq!(sim.replace_kind(out, Not).unwrap().len(), 1);
+
+        let replayed = sim.stop_recording().unwrap().replay().unwrap();
+        assert_eq!(replayed.save(), sim.save());
+    }
+
+    #[test]
+    fn test_replace_kind_grows_next_to_a_component_on() {
+        let mut sim = SimulationEngine::default();
+        // `next` owns the signal right after the ones of `grown`
+        let [grown, next] = sim.add_array_of(Delay);
+        sim.set_value(next, true);
+        sim.replace_kind(grown, HalfAdder).unwrap();
+        assert_eq!(sim.state(grown).unwrap().values(), [false, false]);
+        assert!(sim.is_on(next));
+
+        sim.set_logic_mode(LogicMode::FourValued);
+        let [grown, next] = sim.add_array_of(Delay);
+        sim.set_value(grown, true);
+        sim.set_value(next, true);
+        sim.replace_kind(grown, HalfAdder).unwrap();
+        assert_eq!(
+            sim.state(grown).unwrap().logic_values(),
+            [Logic::One, Logic::X]
+        );
+    }
+
+    #[test]
+    fn test_replace_delay_revalidates_cycles() {
+        let mut sim = SimulationEngine::default();
+        let [delay, not] = sim.add_array_wired_loop([Delay, Not]);
+        sim.run_steps(3);
+
+        assert_eq!(
+            sim.replace_kind(delay, And(1)),
+            Err(EngineError::CreatesCycle {
+                parent: not,
+                child: delay
+            })
+        );
+        assert_eq!(sim.component(delay).unwrap().kind, Delay);
+        sim.run_step();
+
+        // the other way around breaks the loop
+        sim.remove(delay).unwrap();
+        let [first, second] = sim.add_array_wired([Not, Not]);
+        assert!(sim.wire(second, first, 0, 0).is_err());
+        sim.replace_kind(second, Delay).unwrap();
+        sim.wire(second, first, 0, 0).unwrap();
+        sim.run_steps(2);
+    }
+
+    #[test]
+    fn test_invalid_kinds() {
+        let mut sim = SimulationEngine::default();
+        let not = sim.add(Not);
+        let clock = Clock {
+            period: 0,
+            duty: 1,
+            phase: 0,
+        };
+        assert_eq!(sim.try_add(clock), Err(EngineError::ZeroClockPeriod));
+        let table = TableId::new(0);
+        let lut = Lut {
+            inputs: 1,
+            outputs: 1,
+            table,
+        };
+        assert_eq!(sim.try_add(lut), Err(EngineError::InvalidTable(table)));
+        assert_eq!(
+            sim.replace_kind(not, lut),
+            Err(EngineError::InvalidTable(table))
+        );
+        let custom = ComponentKind::Custom {
+            id: CustomId::new(0),
+            inputs: 1,
+            outputs: 1,
+        };
+        assert_eq!(
+            sim.replace_kind(not, custom),
+            Err(EngineError::CustomKind(CustomId::new(0)))
+        );
+
+        let mut batch = Batch::new();
+        batch.add(Not);
+        batch.add(clock);
+        assert_eq!(
+            sim.apply(&batch).unwrap_err().errors,
+            [(1, EngineError::ZeroClockPeriod)]
+        );
+        assert_eq!(sim.component(not).unwrap().kind, Not);
+        assert_eq!(sim.component_count(), 1);
+    }
+
+    #[test]
+    fn test_outputs_are_stored_as_bits() {
+        // components used to own their outputs as a `Vec<bool>` and a version, 32 bytes and an
+        // allocation each, which would make this 176 on 64 bits. They now keep where their
+        // outputs start in two bitsets.
+        assert!(size_of::<Component>() <= 152);
+
+        let mut sim = SimulationEngine::default();
+        for _ in 0..1024 {
+            sim.add(HalfAdder);
+        }
+        assert_eq!(sim.signals.len(), 2048);
+        // a bit for the value and one for whether it's unknown, per output
+        let blocks = sim.signals.as_slice().len() + sim.unknown.as_slice().len();
+        assert_eq!(blocks * size_of::<usize>(), 2 * 2048 / 8);
+    }
+
+    #[test]
+    fn test_reads_of_missing_outputs() {
+        let mut sim = SimulationEngine::default();
+        sim.set_activity_tracking(true);
+        let [delay, not] = sim.add_array_wired([Delay, Not]);
+        sim.run_step();
+        assert!(sim.last_changes().contains(&(not, 0)));
+        sim.remove(not).unwrap();
+
+        assert_eq!(
+            sim.try_logic_at(not, 0),
+            Err(EngineError::StaleComponent(not))
+        );
+        assert_eq!(
+            sim.try_logic_at(delay, 1),
+            Err(EngineError::OutputOutOfBounds {
+                id: delay,
+                output: 1
+            })
+        );
+        assert_eq!(
+            sim.bus_value(&[(delay, 0), (not, 0)]),
+            Err(EngineError::StaleComponent(not))
+        );
+        assert_eq!(sim.toggles(not, 0), None);
+        assert_eq!(sim.toggles(delay, 1), None);
+        assert!(!Breakpoint::BecomesOn { id: not, output: 0 }.is_hit(&sim));
+    }
+
+    #[test]
+    fn test_reused_slot_gets_new_generation() {
+        let mut sim = SimulationEngine::default();
+        let old = sim.add(Not);
+        sim.remove(old).unwrap();
+        let new = sim.add(And(2));
+
+        assert_eq!(old.index(), new.index());
+        assert_ne!(old, new);
+        assert!(sim.component(old).is_err());
+        assert_eq!(sim.component(new).unwrap().kind, And(2));
+        assert_eq!(sim.components().count(), 1);
+    }
+
+    #[test]
+    fn test_state() {
+        let mut sim = SimulationEngine::default();
+        let [not, adder] = sim.add_array([Not, HalfAdder]);
+        sim.wire(not, adder, 0, 0).unwrap();
+        sim.wire(not, adder, 0, 1).unwrap();
+        sim.run_step();
+
+        assert_eq!(sim.state(not).unwrap().values(), [true]);
+        assert_eq!(sim.state(adder).unwrap().values(), [false, true]);
+
+        // one bit per output
+        sim.remove(adder).unwrap();
+        assert_eq!(sim.signals.len(), 3);
+        assert!(sim.state(adder).is_err());
+
+        // the signals of removed components are reused, and start off
+        let [first, second] = sim.add_array_of(Not);
+        assert_eq!(sim.signals.len(), 3);
+        assert_eq!(sim.state(first).unwrap().values(), [false]);
+        assert_eq!(sim.state(second).unwrap().values(), [false]);
+        sim.replace_kind(not, FullAdder).unwrap();
+        sim.remove(first).unwrap();
+        sim.remove(second).unwrap();
+        // the freed ranges are merged
+        sim.add(HalfAdder);
+        assert_eq!(sim.signals.len(), 5);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut sim = SimulationEngine::default();
+        let [_, full_adder, delay] = sim.add_array_wired_loop([Pulse, FullAdder, Delay]);
+        let clock = sim.add(Clock {
+            period: 3,
+            duty: 1,
+            phase: 0,
+        });
+        sim.wire(clock, full_adder, 0, 1).unwrap();
+        let not = sim.add(Not);
+        sim.wire(delay, not, 0, 0).unwrap();
+        sim.drive(not, 0, true).unwrap();
+        sim.run_steps(7);
+
+        let saved = sim.save();
+        let (mut loaded, ids) = SimulationEngine::load(&saved).unwrap();
+        assert_eq!(ids.len(), 5);
+        assert_eq!(loaded.save(), saved);
+
+        for _ in 0..20 {
+            sim.run_step();
+            loaded.run_step();
+            assert_eq!(loaded.save(), sim.save());
+        }
+    }
+
+    #[test]
+    fn test_four_valued_logic() {
+        let mut sim = SimulationEngine::default();
+        sim.set_logic_mode(LogicMode::FourValued);
+        let register = sim.add(Delay);
+        let [not, and, adder] = sim.add_array([Not, And(2), HalfAdder]);
+        sim.wire(register, and, 0, 0).unwrap();
+        sim.wire(not, and, 0, 1).unwrap();
+        sim.wire(register, adder, 0, 0).unwrap();
+        sim.run_step();
+
+        // `not` is unconnected, the register is uninitialized
+        assert_eq!(sim.logic_at(not, 0), Logic::X);
+        assert_eq!(sim.logic_at(register, 0), Logic::X);
+        assert_eq!(sim.logic_at(and, 0), Logic::X);
+        assert_eq!(sim.state(adder).unwrap().logic_values(), [Logic::X; 2]);
+        assert!(sim.is_off(and));
+
+        // a known `0` decides the and
+        sim.drive(register, 0, false).unwrap();
+        sim.run_step();
+        assert_eq!(sim.logic_at(and, 0), Logic::Zero);
+        assert_eq!(sim.logic_at(adder, 0), Logic::X);
+
+        let saved = sim.save();
+        let (loaded, _) = SimulationEngine::load(&saved).unwrap();
+        assert_eq!(loaded.save(), saved);
+        assert_eq!(loaded.logic_at(not, 0), Logic::X);
+
+        sim.set_logic_mode(LogicMode::TwoValued);
+        assert_eq!(sim.logic_at(not, 0), Logic::Zero);
+        sim.run_step();
+        assert_eq!(sim.logic_at(not, 0), Logic::One);
+        assert_eq!(sim.logic_at(adder, 0), Logic::Zero);
+        assert_eq!(sim.logic_at(adder, 1), Logic::Zero);
+    }
+
+    #[test]
+    fn test_tri_state_bus() {
+        let mut sim = SimulationEngine::default();
+        let [data_a, enable_a, data_b, enable_b] = sim.add_array_of(Delay);
+        let [a, b] = sim.add_array_of(TriState);
+        let bus = sim.add(Bus(2));
+        let not = sim.add(Not);
+        sim.wire(data_a, a, 0, 0).unwrap();
+        sim.wire(enable_a, a, 0, 1).unwrap();
+        sim.wire(data_b, b, 0, 0).unwrap();
+        sim.wire(enable_b, b, 0, 1).unwrap();
+        sim.wire(a, bus, 0, 0).unwrap();
+        sim.wire(b, bus, 0, 1).unwrap();
+        sim.wire0(bus, not);
+
+        // nothing enabled, the bus floats, gates read it as off
+        sim.run_step();
+        assert_eq!(sim.logic_at(a, 0), Logic::Z);
+        assert_eq!(sim.logic_at(bus, 0), Logic::Z);
+        assert!(sim.is_on(not));
+
+        sim.drive(data_a, 0, true).unwrap();
+        sim.drive(enable_a, 0, true).unwrap();
+        sim.run_step();
+        assert_eq!(sim.logic_at(bus, 0), Logic::One);
+        assert!(sim.contentions().is_empty());
+
+        // agreeing drivers are fine
+        sim.drive(data_b, 0, true).unwrap();
+        sim.drive(enable_b, 0, true).unwrap();
+        sim.run_step();
+        assert_eq!(sim.logic_at(bus, 0), Logic::One);
+        assert!(sim.contentions().is_empty());
+
+        sim.drive(data_b, 0, false).unwrap();
+        sim.run_step();
+        assert_eq!(sim.logic_at(bus, 0), Logic::X);
+        assert_eq!(sim.contentions(), [bus]);
+
+        sim.drive(enable_a, 0, false).unwrap();
+        sim.run_step();
+        assert_eq!(sim.logic_at(bus, 0), Logic::Zero);
+        assert!(sim.contentions().is_empty());
+
+        let saved = sim.save();
+        assert!(saved.contains(" z\n"));
+        assert_eq!(SimulationEngine::load(&saved).unwrap().0.save(), saved);
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let load_error = |text: &str| SimulationEngine::load(text).err().unwrap();
+
+        assert_eq!(load_error("").line, 1);
+        assert_eq!(
+            load_error("tick 0").message,
+            "expected header `firestone-circuit 1`"
+        );
+        assert_eq!(load_error("firestone-circuit 1\n\ncomponent nand").line, 3);
+        assert_eq!(
+            load_error("firestone-circuit 1\ncomponent not\nwire 0 0 0 0").message,
+            "wire creates a cycle without delays"
+        );
+        assert_eq!(
+            load_error("firestone-circuit 1\ncomponent not\nwire 0 1 0 0").message,
+            "unknown component"
+        );
+        assert_eq!(
+            load_error("firestone-circuit 1\ncomponent bus(99999999999)").message,
+            "invalid component kind `bus(99999999999)`"
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        let mut sim = SimulationEngine::default();
+        let clock = sim.add(Clock {
+            period: 4,
+            duty: 1,
+            phase: 0,
+        });
+        let shifted = sim.add(Clock {
+            period: 4,
+            duty: 3,
+            phase: 2,
+        });
+
+        for _ in 0..100 {
+            sim.run_step();
+            let tick = sim.current_tick();
+            assert_eq!(tick % 4 == 0, sim.is_on(clock), "{tick}");
+            assert_eq!((tick + 2) % 4 < 3, sim.is_on(shifted), "{tick}");
+        }
+
+        let far = sim.add(Clock {
+            period: 4,
+            duty: 1,
+            phase: u64::MAX,
+        });
+        sim.run_step();
+        let tick = sim.current_tick();
+        assert_eq!((tick + 3) % 4 == 0, sim.is_on(far), "{tick}");
+        assert_eq!(
+            "clock(4, 1, 6)".parse(),
+            Ok(Clock {
+                period: 4,
+                duty: 1,
+                phase: 2,
+            })
+        );
+    }
+
+    #[test]
+    fn test_pulse() {
+        let mut sim = SimulationEngine::default();
+        let [_, pulse] = sim.add_array_wired([
+            Clock {
+                period: 5,
+                duty: 3,
+                phase: 3,
+            },
+            Pulse,
+        ]);
+
+        for _ in 0..100 {
+            sim.run_step();
+            let tick = sim.current_tick();
+            assert_eq!(tick % 5 == 2, sim.is_on(pulse), "{tick}");
+        }
+    }
+
+    #[test]
+    fn test_pulse_with_always_on_input() {
+        let mut sim = SimulationEngine::default();
+        let [_, pulse] = sim.add_array_wired([Not, Pulse]);
+
+        sim.run_step();
+        assert!(sim.is_on(pulse));
+        for i in 0..100 {
+            sim.run_step();
+            assert!(sim.is_off(pulse), "{i}");
+        }
+    }
 }
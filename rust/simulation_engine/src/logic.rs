@@ -0,0 +1,86 @@
+use std::fmt;
+
+/// How signals are evaluated, see [`SimulationEngine::set_logic_mode`].
+///
+/// [`SimulationEngine::set_logic_mode`]: crate::SimulationEngine::set_logic_mode
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogicMode {
+    /// Signals are `0` or `1`, unconnected inputs read as `0`.
+    #[default]
+    TwoValued,
+    /// Signals can also be [`Logic::X`] or [`Logic::Z`], unconnected inputs read as `X`.
+    FourValued,
+}
+
+/// Value of a signal in [`LogicMode::FourValued`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Logic {
+    Zero,
+    One,
+    /// Unknown, either uninitialized or depends on something unknown.
+    X,
+    /// High impedance, not driven by anything.
+    Z,
+}
+
+impl Logic {
+    pub fn is_known(self) -> bool {
+        matches!(self, Logic::Zero | Logic::One)
+    }
+
+    /// The boolean value, `None` for `X` and `Z`.
+    pub fn to_bool(self) -> Option<bool> {
+        match self {
+            Logic::Zero => Some(false),
+            Logic::One => Some(true),
+            Logic::X | Logic::Z => None,
+        }
+    }
+
+    /// Resolves two drivers wired to the same input, `Z` doesn't affect the other side.
+    pub(crate) fn wired_or(self, other: Logic) -> Logic {
+        match (self, other) {
+            (Logic::Z, value) | (value, Logic::Z) => value,
+            (Logic::One, _) | (_, Logic::One) => Logic::One,
+            (Logic::X, _) | (_, Logic::X) => Logic::X,
+            (Logic::Zero, Logic::Zero) => Logic::Zero,
+        }
+    }
+
+    pub(crate) fn not(self) -> Logic {
+        match self {
+            Logic::Zero => Logic::One,
+            Logic::One => Logic::Zero,
+            Logic::X | Logic::Z => Logic::X,
+        }
+    }
+
+    /// A `0` decides the result even if other inputs are unknown.
+    pub(crate) fn and(inputs: impl IntoIterator<Item = Logic>) -> Logic {
+        inputs
+            .into_iter()
+            .fold(Logic::One, |result, input| match (result, input) {
+                (Logic::Zero, _) | (_, Logic::Zero) => Logic::Zero,
+                (Logic::One, Logic::One) => Logic::One,
+                _ => Logic::X,
+            })
+    }
+}
+
+impl From<bool> for Logic {
+    fn from(value: bool) -> Self {
+        if value { Logic::One } else { Logic::Zero }
+    }
+}
+
+impl fmt::Display for Logic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Logic::Zero => "0",
+            Logic::One => "1",
+            Logic::X => "x",
+            Logic::Z => "z",
+        };
+        write!(f, "{symbol}")
+    }
+}
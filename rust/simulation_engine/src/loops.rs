@@ -0,0 +1,145 @@
+//! Cycles without delays, like latches made of cross-coupled gates.
+
+use rustc_hash::FxHashSet;
+
+use crate::{ComponentId, SimulationEngine, recording::Event};
+
+/// Whether wires can close cycles without delays, see [`SimulationEngine::set_loop_mode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoopMode {
+    /// Wiring a cycle without delays fails with `EngineError::CreatesCycle`.
+    #[default]
+    Forbidden,
+    /// Every tick, the circuit is evaluated again until its values settle, at most
+    /// `max_passes` times.
+    FixedPoint { max_passes: u32 },
+}
+
+impl SimulationEngine {
+    pub fn loop_mode(&self) -> LoopMode {
+        self.loop_mode
+    }
+
+    /// Allows or forbids wires closing cycles without delays. Such wires are kept out of the
+    /// levels, and a region with one can't be converted to a lookup table.
+    ///
+    /// Panics if loops are forbidden while the circuit still has some.
+    pub fn set_loop_mode(&mut self, mode: LoopMode) {
+        if let LoopMode::FixedPoint { max_passes } = mode {
+            assert_ne!(max_passes, 0, "at least one pass is needed");
+        } else {
+            assert!(!self.has_loops(), "the circuit has loops without delays");
+        }
+        self.loop_mode = mode;
+        self.record(&[], |_| Event::LoopMode(mode));
+    }
+
+    /// Whether some wire closes a cycle without delays.
+    pub fn has_loops(&self) -> bool {
+        !self.feedback.is_empty()
+    }
+
+    /// Components that were still changing when the last tick ran out of passes, the circuit
+    /// has no stable state for its inputs then.
+    pub fn unstable(&self) -> &[ComponentId] {
+        &self.unstable
+    }
+
+    /// Children of a slot through wires closing cycles without delays.
+    pub(crate) fn feedback_children(&self, slot: u32) -> impl Iterator<Item = u32> {
+        self.feedback
+            .iter()
+            .filter(move |&&(parent, _)| parent == slot)
+            .map(|&(_, child)| child)
+    }
+
+    /// Evaluates from the leaves until nothing changes, or the passes run out.
+    pub(crate) fn settle(&mut self, leaves: &[ComponentId]) {
+        let max_passes = match self.loop_mode {
+            LoopMode::FixedPoint { max_passes } if self.has_loops() => max_passes,
+            _ => 1,
+        };
+        self.unstable.clear();
+        for pass in 1..=max_passes {
+            let changes = self.changes.len();
+            self.evaluated.clear();
+            self.contentions.clear();
+            for &leaf in leaves {
+                assert!(!self.nodes[leaf].kind.is_delay());
+                self.recursive_eval_and_update(leaf);
+            }
+            if max_passes == 1 || self.changes.len() == changes {
+                return;
+            }
+            if pass == max_passes {
+                let unstable: FxHashSet<ComponentId> =
+                    self.changes[changes..].iter().map(|&(id, _)| id).collect();
+                self.unstable.extend(unstable);
+                self.unstable.sort();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ComponentKind::*, EngineError};
+
+    #[test]
+    fn test_sr_latch() {
+        let mut sim = SimulationEngine::default();
+        let [set, reset] = sim.add_array_of(Delay);
+        // `not`s OR their inputs, making them NORs
+        let [q, q_bar] = sim.add_array_of(Not);
+        sim.wire(reset, q, 0, 0).unwrap();
+        sim.wire(set, q_bar, 0, 0).unwrap();
+        sim.wire(q_bar, q, 0, 0).unwrap();
+        assert_eq!(
+            sim.wire(q, q_bar, 0, 0),
+            Err(EngineError::CreatesCycle {
+                parent: q,
+                child: q_bar
+            })
+        );
+
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 8 });
+        sim.wire(q, q_bar, 0, 0).unwrap();
+        assert!(sim.has_loops());
+        sim.run_step();
+        assert!(sim.is_off(q) && sim.is_on(q_bar));
+
+        sim.set_value(set, true);
+        sim.run_step();
+        assert!(sim.is_on(q) && sim.is_off(q_bar));
+        assert!(sim.unstable().is_empty());
+        // only the settled values are reported
+        assert_eq!(sim.last_changes(), [(q_bar, 0), (q, 0)]);
+        sim.set_value(set, false);
+        sim.run_step();
+        assert!(sim.is_on(q), "the latch holds");
+
+        sim.set_value(reset, true);
+        sim.run_step();
+        assert!(sim.is_off(q) && sim.is_on(q_bar));
+
+        let (copy, ids) = SimulationEngine::load(&sim.save()).unwrap();
+        assert_eq!(copy.loop_mode(), sim.loop_mode());
+        assert!(copy.is_on(ids[3]));
+    }
+
+    #[test]
+    fn test_unstable_loop() {
+        let mut sim = SimulationEngine::default();
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 5 });
+        let [not] = sim.add_array_wired_loop_of(Not);
+        let [first, _, _] = sim.add_array_wired_loop_of(Not);
+        sim.run_step();
+        assert!(sim.unstable().contains(&not));
+        assert!(sim.unstable().contains(&first));
+
+        sim.unwire(not, not, 0, 0).unwrap();
+        sim.run_step();
+        assert!(!sim.unstable().contains(&not));
+    }
+}
@@ -0,0 +1,510 @@
+//! Lookup tables, components that evaluate any boolean function in a single step, and the
+//! conversion of combinational regions into them.
+
+use std::{
+    collections::{BTreeSet, VecDeque},
+    error::Error,
+    fmt,
+};
+
+use rustc_hash::{FxHashMap, FxHashSet};
+
+use crate::{ComponentId, ComponentKind, EngineError, Logic, SimulationEngine, recording::Event};
+
+/// Identifies a truth table stored in an engine, tables are never removed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TableId(u32);
+
+impl TableId {
+    pub(crate) fn new(index: usize) -> Self {
+        Self(index as u32)
+    }
+
+    pub(crate) fn index(&self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl fmt::Display for TableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// The outputs for every combination of inputs, a row per combination.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TruthTable {
+    inputs: usize,
+    outputs: usize,
+    /// Indexed by the inputs, `in0` being the least significant bit, with `out0` in the least
+    /// significant bit.
+    rows: Vec<u64>,
+}
+
+impl TruthTable {
+    pub const MAX_INPUTS: usize = 16;
+    pub const MAX_OUTPUTS: usize = 64;
+
+    /// Panics if there isn't a row for each of the `2^inputs` combinations.
+    pub fn new(inputs: usize, outputs: usize, rows: Vec<u64>) -> Self {
+        assert!(inputs <= Self::MAX_INPUTS, "too many lookup table inputs");
+        assert!(
+            outputs <= Self::MAX_OUTPUTS,
+            "too many lookup table outputs"
+        );
+        assert_eq!(
+            rows.len(),
+            1 << inputs,
+            "lookup tables need a row per input"
+        );
+        let mask = u64::MAX.checked_shr(64 - outputs as u32).unwrap_or(0);
+        let rows = rows.into_iter().map(|row| row & mask).collect();
+        Self {
+            inputs,
+            outputs,
+            rows,
+        }
+    }
+
+    /// Builds the rows by calling `f` with the inputs of each.
+    pub fn from_fn(inputs: usize, outputs: usize, f: impl FnMut(u64) -> u64) -> Self {
+        assert!(inputs <= Self::MAX_INPUTS, "too many lookup table inputs");
+        Self::new(inputs, outputs, (0..1 << inputs).map(f).collect())
+    }
+
+    pub fn inputs(&self) -> usize {
+        self.inputs
+    }
+
+    pub fn outputs(&self) -> usize {
+        self.outputs
+    }
+
+    /// The outputs for the inputs in `row`.
+    pub fn row(&self, row: u64) -> u64 {
+        self.rows[row as usize]
+    }
+
+    pub(crate) fn eval(&self, inputs: &[bool]) -> Vec<bool> {
+        let row = self.row(to_number(inputs));
+        (0..self.outputs)
+            .map(|output| row & (1 << output) != 0)
+            .collect()
+    }
+
+    /// An output is known if it's the same in every row matching the known inputs.
+    pub(crate) fn eval_logic(&self, inputs: &[Logic]) -> Vec<Logic> {
+        let (mut known, mut value) = (0, 0);
+        for (input, logic) in inputs.iter().enumerate() {
+            if let Some(bit) = logic.to_bool() {
+                known |= 1 << input;
+                value |= (bit as u64) << input;
+            }
+        }
+        let (mut ones, mut zeros) = (0, 0);
+        for (_, &row) in self
+            .rows
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| index as u64 & known == value)
+        {
+            ones |= row;
+            zeros |= !row;
+        }
+        (0..self.outputs)
+            .map(
+                |output| match (ones & (1 << output), zeros & (1 << output)) {
+                    (0, _) => Logic::Zero,
+                    (_, 0) => Logic::One,
+                    _ => Logic::X,
+                },
+            )
+            .collect()
+    }
+
+    /// Reads the format used by `Display`.
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let mut words = s.split_whitespace();
+        let inputs: usize = words.next()?.parse().ok()?;
+        let outputs: usize = words.next()?.parse().ok()?;
+        let rows = words
+            .map(|row| u64::from_str_radix(row, 16).ok())
+            .collect::<Option<Vec<u64>>>()?;
+        let valid =
+            inputs <= Self::MAX_INPUTS && outputs <= Self::MAX_OUTPUTS && rows.len() == 1 << inputs;
+        valid.then(|| Self::new(inputs, outputs, rows))
+    }
+}
+
+/// `<inputs> <outputs> <rows>...`, with the rows in hexadecimal.
+impl fmt::Display for TruthTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.inputs, self.outputs)?;
+        for row in &self.rows {
+            write!(f, " {row:x}")?;
+        }
+        Ok(())
+    }
+}
+
+fn to_number(bits: &[bool]) -> u64 {
+    bits.iter()
+        .enumerate()
+        .map(|(bit, &on)| (on as u64) << bit)
+        .sum()
+}
+
+/// The lookup table that replaced a region.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LutConversion {
+    pub id: ComponentId,
+    /// The output outside the region wired to each input of the table.
+    pub inputs: Vec<(ComponentId, usize)>,
+    /// The output of the removed region each output of the table stands for.
+    pub outputs: Vec<(ComponentId, usize)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LutError {
+    Engine(EngineError),
+    EmptyRegion,
+    /// Only stateless components without delays can be converted.
+    NotCombinational(ComponentId),
+    TooManyInputs {
+        inputs: usize,
+        max: usize,
+    },
+    TooManyOutputs(usize),
+    /// A path leaves the region and comes back into it, the table would be in a cycle.
+    NotConvex,
+}
+
+impl From<EngineError> for LutError {
+    fn from(error: EngineError) -> Self {
+        LutError::Engine(error)
+    }
+}
+
+impl fmt::Display for LutError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LutError::Engine(error) => write!(f, "{error}"),
+            LutError::EmptyRegion => write!(f, "region is empty"),
+            LutError::NotCombinational(id) => write!(f, "component {id:?} isn't combinational"),
+            LutError::TooManyInputs { inputs, max } => {
+                write!(f, "region has {inputs} inputs, at most {max} allowed")
+            }
+            LutError::TooManyOutputs(outputs) => write!(f, "region has {outputs} outputs"),
+            LutError::NotConvex => write!(f, "region isn't convex, a path leaves and re-enters it"),
+        }
+    }
+}
+
+impl Error for LutError {}
+
+impl SimulationEngine {
+    pub fn add_table(&mut self, table: TruthTable) -> TableId {
+        let id = TableId::new(self.tables.len());
+        self.tables.push(table.clone());
+        self.record(&[], |_| Event::Table(table));
+        id
+    }
+
+    pub fn table(&self, id: TableId) -> Option<&TruthTable> {
+        self.tables.get(id.index())
+    }
+
+    /// Stores `table` and adds a lookup table component evaluating it.
+    pub fn add_lut(&mut self, table: TruthTable) -> ComponentId {
+        let (inputs, outputs) = (table.inputs, table.outputs);
+        let table = self.add_table(table);
+        self.add(ComponentKind::Lut {
+            inputs,
+            outputs,
+            table,
+        })
+    }
+
+    /// Replaces the components of `region` with a single lookup table computing the same
+    /// function, if the region has at most `max_inputs` inputs.
+    ///
+    /// The inputs of the table are the outputs outside the region wired into it, its outputs the
+    /// outputs of the region wired outside of it. Outputs only used inside the region, drives
+    /// and faults of the region are dropped.
+    pub fn convert_to_lut(
+        &mut self,
+        region: &[ComponentId],
+        max_inputs: usize,
+    ) -> Result<LutConversion, LutError> {
+        let members: FxHashSet<ComponentId> = region.iter().copied().collect();
+        if members.is_empty() {
+            return Err(LutError::EmptyRegion);
+        }
+        for &id in &members {
+            match self.component(id)?.kind {
+                ComponentKind::Not
+                | ComponentKind::And(_)
+                | ComponentKind::HalfAdder
+                | ComponentKind::FullAdder
+                | ComponentKind::Lut { .. } => {}
+                _ => return Err(LutError::NotCombinational(id)),
+            }
+        }
+        // a loop without delays has no truth table
+        for &(parent, child) in &self.feedback {
+            let member = |slot| self.nodes.id_at(slot).filter(|id| members.contains(id));
+            if let (Some(_), Some(child)) = (member(parent), member(child)) {
+                return Err(LutError::NotCombinational(child));
+            }
+        }
+
+        let mut inputs = BTreeSet::new();
+        let mut outputs = BTreeSet::new();
+        // `(region output, child, child input)`
+        let mut loads = vec![];
+        for &id in &members {
+            for (parent, edge) in self.incoming_to(id) {
+                if !members.contains(&parent) {
+                    inputs.insert((parent, edge.parent_output));
+                }
+            }
+            for (&child, edges) in &self.nodes[id].outgoing {
+                if !members.contains(&child) {
+                    for edge in edges {
+                        outputs.insert((id, edge.parent_output));
+                        loads.push(((id, edge.parent_output), child, edge.child_input));
+                    }
+                }
+            }
+        }
+        let inputs: Vec<(ComponentId, usize)> = inputs.into_iter().collect();
+        let outputs: Vec<(ComponentId, usize)> = outputs.into_iter().collect();
+        let max = max_inputs.min(TruthTable::MAX_INPUTS);
+        if inputs.len() > max {
+            return Err(LutError::TooManyInputs {
+                inputs: inputs.len(),
+                max,
+            });
+        }
+        if outputs.len() > TruthTable::MAX_OUTPUTS {
+            return Err(LutError::TooManyOutputs(outputs.len()));
+        }
+        if !self.is_convex(&members, loads.iter().map(|&(_, child, _)| child)) {
+            return Err(LutError::NotConvex);
+        }
+
+        let order = self.topological_order(&members);
+        let input_index: FxHashMap<(ComponentId, usize), usize> = inputs
+            .iter()
+            .enumerate()
+            .map(|(index, &pin)| (pin, index))
+            .collect();
+        let table = TruthTable::from_fn(inputs.len(), outputs.len(), |row| {
+            let mut values: FxHashMap<ComponentId, Vec<bool>> = FxHashMap::default();
+            for &id in &order {
+                let kind = self.nodes[id].kind;
+                let mut component_inputs = vec![false; kind.arity().0];
+                for (parent, edge) in self.incoming_to(id) {
+                    component_inputs[edge.child_input] |= match values.get(&parent) {
+                        Some(parent_values) => parent_values[edge.parent_output],
+                        None => row & (1 << input_index[&(parent, edge.parent_output)]) != 0,
+                    };
+                }
+                values.insert(id, self.eval_combinational(kind, &component_inputs));
+            }
+            outputs
+                .iter()
+                .enumerate()
+                .map(|(output, &(id, id_output))| (values[&id][id_output] as u64) << output)
+                .sum()
+        });
+
+        let current: Vec<Logic> = outputs
+            .iter()
+            .map(|&(id, output)| self.logic_at(id, output))
+            .collect();
+        for &id in &order {
+            self.remove(id)?;
+        }
+        let lut = self.add_lut(table);
+        for (input, &(parent, parent_output)) in inputs.iter().enumerate() {
+            self.wire(parent, lut, parent_output, input)?;
+        }
+        for (pin, child, child_input) in loads {
+            let output = outputs.binary_search(&pin).unwrap();
+            self.wire(lut, child, output, child_input)?;
+        }
+        // keeps the values until the next tick, as if nothing changed
+        let first_signal = self.nodes[lut].first_signal;
+        for (output, value) in current.into_iter().enumerate() {
+            self.set_signal(first_signal + output, value);
+        }
+
+        Ok(LutConversion {
+            id: lut,
+            inputs,
+            outputs,
+        })
+    }
+
+    /// Whether nothing reachable from `loads` without crossing delays is in `members`.
+    fn is_convex(
+        &self,
+        members: &FxHashSet<ComponentId>,
+        loads: impl Iterator<Item = ComponentId>,
+    ) -> bool {
+        let member_slots: FxHashSet<u32> = members.iter().map(|id| id.index()).collect();
+        let mut seen = FxHashSet::default();
+        let mut queue: VecDeque<u32> = loads
+            .filter(|&id| !self.nodes[id].kind.is_delay())
+            .map(|id| id.index())
+            .collect();
+        while let Some(slot) = queue.pop_front() {
+            if member_slots.contains(&slot) {
+                return false;
+            }
+            if seen.insert(slot) {
+                queue.extend(self.tickless_dag.children(slot));
+                queue.extend(self.feedback_children(slot));
+            }
+        }
+        true
+    }
+
+    /// Orders `members` so that parents come before their children.
+    fn topological_order(&self, members: &FxHashSet<ComponentId>) -> Vec<ComponentId> {
+        let mut parents: FxHashMap<ComponentId, usize> = members
+            .iter()
+            .map(|&id| {
+                let count = self.nodes[id]
+                    .incoming
+                    .keys()
+                    .filter(|parent| members.contains(parent))
+                    .count();
+                (id, count)
+            })
+            .collect();
+        let mut ready: Vec<ComponentId> = parents
+            .iter()
+            .filter(|&(_, &count)| count == 0)
+            .map(|(&id, _)| id)
+            .collect();
+        ready.sort();
+
+        let mut order = vec![];
+        while let Some(id) = ready.pop() {
+            order.push(id);
+            for child in self.nodes[id].outgoing.keys() {
+                if let Some(count) = parents.get_mut(child) {
+                    *count -= 1;
+                    if *count == 0 {
+                        ready.push(*child);
+                    }
+                }
+            }
+        }
+        order
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ComponentKind::*, LogicMode, stdlib};
+
+    #[test]
+    fn test_lut() {
+        let mut sim = SimulationEngine::default();
+        // a majority of three
+        let table = TruthTable::from_fn(3, 1, |row| (row.count_ones() >= 2) as u64);
+        let lut = sim.add_lut(table);
+        let inputs = sim.add_array_of::<3>(Delay);
+        for (input, &id) in inputs.iter().enumerate() {
+            sim.wire(id, lut, 0, input).unwrap();
+        }
+
+        for (value, expected) in [(0b000, false), (0b101, true), (0b010, false), (0b111, true)] {
+            for (bit, &id) in inputs.iter().enumerate() {
+                sim.set_value(id, value & (1 << bit) != 0);
+            }
+            sim.run_step();
+            assert_eq!(sim.is_on(lut), expected);
+        }
+
+        let (copy, ids) = SimulationEngine::load(&sim.save()).unwrap();
+        assert_eq!(copy.save(), sim.save());
+        assert!(matches!(copy.nodes[ids[0]].kind, Lut { inputs: 3, .. }));
+    }
+
+    #[test]
+    fn test_lut_four_valued() {
+        let table = TruthTable::from_fn(2, 2, |row| match row {
+            0b11 => 0b11,
+            _ => 0b10,
+        });
+        // `out1` is on whatever the inputs, `out0` depends on both
+        assert_eq!(
+            table.eval_logic(&[Logic::One, Logic::X]),
+            [Logic::X, Logic::One]
+        );
+        assert_eq!(
+            table.eval_logic(&[Logic::Zero, Logic::X]),
+            [Logic::Zero, Logic::One]
+        );
+        assert_eq!(TruthTable::parse(&table.to_string()), Some(table));
+
+        let mut sim = SimulationEngine::default();
+        sim.set_logic_mode(LogicMode::FourValued);
+        let lut = sim.add_lut(TruthTable::from_fn(1, 1, |row| row ^ 1));
+        sim.run_step();
+        assert_eq!(sim.logic_at(lut, 0), Logic::X);
+    }
+
+    #[test]
+    fn test_convert_to_lut() {
+        let mut sim = SimulationEngine::default();
+        let adder = stdlib::ripple_adder(&mut sim, 2);
+        let probe = sim.add(And(1));
+        sim.wire(adder.carry_out.0, probe, adder.carry_out.1, 0)
+            .unwrap();
+        let sum: Vec<ComponentId> = adder.sum.iter().map(|&(id, _)| id).collect();
+        let count = sim.component_count();
+
+        assert_eq!(
+            sim.convert_to_lut(&sum, 3),
+            Err(LutError::TooManyInputs { inputs: 5, max: 3 })
+        );
+        let memory = sim.add(Delay);
+        assert_eq!(
+            sim.convert_to_lut(&[memory, sum[0]], 8),
+            Err(LutError::NotCombinational(memory))
+        );
+        sim.remove(memory).unwrap();
+
+        let conversion = sim.convert_to_lut(&sum, 8).unwrap();
+        assert_eq!(sim.component_count(), count - 1);
+        // the sum outputs lost their loads, only the carry is left
+        assert_eq!(conversion.outputs, [adder.carry_out]);
+
+        for (a, b, carry_in) in [(1, 2, 0), (3, 1, 0), (2, 2, 0), (3, 3, 1)] {
+            for (bit, (&a_bit, &b_bit)) in adder.a.iter().zip(&adder.b).enumerate() {
+                sim.drive(a_bit, 0, a & (1 << bit) != 0).unwrap();
+                sim.drive(b_bit, 0, b & (1 << bit) != 0).unwrap();
+            }
+            sim.drive(adder.carry_in, 0, carry_in == 1).unwrap();
+            sim.run_step();
+            assert_eq!(sim.is_on(probe), a + b + carry_in >= 4);
+        }
+    }
+
+    #[test]
+    fn test_convert_non_convex() {
+        let mut sim = SimulationEngine::default();
+        let [first, outside, last] = sim.add_array_wired_of(Not);
+        assert_eq!(
+            sim.convert_to_lut(&[first, last], 4),
+            Err(LutError::NotConvex)
+        );
+        assert!(sim.contains(first) && sim.contains(outside) && sim.contains(last));
+    }
+}
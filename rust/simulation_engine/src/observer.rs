@@ -0,0 +1,24 @@
+use crate::ComponentId;
+
+/// Gets notified when an output of a subscribed component changes, see
+/// [`SimulationEngine::subscribe`](crate::SimulationEngine::subscribe).
+pub trait Observer {
+    fn on_change(&mut self, tick: u64, id: ComponentId, output: usize, value: bool);
+}
+
+impl<F: FnMut(u64, ComponentId, usize, bool)> Observer for F {
+    fn on_change(&mut self, tick: u64, id: ComponentId, output: usize, value: bool) {
+        self(tick, id, output, value);
+    }
+}
+
+#[derive(Default, PartialEq, Eq, Hash, PartialOrd, Ord, Debug, Clone, Copy)]
+pub struct ObserverId(usize);
+
+impl ObserverId {
+    pub(crate) fn next(&mut self) -> ObserverId {
+        let id = *self;
+        self.0 += 1;
+        id
+    }
+}
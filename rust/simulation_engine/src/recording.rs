@@ -0,0 +1,951 @@
+//! Logs of the mutations done to an engine, that can be replayed to reproduce a run.
+//!
+//! ```text
+//! firestone-recording 1
+//! # the circuit when the recording started, as written by `SimulationEngine::save`
+//! base 3
+//! firestone-circuit 1
+//! tick 0
+//! component delay
+//! # mutations, with the tick in which they happened, and the state hash after every tick
+//! event 0 add not
+//! event 0 wire 1 0 0 0
+//! hash 1 9c1f0e2a55b3d871
+//! event 1 drive 0 0 1
+//! hash 2 03aa71c6e2f4b918
+//! ```
+//!
+//! Components are numbered in the order they appear, first the ones of the base circuit.
+
+use std::{
+    error::Error,
+    fmt,
+    fmt::Write,
+    hash::{Hash, Hasher},
+};
+
+use rustc_hash::{FxHashMap, FxHasher};
+
+use crate::{
+    ComponentId, ComponentKind, EngineError, LoadError, Logic, LogicMode, LoopMode,
+    SimulationEngine, TimingMode, TruthTable,
+    fault::Fault,
+    save::{quote, unquote},
+};
+
+const HEADER: &str = "firestone-recording 1";
+
+/// A mutation, components are referred to by their number in the recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// Stores a truth table, numbered after the previous ones.
+    Table(TruthTable),
+    Add(ComponentKind),
+    Remove(usize),
+    Replace {
+        component: usize,
+        kind: ComponentKind,
+    },
+    Wire {
+        parent: usize,
+        child: usize,
+        parent_output: usize,
+        child_input: usize,
+    },
+    Unwire {
+        parent: usize,
+        child: usize,
+        parent_output: usize,
+        child_input: usize,
+    },
+    Drive {
+        component: usize,
+        output: usize,
+        value: bool,
+    },
+    Release {
+        component: usize,
+        output: usize,
+    },
+    LogicMode(LogicMode),
+    LoopMode(LoopMode),
+    TimingMode(TimingMode),
+    InjectFault {
+        component: usize,
+        output: usize,
+        fault: RecordedFault,
+    },
+    RemoveFault {
+        component: usize,
+        output: usize,
+        fault: RecordedFault,
+    },
+    ClearFaults,
+    /// Sets the first output directly, only done by tests.
+    SetValue {
+        component: usize,
+        value: bool,
+    },
+    Label {
+        component: usize,
+        label: Option<String>,
+    },
+    Metadata {
+        component: usize,
+        key: String,
+        value: String,
+    },
+    RemoveMetadata {
+        component: usize,
+        key: String,
+    },
+}
+
+/// A [`Fault`] without the output it targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordedFault {
+    StuckAt { value: bool },
+    Flip { tick: u64 },
+}
+
+impl RecordedFault {
+    fn new(fault: Fault) -> RecordedFault {
+        match fault {
+            Fault::StuckAt { value, .. } => RecordedFault::StuckAt { value },
+            Fault::Flip { tick, .. } => RecordedFault::Flip { tick },
+        }
+    }
+
+    fn on(self, id: ComponentId, output: usize) -> Fault {
+        match self {
+            RecordedFault::StuckAt { value } => Fault::StuckAt { id, output, value },
+            RecordedFault::Flip { tick } => Fault::Flip { id, output, tick },
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Recording {
+    /// The circuit when the recording started.
+    pub base: String,
+    /// Mutations and the tick in which they happened, before that tick's step.
+    pub events: Vec<(u64, Event)>,
+    /// State hash after every step.
+    pub hashes: Vec<(u64, u64)>,
+}
+
+/// The recording in progress, kept by the engine.
+#[derive(Default)]
+pub(crate) struct Recorder {
+    recording: Recording,
+    numbers: FxHashMap<ComponentId, usize>,
+    next_number: usize,
+}
+
+impl Recorder {
+    fn number(&mut self, id: ComponentId) -> usize {
+        let number = self.next_number;
+        self.next_number += 1;
+        self.numbers.insert(id, number);
+        number
+    }
+}
+
+impl SimulationEngine {
+    /// Starts logging mutations and state hashes, replacing the recording in progress.
+    pub fn start_recording(&mut self) {
+        let mut recorder = Recorder::default();
+        recorder.recording.base = self.save();
+        // numbered in the order `save` writes them
+        let mut ids: Vec<ComponentId> = self.components().map(|(id, _)| id).collect();
+        ids.sort();
+        for id in ids {
+            recorder.number(id);
+        }
+        self.recorder = Some(Box::new(recorder));
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recorder.take().map(|recorder| recorder.recording)
+    }
+
+    pub(crate) fn record_add(&mut self, id: ComponentId, kind: ComponentKind) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.number(id);
+            let event = (self.current_tick, Event::Add(kind));
+            recorder.recording.events.push(event);
+        }
+    }
+
+    /// Records an event built from the numbers of the components involved.
+    pub(crate) fn record(&mut self, ids: &[ComponentId], event: impl FnOnce(&[usize]) -> Event) {
+        if let Some(recorder) = &mut self.recorder {
+            let numbers: Vec<usize> = ids.iter().map(|id| recorder.numbers[id]).collect();
+            let event = (self.current_tick, event(&numbers));
+            recorder.recording.events.push(event);
+        }
+    }
+
+    /// Records the injection or removal of a fault.
+    pub(crate) fn record_fault(&mut self, fault: Fault, injected: bool) {
+        let (id, output) = fault.target();
+        let fault = RecordedFault::new(fault);
+        self.record(&[id], |numbers| {
+            let component = numbers[0];
+            if injected {
+                Event::InjectFault {
+                    component,
+                    output,
+                    fault,
+                }
+            } else {
+                Event::RemoveFault {
+                    component,
+                    output,
+                    fault,
+                }
+            }
+        });
+    }
+
+    pub(crate) fn record_tick(&mut self) {
+        if let Some(recorder) = &self.recorder {
+            let hash = self.state_hash(&recorder.numbers);
+            let recorder = self.recorder.as_mut().unwrap();
+            recorder.recording.hashes.push((self.current_tick, hash));
+        }
+    }
+
+    /// Hashes the tick, components and outputs, independent of how ids were assigned.
+    fn state_hash(&self, numbers: &FxHashMap<ComponentId, usize>) -> u64 {
+        let mut components: Vec<(usize, ComponentId)> = self
+            .components()
+            .map(|(id, _)| (numbers[&id], id))
+            .collect();
+        components.sort();
+
+        let mut hasher = FxHasher::default();
+        self.current_tick.hash(&mut hasher);
+        for (number, id) in components {
+            number.hash(&mut hasher);
+            self.nodes[id].kind.hash(&mut hasher);
+            self.state(id).unwrap().logic_values().hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+}
+
+impl Recording {
+    /// Rebuilds the recorded run, checking the state hash after every tick.
+    pub fn replay(&self) -> Result<SimulationEngine, ReplayError> {
+        let (mut engine, ids) = SimulationEngine::load(&self.base).map_err(ReplayError::Base)?;
+        let mut components: Vec<Option<ComponentId>> = ids.into_iter().map(Some).collect();
+        let mut numbers: FxHashMap<ComponentId, usize> = components
+            .iter()
+            .enumerate()
+            .map(|(number, id)| (id.unwrap(), number))
+            .collect();
+
+        let mut events = self.events.iter().peekable();
+        let mut hashes = self.hashes.iter();
+        loop {
+            while let Some((tick, event)) =
+                events.next_if(|(tick, _)| *tick <= engine.current_tick())
+            {
+                let tick = *tick;
+                let error = |error| ReplayError::Event { tick, error };
+                let id = |number: usize| -> Result<ComponentId, ReplayError> {
+                    components
+                        .get(number)
+                        .copied()
+                        .flatten()
+                        .ok_or(ReplayError::UnknownComponent { tick, number })
+                };
+                match *event {
+                    Event::Table(ref table) => {
+                        engine.add_table(table.clone());
+                    }
+                    Event::Add(kind) => {
+                        let id = engine.try_add(kind).map_err(error)?;
+                        numbers.insert(id, components.len());
+                        components.push(Some(id));
+                    }
+                    Event::Remove(number) => {
+                        engine.remove(id(number)?).map_err(error)?;
+                        components[number] = None;
+                    }
+                    Event::Replace { component, kind } => {
+                        engine.replace_kind(id(component)?, kind).map_err(error)?;
+                    }
+                    Event::Wire {
+                        parent,
+                        child,
+                        parent_output,
+                        child_input,
+                    } => engine
+                        .wire(id(parent)?, id(child)?, parent_output, child_input)
+                        .map_err(error)?,
+                    Event::Unwire {
+                        parent,
+                        child,
+                        parent_output,
+                        child_input,
+                    } => {
+                        engine
+                            .unwire(id(parent)?, id(child)?, parent_output, child_input)
+                            .map_err(error)?;
+                    }
+                    Event::Drive {
+                        component,
+                        output,
+                        value,
+                    } => engine.drive(id(component)?, output, value).map_err(error)?,
+                    Event::Release { component, output } => {
+                        engine.release(id(component)?, output);
+                    }
+                    Event::LogicMode(mode) => engine.set_logic_mode(mode),
+                    Event::LoopMode(mode) => {
+                        let valid = match mode {
+                            LoopMode::Forbidden => !engine.has_loops(),
+                            LoopMode::FixedPoint { max_passes } => max_passes > 0,
+                        };
+                        if !valid {
+                            return Err(ReplayError::InvalidMode { tick });
+                        }
+                        engine.set_loop_mode(mode);
+                    }
+                    Event::TimingMode(mode) => {
+                        if let TimingMode::UnitDelay { ticks: 0 } = mode {
+                            return Err(ReplayError::InvalidMode { tick });
+                        }
+                        engine.set_timing_mode(mode);
+                    }
+                    Event::InjectFault {
+                        component,
+                        output,
+                        fault,
+                    } => engine
+                        .inject_fault(fault.on(id(component)?, output))
+                        .map_err(error)?,
+                    Event::RemoveFault {
+                        component,
+                        output,
+                        fault,
+                    } => engine.remove_fault(fault.on(id(component)?, output)),
+                    Event::ClearFaults => engine.clear_faults(),
+                    Event::SetValue { component, value } => {
+                        let id = id(component)?;
+                        if engine.component(id).map_err(error)?.kind.arity().1 == 0 {
+                            return Err(error(EngineError::OutputOutOfBounds { id, output: 0 }));
+                        }
+                        engine.update_output(id, 0, Logic::from(value));
+                    }
+                    Event::Label {
+                        component,
+                        ref label,
+                    } => engine
+                        .set_label(id(component)?, label.as_deref())
+                        .map_err(error)?,
+                    Event::Metadata {
+                        component,
+                        ref key,
+                        ref value,
+                    } => engine
+                        .set_metadata(id(component)?, key, value)
+                        .map_err(error)?,
+                    Event::RemoveMetadata { component, ref key } => {
+                        engine.remove_metadata(id(component)?, key);
+                    }
+                }
+            }
+
+            let Some(&(tick, expected)) = hashes.next() else {
+                break;
+            };
+            engine.run_step();
+            let actual = engine.state_hash(&numbers);
+            if engine.current_tick() != tick || actual != expected {
+                return Err(ReplayError::Diverged { tick });
+            }
+        }
+        Ok(engine)
+    }
+
+    /// Reads a recording written by its `Display` implementation.
+    pub fn parse(text: &str) -> Result<Recording, LoadError> {
+        let mut recording = Recording::default();
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        let mut content = lines
+            .by_ref()
+            .map(|(line, content)| (line, content.trim()))
+            .filter(|(_, content)| !content.is_empty() && !content.starts_with('#'));
+
+        match content.next() {
+            Some((_, HEADER)) => {}
+            Some((line, _)) => {
+                return Err(LoadError::new(line, &format!("expected header `{HEADER}`")));
+            }
+            None => return Err(LoadError::new(1, "empty file")),
+        }
+
+        let Some((line, base)) = content.next() else {
+            return Err(LoadError::new(1, "expected `base <lines>`"));
+        };
+        let base_lines: usize = base
+            .strip_prefix("base ")
+            .and_then(|count| count.parse().ok())
+            .ok_or_else(|| LoadError::new(line, "expected `base <lines>`"))?;
+        for _ in 0..base_lines {
+            let (_, base_line) = lines
+                .next()
+                .ok_or_else(|| LoadError::new(line, "base circuit ends early"))?;
+            writeln!(recording.base, "{base_line}").unwrap();
+        }
+
+        for (line, content) in lines {
+            let content = content.trim();
+            if content.is_empty() || content.starts_with('#') {
+                continue;
+            }
+            let err = |message: &str| LoadError::new(line, message);
+            let words: Vec<&str> = content.split_whitespace().collect();
+            let number = |word: &str| word.parse::<usize>().map_err(|_| err("expected number"));
+            let tick = || -> Result<u64, LoadError> {
+                words
+                    .get(1)
+                    .and_then(|tick| tick.parse().ok())
+                    .ok_or_else(|| err("expected tick"))
+            };
+
+            match words[..] {
+                ["hash", _, hash] => {
+                    let hash = u64::from_str_radix(hash, 16).map_err(|_| err("invalid hash"))?;
+                    recording.hashes.push((tick()?, hash));
+                }
+                ["event", _, "table", ..] => {
+                    let table = content.split_once(" table ").unwrap().1;
+                    let table =
+                        TruthTable::parse(table).ok_or_else(|| err("invalid truth table"))?;
+                    recording.events.push((tick()?, Event::Table(table)));
+                }
+                ["event", _, "add", ..] => {
+                    let kind = content.split_once(" add ").unwrap().1;
+                    let kind = kind.parse().map_err(|e| err(&format!("{e}")))?;
+                    recording.events.push((tick()?, Event::Add(kind)));
+                }
+                ["event", _, "remove", component] => {
+                    recording
+                        .events
+                        .push((tick()?, Event::Remove(number(component)?)));
+                }
+                ["event", _, "replace", component, ..] => {
+                    let kind = content.split_once(" replace ").unwrap().1;
+                    let kind = kind.split_once(' ').unwrap().1;
+                    let event = Event::Replace {
+                        component: number(component)?,
+                        kind: kind.parse().map_err(|e| err(&format!("{e}")))?,
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                [
+                    "event",
+                    _,
+                    "wire",
+                    parent,
+                    child,
+                    parent_output,
+                    child_input,
+                ] => {
+                    let event = Event::Wire {
+                        parent: number(parent)?,
+                        child: number(child)?,
+                        parent_output: number(parent_output)?,
+                        child_input: number(child_input)?,
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                [
+                    "event",
+                    _,
+                    "unwire",
+                    parent,
+                    child,
+                    parent_output,
+                    child_input,
+                ] => {
+                    let event = Event::Unwire {
+                        parent: number(parent)?,
+                        child: number(child)?,
+                        parent_output: number(parent_output)?,
+                        child_input: number(child_input)?,
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                ["event", _, "drive", component, output, value @ ("0" | "1")] => {
+                    let event = Event::Drive {
+                        component: number(component)?,
+                        output: number(output)?,
+                        value: value == "1",
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                ["event", _, "release", component, output] => {
+                    let event = Event::Release {
+                        component: number(component)?,
+                        output: number(output)?,
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                ["event", _, "logic", mode] => {
+                    let mode = match mode {
+                        "two_valued" => LogicMode::TwoValued,
+                        "four_valued" => LogicMode::FourValued,
+                        _ => return Err(err("expected `logic two_valued|four_valued`")),
+                    };
+                    recording.events.push((tick()?, Event::LogicMode(mode)));
+                }
+                ["event", _, "loops", "forbidden"] => {
+                    recording
+                        .events
+                        .push((tick()?, Event::LoopMode(LoopMode::Forbidden)));
+                }
+                ["event", _, "loops", "fixed_point", max_passes] => {
+                    let max_passes = match max_passes.parse() {
+                        Ok(max_passes @ 1..) => max_passes,
+                        _ => return Err(err("expected a number of passes from 1")),
+                    };
+                    let mode = LoopMode::FixedPoint { max_passes };
+                    recording.events.push((tick()?, Event::LoopMode(mode)));
+                }
+                ["event", _, "timing", "zero_delay"] => {
+                    recording
+                        .events
+                        .push((tick()?, Event::TimingMode(TimingMode::ZeroDelay)));
+                }
+                ["event", _, "timing", "unit_delay", ticks] => {
+                    let ticks = match ticks.parse() {
+                        Ok(ticks @ 1..) => ticks,
+                        _ => return Err(err("expected a number of ticks from 1")),
+                    };
+                    let mode = TimingMode::UnitDelay { ticks };
+                    recording.events.push((tick()?, Event::TimingMode(mode)));
+                }
+                [
+                    "event",
+                    _,
+                    action @ ("inject" | "remove_fault"),
+                    component,
+                    output,
+                    kind,
+                    argument,
+                ] => {
+                    let fault = match (kind, argument) {
+                        ("stuck", value @ ("0" | "1")) => RecordedFault::StuckAt {
+                            value: value == "1",
+                        },
+                        ("flip", tick) => RecordedFault::Flip {
+                            tick: tick.parse().map_err(|_| err("expected tick"))?,
+                        },
+                        _ => return Err(err("expected `stuck <0|1>` or `flip <tick>`")),
+                    };
+                    let (component, output) = (number(component)?, number(output)?);
+                    let event = if action == "inject" {
+                        Event::InjectFault {
+                            component,
+                            output,
+                            fault,
+                        }
+                    } else {
+                        Event::RemoveFault {
+                            component,
+                            output,
+                            fault,
+                        }
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                ["event", _, "clear_faults"] => {
+                    recording.events.push((tick()?, Event::ClearFaults));
+                }
+                ["event", _, "set", component, value @ ("0" | "1")] => {
+                    let event = Event::SetValue {
+                        component: number(component)?,
+                        value: value == "1",
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                ["event", _, "unlabel", component] => {
+                    let event = Event::Label {
+                        component: number(component)?,
+                        label: None,
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                [
+                    "event",
+                    _,
+                    command @ ("label" | "meta" | "unmeta"),
+                    component,
+                    ..,
+                ] => {
+                    // the quoted texts after the component can contain spaces
+                    let texts = content.splitn(5, ' ').nth(4).and_then(unquote);
+                    let component = number(component)?;
+                    let event = match (command, texts.as_deref()) {
+                        ("label", Some([label])) => Event::Label {
+                            component,
+                            label: Some(label.clone()),
+                        },
+                        ("meta", Some([key, value])) => Event::Metadata {
+                            component,
+                            key: key.clone(),
+                            value: value.clone(),
+                        },
+                        ("unmeta", Some([key])) => Event::RemoveMetadata {
+                            component,
+                            key: key.clone(),
+                        },
+                        _ => return Err(err("expected quoted texts")),
+                    };
+                    recording.events.push((tick()?, event));
+                }
+                _ => return Err(err("expected an `event` or a `hash`")),
+            }
+        }
+        Ok(recording)
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{HEADER}")?;
+        writeln!(f, "base {}", self.base.lines().count())?;
+        write!(f, "{}", self.base)?;
+
+        // events happen before the step of their tick, so they come before its hash
+        let mut events = self.events.iter().peekable();
+        for &(hash_tick, hash) in &self.hashes {
+            while let Some((tick, event)) = events.next_if(|(tick, _)| *tick < hash_tick) {
+                writeln!(f, "event {tick} {event}")?;
+            }
+            writeln!(f, "hash {hash_tick} {hash:016x}")?;
+        }
+        for (tick, event) in events {
+            writeln!(f, "event {tick} {event}")?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Event::Table(ref table) => write!(f, "table {table}"),
+            Event::Add(kind) => write!(f, "add {kind}"),
+            Event::Remove(component) => write!(f, "remove {component}"),
+            Event::Replace { component, kind } => write!(f, "replace {component} {kind}"),
+            Event::Wire {
+                parent,
+                child,
+                parent_output,
+                child_input,
+            } => write!(f, "wire {parent} {child} {parent_output} {child_input}"),
+            Event::Unwire {
+                parent,
+                child,
+                parent_output,
+                child_input,
+            } => write!(f, "unwire {parent} {child} {parent_output} {child_input}"),
+            Event::Drive {
+                component,
+                output,
+                value,
+            } => write!(f, "drive {component} {output} {}", value as u8),
+            Event::Release { component, output } => write!(f, "release {component} {output}"),
+            Event::LogicMode(LogicMode::TwoValued) => write!(f, "logic two_valued"),
+            Event::LogicMode(LogicMode::FourValued) => write!(f, "logic four_valued"),
+            Event::LoopMode(LoopMode::Forbidden) => write!(f, "loops forbidden"),
+            Event::LoopMode(LoopMode::FixedPoint { max_passes }) => {
+                write!(f, "loops fixed_point {max_passes}")
+            }
+            Event::TimingMode(TimingMode::ZeroDelay) => write!(f, "timing zero_delay"),
+            Event::TimingMode(TimingMode::UnitDelay { ticks }) => {
+                write!(f, "timing unit_delay {ticks}")
+            }
+            Event::InjectFault {
+                component,
+                output,
+                fault,
+            } => write!(f, "inject {component} {output} {fault}"),
+            Event::RemoveFault {
+                component,
+                output,
+                fault,
+            } => write!(f, "remove_fault {component} {output} {fault}"),
+            Event::ClearFaults => write!(f, "clear_faults"),
+            Event::SetValue { component, value } => write!(f, "set {component} {}", value as u8),
+            Event::Label {
+                component,
+                label: Some(ref label),
+            } => write!(f, "label {component} {}", quote(label)),
+            Event::Label {
+                component,
+                label: None,
+            } => write!(f, "unlabel {component}"),
+            Event::Metadata {
+                component,
+                ref key,
+                ref value,
+            } => write!(f, "meta {component} {} {}", quote(key), quote(value)),
+            Event::RemoveMetadata { component, ref key } => {
+                write!(f, "unmeta {component} {}", quote(key))
+            }
+        }
+    }
+}
+
+impl fmt::Display for RecordedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            RecordedFault::StuckAt { value } => write!(f, "stuck {}", value as u8),
+            RecordedFault::Flip { tick } => write!(f, "flip {tick}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The base circuit couldn't be loaded.
+    Base(LoadError),
+    UnknownComponent {
+        tick: u64,
+        number: usize,
+    },
+    Event {
+        tick: u64,
+        error: EngineError,
+    },
+    /// A loop or timing mode the engine can't take, like zero passes, or forbidding loops
+    /// while the circuit has some.
+    InvalidMode {
+        tick: u64,
+    },
+    /// The state after `tick` doesn't match the recorded one.
+    Diverged {
+        tick: u64,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Base(error) => write!(f, "base circuit: {error}"),
+            ReplayError::UnknownComponent { tick, number } => {
+                write!(f, "tick {tick}: unknown component {number}")
+            }
+            ReplayError::Event { tick, error } => write!(f, "tick {tick}: {error}"),
+            ReplayError::InvalidMode { tick } => {
+                write!(f, "tick {tick}: invalid loop or timing mode")
+            }
+            ReplayError::Diverged { tick } => write!(f, "replay diverged at tick {tick}"),
+        }
+    }
+}
+
+impl Error for ReplayError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComponentKind::*;
+
+    fn record() -> (SimulationEngine, Recording) {
+        let mut sim = SimulationEngine::default();
+        let [lever, _] = sim.add_array_wired([Delay, Not]);
+        sim.run_steps(2);
+
+        sim.start_recording();
+        let removed = sim.add(Not);
+        let and = sim.add(And(2));
+        sim.wire(lever, and, 0, 0).unwrap();
+        sim.wire(removed, and, 0, 1).unwrap();
+        sim.run_step();
+        sim.drive(lever, 0, true).unwrap();
+        sim.run_steps(2);
+        sim.remove(removed).unwrap();
+        // reuses the slot of the removed component
+        let not = sim.add(Not);
+        sim.wire(not, and, 0, 1).unwrap();
+        sim.run_step();
+        sim.release(lever, 0);
+        sim.run_steps(3);
+        let recording = sim.stop_recording().unwrap();
+        (sim, recording)
+    }
+
+    #[test]
+    fn test_replay() {
+        let (sim, recording) = record();
+        assert_eq!(recording.hashes.len(), 7);
+        assert_eq!(recording.events.len(), 9);
+
+        let replayed = recording.replay().unwrap();
+        assert_eq!(replayed.current_tick(), sim.current_tick());
+        assert_eq!(replayed.save(), sim.save());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let (_, recording) = record();
+        let text = recording.to_string();
+        assert_eq!(Recording::parse(&text).unwrap(), recording);
+        assert!(text.contains("event 5 remove 2\nevent 5 add not\n"));
+    }
+
+    #[test]
+    fn test_divergence() {
+        let (_, mut recording) = record();
+        let (_, event) = &mut recording.events[4];
+        *event = Event::Drive {
+            component: 0,
+            output: 0,
+            value: false,
+        };
+        assert_eq!(
+            recording.replay().err().unwrap(),
+            ReplayError::Diverged { tick: 4 }
+        );
+    }
+
+    #[test]
+    fn test_modes_faults_and_labels() {
+        let mut sim = SimulationEngine::default();
+        let [lever, not] = sim.add_array_wired([Delay, Not]);
+        sim.start_recording();
+        sim.set_logic_mode(LogicMode::FourValued);
+        sim.set_timing_mode(TimingMode::UnitDelay { ticks: 2 });
+        sim.set_label(not, Some("say \"hi\" there")).unwrap();
+        sim.set_metadata(not, "a key", "a value").unwrap();
+        sim.run_steps(3);
+        let stuck = Fault::StuckAt {
+            id: not,
+            output: 0,
+            value: false,
+        };
+        sim.inject_fault(stuck).unwrap();
+        sim.inject_fault(Fault::Flip {
+            id: lever,
+            output: 0,
+            tick: 5,
+        })
+        .unwrap();
+        sim.run_steps(2);
+        sim.remove_fault(stuck);
+        sim.remove_metadata(not, "a key");
+        sim.set_loop_mode(LoopMode::FixedPoint { max_passes: 4 });
+        sim.set_value(lever, true);
+        sim.run_step();
+        sim.clear_faults();
+        sim.set_label(not, None).unwrap();
+        sim.set_timing_mode(TimingMode::ZeroDelay);
+        sim.run_steps(2);
+        let recording = sim.stop_recording().unwrap();
+        assert_eq!(recording.events.len(), 13);
+
+        let parsed = Recording::parse(&recording.to_string()).unwrap();
+        assert_eq!(parsed, recording);
+        let replayed = parsed.replay().unwrap();
+        assert_eq!(replayed.save(), sim.save());
+        assert_eq!(replayed.faults(), sim.faults());
+    }
+
+    #[test]
+    fn test_invalid_kind_is_a_replay_error() {
+        let mut sim = SimulationEngine::default();
+        sim.start_recording();
+        sim.run_step();
+        let mut recording = sim.stop_recording().unwrap();
+        let kind = Lut {
+            table: crate::TableId::new(0),
+            inputs: 1,
+            outputs: 1,
+        };
+        recording.events.push((1, Event::Add(kind)));
+        assert!(matches!(
+            recording.replay(),
+            Err(ReplayError::Event {
+                tick: 1,
+                error: EngineError::InvalidTable(_),
+            })
+        ));
+    }
+
+    #[test]
+    fn test_invalid_events_are_replay_errors() {
+        let replay = |events: Vec<(u64, Event)>| {
+            let mut sim = SimulationEngine::default();
+            sim.start_recording();
+            let mut recording = sim.stop_recording().unwrap();
+            recording.events = events;
+            recording.replay().err().unwrap()
+        };
+
+        let passes = Event::LoopMode(LoopMode::FixedPoint { max_passes: 0 });
+        assert_eq!(
+            replay(vec![(0, passes)]),
+            ReplayError::InvalidMode { tick: 0 }
+        );
+        let ticks = Event::TimingMode(TimingMode::UnitDelay { ticks: 0 });
+        assert_eq!(
+            replay(vec![(0, ticks)]),
+            ReplayError::InvalidMode { tick: 0 }
+        );
+
+        let loops = Event::LoopMode(LoopMode::FixedPoint { max_passes: 4 });
+        let wire = Event::Wire {
+            parent: 0,
+            child: 0,
+            parent_output: 0,
+            child_input: 0,
+        };
+        let forbid = Event::LoopMode(LoopMode::Forbidden);
+        assert_eq!(
+            replay(vec![
+                (0, loops),
+                (0, Event::Add(Not)),
+                (0, wire),
+                (0, forbid)
+            ]),
+            ReplayError::InvalidMode { tick: 0 }
+        );
+
+        let table = crate::TruthTable::new(1, 0, vec![0, 0]);
+        let kind = Lut {
+            table: crate::TableId::new(0),
+            inputs: 1,
+            outputs: 0,
+        };
+        let set = Event::SetValue {
+            component: 0,
+            value: true,
+        };
+        assert!(matches!(
+            replay(vec![
+                (0, Event::Table(table)),
+                (0, Event::Add(kind)),
+                (0, set)
+            ]),
+            ReplayError::Event {
+                tick: 0,
+                error: EngineError::OutputOutOfBounds { output: 0, .. },
+            }
+        ));
+    }
+}
@@ -0,0 +1,363 @@
+use std::{error::Error, fmt, fmt::Write};
+
+use rustc_hash::FxHashMap;
+
+use crate::{
+    Component, ComponentId, ComponentKind, EngineError, Logic, LogicMode, LoopMode,
+    SimulationEngine, TimingMode, TruthTable,
+};
+
+const HEADER: &str = "firestone-circuit 1";
+
+impl SimulationEngine {
+    /// Serializes the circuit and its current state, can be read back with [`Self::load`].
+    ///
+    /// Components are numbered by their order in the file, wires and states refer to them by it.
+    pub fn save(&self) -> String {
+        let mut ids: Vec<ComponentId> = self.nodes.iter().map(|(id, _)| id).collect();
+        ids.sort();
+        let index_of: FxHashMap<ComponentId, usize> = ids
+            .iter()
+            .enumerate()
+            .map(|(index, &id)| (id, index))
+            .collect();
+
+        let mut out = String::new();
+        writeln!(out, "{HEADER}").unwrap();
+        writeln!(out, "tick {}", self.current_tick).unwrap();
+        if self.logic_mode == LogicMode::FourValued {
+            writeln!(out, "logic four_valued").unwrap();
+        }
+        if let LoopMode::FixedPoint { max_passes } = self.loop_mode {
+            writeln!(out, "loops {max_passes}").unwrap();
+        }
+        if let TimingMode::UnitDelay { ticks } = self.timing_mode {
+            writeln!(out, "gate_delay {ticks}").unwrap();
+        }
+        // numbered by their order, like `TableId`s
+        for table in &self.tables {
+            writeln!(out, "table {table}").unwrap();
+        }
+
+        for id in &ids {
+            writeln!(out, "component {}", self.nodes[*id].kind).unwrap();
+        }
+
+        for (index, id) in ids.iter().enumerate() {
+            let node = &self.nodes[*id];
+            // components are loaded with this value
+            let initial = match self.logic_mode {
+                LogicMode::TwoValued => Logic::Zero,
+                LogicMode::FourValued => Logic::X,
+            };
+            let values = self.state(*id).unwrap().logic_values().to_vec();
+            if values.iter().any(|&value| value != initial) {
+                let bits: String = values.iter().map(|value| value.to_string()).collect();
+                writeln!(out, "state {index} {bits}").unwrap();
+            }
+            // oldest first
+            for values in self.in_flight.get(id).into_iter().flatten() {
+                let bits: String = values.iter().map(|value| value.to_string()).collect();
+                writeln!(out, "in_flight {index} {bits}").unwrap();
+            }
+            if let Some(tick) = node.rising_edge_tick {
+                writeln!(out, "rising_edge {index} {tick}").unwrap();
+            }
+            if let Some(label) = &node.label {
+                writeln!(out, "label {index} {}", quote(label)).unwrap();
+            }
+            for (key, value) in &node.metadata {
+                writeln!(out, "meta {index} {} {}", quote(key), quote(value)).unwrap();
+            }
+        }
+
+        for parent in &ids {
+            for (child, edges) in &self.nodes[*parent].outgoing {
+                for edge in edges {
+                    writeln!(
+                        out,
+                        "wire {} {} {} {}",
+                        index_of[parent], index_of[child], edge.parent_output, edge.child_input,
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        let mut driven: Vec<_> = self.driven.iter().collect();
+        driven.sort();
+        for (&(id, output), &value) in driven {
+            writeln!(out, "drive {} {output} {}", index_of[&id], value as u8).unwrap();
+        }
+
+        out
+    }
+
+    /// Copies the circuit and its state, but not observers or faults.
+    ///
+    /// Also returns where each component ended up in the copy. `None` if the circuit has custom
+    /// components, their instances can't be copied.
+    pub fn duplicate(&self) -> Option<(SimulationEngine, FxHashMap<ComponentId, ComponentId>)> {
+        if self.customs.iter().any(Option::is_some) {
+            return None;
+        }
+        let (copy, copied_ids) = Self::load(&self.save()).expect("saved circuits can be loaded");
+        let mut ids: Vec<ComponentId> = self.nodes.iter().map(|(id, _)| id).collect();
+        ids.sort();
+        Some((copy, ids.into_iter().zip(copied_ids).collect()))
+    }
+
+    /// Reads a circuit written by [`Self::save`].
+    ///
+    /// Also returns the ids of the components, in the order they appear in the file.
+    pub fn load(text: &str) -> Result<(SimulationEngine, Vec<ComponentId>), LoadError> {
+        let mut engine = SimulationEngine::new();
+        let mut ids = vec![];
+
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            Some((line, _)) => {
+                return Err(LoadError::new(line, &format!("expected header `{HEADER}`")));
+            }
+            None => return Err(LoadError::new(1, "empty file")),
+        }
+
+        for (line, content) in lines {
+            let err = |message: &str| LoadError::new(line, message);
+            let (command, args) = content.split_once(' ').unwrap_or((content, ""));
+            let numbers = || -> Result<Vec<u64>, LoadError> {
+                args.split_whitespace()
+                    .map(|arg| arg.parse().map_err(|_| err("expected numbers")))
+                    .collect()
+            };
+            let component = |index: u64| -> Result<ComponentId, LoadError> {
+                ids.get(index as usize)
+                    .copied()
+                    .ok_or_else(|| err("unknown component"))
+            };
+
+            match command {
+                "tick" => match numbers()?[..] {
+                    [tick] => engine.current_tick = tick,
+                    _ => return Err(err("expected `tick <tick>`")),
+                },
+                "logic" => match args {
+                    "two_valued" => engine.set_logic_mode(LogicMode::TwoValued),
+                    "four_valued" => engine.set_logic_mode(LogicMode::FourValued),
+                    _ => return Err(err("expected `logic two_valued|four_valued`")),
+                },
+                "loops" => match numbers()?[..] {
+                    [max_passes @ 1..=0xFFFF_FFFF] => engine.set_loop_mode(LoopMode::FixedPoint {
+                        max_passes: max_passes as u32,
+                    }),
+                    _ => return Err(err("expected `loops <max passes>`")),
+                },
+                "gate_delay" => match numbers()?[..] {
+                    [ticks @ 1..=0xFFFF_FFFF] => engine.set_timing_mode(TimingMode::UnitDelay {
+                        ticks: ticks as u32,
+                    }),
+                    _ => return Err(err("expected `gate_delay <ticks>`")),
+                },
+                "table" => {
+                    let table =
+                        TruthTable::parse(args).ok_or_else(|| err("invalid truth table"))?;
+                    engine.add_table(table);
+                }
+                "component" => {
+                    let kind: ComponentKind = args.parse().map_err(|e| err(&format!("{e}")))?;
+                    if let ComponentKind::Lut {
+                        inputs,
+                        outputs,
+                        table,
+                    } = kind
+                    {
+                        let table = engine.table(table).ok_or_else(|| err("unknown table"))?;
+                        if (table.inputs(), table.outputs()) != (inputs, outputs) {
+                            return Err(err("lookup table size doesn't match its table"));
+                        }
+                    }
+                    if kind.is_custom() {
+                        return Err(err("custom components can't be loaded"));
+                    }
+                    ids.push(engine.add(kind));
+                }
+                "state" | "in_flight" => {
+                    let (index, bits) = args
+                        .split_once(' ')
+                        .ok_or_else(|| err(&format!("expected `{command} <component> <bits>`")))?;
+                    let id = component(index.parse().map_err(|_| err("expected number"))?)?;
+                    let node: &Component = &engine.nodes[id];
+                    if bits.len() != node.kind.arity().1 {
+                        return Err(err("wrong number of bits for component outputs"));
+                    }
+                    let values = bits
+                        .chars()
+                        .map(|bit| match bit {
+                            '0' => Ok(Logic::Zero),
+                            '1' => Ok(Logic::One),
+                            'x' => Ok(Logic::X),
+                            'z' => Ok(Logic::Z),
+                            _ => Err(err("bits must be `0`, `1`, `x` or `z`")),
+                        })
+                        .collect::<Result<Vec<Logic>, LoadError>>()?;
+                    if command == "in_flight" {
+                        engine.in_flight.entry(id).or_default().push_back(values);
+                        continue;
+                    }
+                    let first_signal = node.first_signal;
+                    for (output, value) in values.into_iter().enumerate() {
+                        engine.set_signal(first_signal + output, value);
+                    }
+                }
+                "rising_edge" => match numbers()?[..] {
+                    [index, tick] => {
+                        let id = component(index)?;
+                        engine.nodes[id].rising_edge_tick = Some(tick);
+                    }
+                    _ => return Err(err("expected `rising_edge <component> <tick>`")),
+                },
+                "label" => {
+                    let expected = || err("expected `label <component> \"<label>\"`");
+                    let (index, label) = args.split_once(' ').ok_or_else(expected)?;
+                    let id = component(index.parse().map_err(|_| err("expected number"))?)?;
+                    // unquoted in older files
+                    let label = match unquote(label).as_deref() {
+                        Some([label]) => label.clone(),
+                        _ if !label.starts_with('"') => label.to_owned(),
+                        _ => return Err(expected()),
+                    };
+                    engine.nodes[id].label = Some(label);
+                }
+                "meta" => {
+                    let expected = || err("expected `meta <component> \"<key>\" \"<value>\"`");
+                    let (index, fields) = args.split_once(' ').ok_or_else(expected)?;
+                    let id = component(index.parse().map_err(|_| err("expected number"))?)?;
+                    // unquoted in older files
+                    let (key, value) = match unquote(fields) {
+                        Some(fields) => match <[String; 2]>::try_from(fields) {
+                            Ok([key, value]) => (key, value),
+                            Err(_) => return Err(expected()),
+                        },
+                        None if !fields.starts_with('"') => {
+                            let (key, value) = fields.split_once(' ').ok_or_else(expected)?;
+                            (key.to_owned(), value.to_owned())
+                        }
+                        None => return Err(expected()),
+                    };
+                    engine.nodes[id].metadata.insert(key, value);
+                }
+                "wire" => match numbers()?[..] {
+                    [parent, child, parent_output, child_input] => {
+                        let (parent, child) = (component(parent)?, component(child)?);
+                        let result = engine.wire(
+                            parent,
+                            child,
+                            parent_output as usize,
+                            child_input as usize,
+                        );
+                        match result {
+                            Ok(()) => {}
+                            Err(EngineError::CreatesCycle { .. }) => {
+                                return Err(err("wire creates a cycle without delays"));
+                            }
+                            Err(_) => return Err(err("port out of bounds")),
+                        }
+                    }
+                    _ => return Err(err("expected `wire <parent> <child> <output> <input>`")),
+                },
+                "drive" => match numbers()?[..] {
+                    [index, output, value @ (0 | 1)] => {
+                        let id = component(index)?;
+                        engine
+                            .drive(id, output as usize, value == 1)
+                            .map_err(|_| err("port out of bounds"))?;
+                    }
+                    _ => return Err(err("expected `drive <component> <output> <0|1>`")),
+                },
+                _ => return Err(err("unknown command")),
+            }
+        }
+
+        Ok((engine, ids))
+    }
+}
+
+/// Puts text in double quotes, escaping quotes, backslashes and line breaks, so it can have
+/// any character and still fit in a line.
+pub(crate) fn quote(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Reads texts written by [`quote`] separated by spaces, `None` if one is malformed.
+pub(crate) fn unquote(text: &str) -> Option<Vec<String>> {
+    let mut texts = vec![];
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if_eq(&' ').is_some() {}
+        match chars.next() {
+            None => return Some(texts),
+            Some('"') => {}
+            Some(_) => return None,
+        }
+        let mut unquoted = String::new();
+        loop {
+            match chars.next()? {
+                '"' => break,
+                '\\' => unquoted.push(match chars.next()? {
+                    '"' => '"',
+                    '\\' => '\\',
+                    'n' => '\n',
+                    'r' => '\r',
+                    _ => return None,
+                }),
+                c => unquoted.push(c),
+            }
+        }
+        // texts are separated by spaces
+        if chars.peek().is_some_and(|&c| c != ' ') {
+            return None;
+        }
+        texts.push(unquoted);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl LoadError {
+    pub(crate) fn new(line: usize, message: &str) -> Self {
+        Self {
+            line,
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for LoadError {}
@@ -0,0 +1,569 @@
+//! Generators of common circuits, wired into an engine.
+//!
+//! Inputs are `and(1)` buffers, wire signals into their `in0` or drive their output. Outputs are
+//! `(component, output)` pins. Buses are least significant bit first. Combinational circuits
+//! settle in the tick their inputs change, the sequential ones store their state in delays, so
+//! they update a tick after their inputs.
+
+use crate::{ComponentId, ComponentKind, SimulationEngine};
+
+/// An output of a component.
+pub type Pin = (ComponentId, usize);
+
+pub struct RippleAdder {
+    pub a: Vec<ComponentId>,
+    pub b: Vec<ComponentId>,
+    pub carry_in: ComponentId,
+    pub sum: Vec<Pin>,
+    pub carry_out: Pin,
+}
+
+/// Adds two `bits` wide numbers with a chain of full adders.
+pub fn ripple_adder(engine: &mut SimulationEngine, bits: usize) -> RippleAdder {
+    assert_ne!(bits, 0, "adders need at least one bit");
+    let a = inputs(engine, bits);
+    let b = inputs(engine, bits);
+    let carry_in = input(engine);
+
+    let mut carry = (carry_in, 0);
+    let mut sum = vec![];
+    for bit in 0..bits {
+        let adder = engine.add(ComponentKind::FullAdder);
+        wire(engine, (a[bit], 0), adder, 0);
+        wire(engine, (b[bit], 0), adder, 1);
+        wire(engine, carry, adder, 2);
+        sum.push((adder, 0));
+        carry = (adder, 1);
+    }
+
+    RippleAdder {
+        a,
+        b,
+        carry_in,
+        sum,
+        carry_out: carry,
+    }
+}
+
+pub struct Multiplexer {
+    pub data: Vec<ComponentId>,
+    pub select: Vec<ComponentId>,
+    pub out: Pin,
+}
+
+/// Outputs the data input chosen by `select_bits` select inputs.
+pub fn multiplexer(engine: &mut SimulationEngine, select_bits: usize) -> Multiplexer {
+    let data = inputs(engine, 1 << select_bits);
+    let select = inputs(engine, select_bits);
+
+    let lines = decode(engine, &pins(&select));
+    let chosen: Vec<Pin> = lines
+        .into_iter()
+        .zip(&data)
+        .map(|(line, &data)| and(engine, &[line, (data, 0)]))
+        .collect();
+    let out = or(engine, &chosen);
+
+    Multiplexer { data, select, out }
+}
+
+pub struct Demultiplexer {
+    pub data: ComponentId,
+    pub select: Vec<ComponentId>,
+    pub outputs: Vec<Pin>,
+}
+
+/// Routes the data input to the output chosen by `select_bits` select inputs, others are off.
+pub fn demultiplexer(engine: &mut SimulationEngine, select_bits: usize) -> Demultiplexer {
+    let data = input(engine);
+    let select = inputs(engine, select_bits);
+
+    let lines = decode(engine, &pins(&select));
+    let outputs = lines
+        .into_iter()
+        .map(|line| and(engine, &[line, (data, 0)]))
+        .collect();
+
+    Demultiplexer {
+        data,
+        select,
+        outputs,
+    }
+}
+
+pub struct Decoder {
+    pub inputs: Vec<ComponentId>,
+    /// The output numbered by the inputs is on, the others are off.
+    pub outputs: Vec<Pin>,
+}
+
+pub fn decoder(engine: &mut SimulationEngine, bits: usize) -> Decoder {
+    let inputs = inputs(engine, bits);
+    let outputs = decode(engine, &pins(&inputs));
+    Decoder { inputs, outputs }
+}
+
+pub struct Encoder {
+    /// One-hot, the outputs are OR-ed if several are on.
+    pub inputs: Vec<ComponentId>,
+    pub outputs: Vec<Pin>,
+    /// On if any input is.
+    pub valid: Pin,
+}
+
+/// Numbers the input that is on, the inverse of [`decoder`].
+pub fn encoder(engine: &mut SimulationEngine, bits: usize) -> Encoder {
+    let inputs = inputs(engine, 1 << bits);
+
+    let outputs = (0..bits)
+        .map(|bit| {
+            let set: Vec<Pin> = (0..inputs.len())
+                .filter(|index| index & (1 << bit) != 0)
+                .map(|index| (inputs[index], 0))
+                .collect();
+            or(engine, &set)
+        })
+        .collect();
+    let valid = or(engine, &pins(&inputs));
+
+    Encoder {
+        inputs,
+        outputs,
+        valid,
+    }
+}
+
+pub struct Register {
+    pub data: Vec<ComponentId>,
+    /// Stores `data` while on.
+    pub load: ComponentId,
+    pub outputs: Vec<Pin>,
+}
+
+pub fn register(engine: &mut SimulationEngine, bits: usize) -> Register {
+    let data = inputs(engine, bits);
+    let load = input(engine);
+    let keep = not(engine, (load, 0));
+
+    let outputs = data
+        .iter()
+        .map(|&data| {
+            let memory = engine.add(ComponentKind::Delay);
+            let new = and(engine, &[(data, 0), (load, 0)]);
+            let old = and(engine, &[(memory, 0), keep]);
+            let next = or(engine, &[new, old]);
+            wire(engine, next, memory, 0);
+            (memory, 0)
+        })
+        .collect();
+
+    Register {
+        data,
+        load,
+        outputs,
+    }
+}
+
+pub struct Counter {
+    /// Counts up by one every tick while on.
+    pub enable: ComponentId,
+    /// Goes back to zero while on.
+    pub reset: ComponentId,
+    pub outputs: Vec<Pin>,
+}
+
+/// Counts modulo `2^bits`.
+pub fn counter(engine: &mut SimulationEngine, bits: usize) -> Counter {
+    let enable = input(engine);
+    let reset = input(engine);
+    let keep = not(engine, (reset, 0));
+
+    let mut carry = (enable, 0);
+    let mut outputs = vec![];
+    for _ in 0..bits {
+        let memory = engine.add(ComponentKind::Delay);
+        let adder = engine.add(ComponentKind::HalfAdder);
+        wire(engine, (memory, 0), adder, 0);
+        wire(engine, carry, adder, 1);
+        let next = and(engine, &[(adder, 0), keep]);
+        wire(engine, next, memory, 0);
+        outputs.push((memory, 0));
+        carry = (adder, 1);
+    }
+
+    Counter {
+        enable,
+        reset,
+        outputs,
+    }
+}
+
+pub struct ShiftRegister {
+    pub data: ComponentId,
+    /// Shifts `data` into the first output, and each output into the next, while on.
+    pub shift: ComponentId,
+    pub outputs: Vec<Pin>,
+}
+
+pub fn shift_register(engine: &mut SimulationEngine, bits: usize) -> ShiftRegister {
+    let data = input(engine);
+    let shift = input(engine);
+    let keep = not(engine, (shift, 0));
+
+    let mut previous = (data, 0);
+    let mut outputs = vec![];
+    for _ in 0..bits {
+        let memory = engine.add(ComponentKind::Delay);
+        let new = and(engine, &[previous, (shift, 0)]);
+        let old = and(engine, &[(memory, 0), keep]);
+        let next = or(engine, &[new, old]);
+        wire(engine, next, memory, 0);
+        outputs.push((memory, 0));
+        previous = (memory, 0);
+    }
+
+    ShiftRegister {
+        data,
+        shift,
+        outputs,
+    }
+}
+
+pub struct Comparator {
+    pub a: Vec<ComponentId>,
+    pub b: Vec<ComponentId>,
+    pub equal: Pin,
+    /// `a < b`.
+    pub less: Pin,
+    /// `a > b`.
+    pub greater: Pin,
+}
+
+/// Compares two unsigned numbers.
+pub fn comparator(engine: &mut SimulationEngine, bits: usize) -> Comparator {
+    assert_ne!(bits, 0, "comparators need at least one bit");
+    let a = inputs(engine, bits);
+    let b = inputs(engine, bits);
+
+    let same: Vec<Pin> = (0..bits)
+        .map(|bit| {
+            let differ = xor(engine, (a[bit], 0), (b[bit], 0));
+            not(engine, differ)
+        })
+        .collect();
+    let equal = and(engine, &same);
+
+    // the most significant differing bit decides
+    let mut decided_by = |x: &[ComponentId], y: &[ComponentId]| {
+        let terms: Vec<Pin> = (0..bits)
+            .map(|bit| {
+                let not_y = not(engine, (y[bit], 0));
+                let mut term = vec![(x[bit], 0), not_y];
+                term.extend(&same[bit + 1..]);
+                and(engine, &term)
+            })
+            .collect();
+        or(engine, &terms)
+    };
+    let greater = decided_by(&a, &b);
+    let less = decided_by(&b, &a);
+
+    Comparator {
+        a,
+        b,
+        equal,
+        less,
+        greater,
+    }
+}
+
+pub struct Alu {
+    pub a: Vec<ComponentId>,
+    pub b: Vec<ComponentId>,
+    /// `0` adds, `1` subtracts, `2` ands and `3` ors, see [`AluOp`].
+    pub op: [ComponentId; 2],
+    pub result: Vec<Pin>,
+    /// Carry out of additions, or no borrow out of subtractions, off for the logic operations.
+    pub carry: Pin,
+    pub zero: Pin,
+}
+
+/// Values of [`Alu::op`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add = 0,
+    Sub = 1,
+    And = 2,
+    Or = 3,
+}
+
+pub fn alu(engine: &mut SimulationEngine, bits: usize) -> Alu {
+    assert_ne!(bits, 0, "ALUs need at least one bit");
+    let a = inputs(engine, bits);
+    let b = inputs(engine, bits);
+    let op = [input(engine), input(engine)];
+    let (subtract, logic) = ((op[0], 0), (op[1], 0));
+    let arithmetic = not(engine, logic);
+    let and_op = {
+        let not_subtract = not(engine, subtract);
+        and(engine, &[logic, not_subtract])
+    };
+    let or_op = and(engine, &[logic, subtract]);
+
+    // subtracts by adding the two's complement of `b`
+    let mut carry = subtract;
+    let mut result = vec![];
+    for bit in 0..bits {
+        let (a_bit, b_bit) = ((a[bit], 0), (b[bit], 0));
+        let b_operand = xor(engine, b_bit, subtract);
+        let adder = engine.add(ComponentKind::FullAdder);
+        wire(engine, a_bit, adder, 0);
+        wire(engine, b_operand, adder, 1);
+        wire(engine, carry, adder, 2);
+        carry = (adder, 1);
+
+        let a_and_b = and(engine, &[a_bit, b_bit]);
+        let a_or_b = or(engine, &[a_bit, b_bit]);
+        let terms = [
+            and(engine, &[(adder, 0), arithmetic]),
+            and(engine, &[a_and_b, and_op]),
+            and(engine, &[a_or_b, or_op]),
+        ];
+        result.push(or(engine, &terms));
+    }
+    let carry = and(engine, &[carry, arithmetic]);
+
+    // a `not` with every bit wired to its input is a NOR
+    let zero = engine.add(ComponentKind::Not);
+    for &bit in &result {
+        wire(engine, bit, zero, 0);
+    }
+
+    Alu {
+        a,
+        b,
+        op,
+        result,
+        carry,
+        zero: (zero, 0),
+    }
+}
+
+fn input(engine: &mut SimulationEngine) -> ComponentId {
+    engine.add(ComponentKind::And(1))
+}
+
+fn inputs(engine: &mut SimulationEngine, count: usize) -> Vec<ComponentId> {
+    (0..count).map(|_| input(engine)).collect()
+}
+
+fn pins(inputs: &[ComponentId]) -> Vec<Pin> {
+    inputs.iter().map(|&id| (id, 0)).collect()
+}
+
+fn wire(engine: &mut SimulationEngine, (parent, output): Pin, child: ComponentId, input: usize) {
+    engine
+        .wire(parent, child, output, input)
+        .expect("generated circuits are valid");
+}
+
+fn not(engine: &mut SimulationEngine, pin: Pin) -> Pin {
+    let gate = engine.add(ComponentKind::Not);
+    wire(engine, pin, gate, 0);
+    (gate, 0)
+}
+
+fn and(engine: &mut SimulationEngine, pins: &[Pin]) -> Pin {
+    let gate = engine.add(ComponentKind::And(pins.len()));
+    for (input, &pin) in pins.iter().enumerate() {
+        wire(engine, pin, gate, input);
+    }
+    (gate, 0)
+}
+
+/// Signals wired to the same input are OR-ed, except on delays.
+fn or(engine: &mut SimulationEngine, pins: &[Pin]) -> Pin {
+    let gate = engine.add(ComponentKind::And(1));
+    for &pin in pins {
+        wire(engine, pin, gate, 0);
+    }
+    (gate, 0)
+}
+
+fn xor(engine: &mut SimulationEngine, a: Pin, b: Pin) -> Pin {
+    let adder = engine.add(ComponentKind::HalfAdder);
+    wire(engine, a, adder, 0);
+    wire(engine, b, adder, 1);
+    (adder, 0)
+}
+
+/// One line per value of `pins`, on when it matches.
+fn decode(engine: &mut SimulationEngine, pins: &[Pin]) -> Vec<Pin> {
+    let inverted: Vec<Pin> = pins.iter().map(|&pin| not(engine, pin)).collect();
+    (0..1 << pins.len())
+        .map(|value: usize| {
+            let terms: Vec<Pin> = (0..pins.len())
+                .map(|bit| match value & (1 << bit) {
+                    0 => inverted[bit],
+                    _ => pins[bit],
+                })
+                .collect();
+            and(engine, &terms)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(engine: &mut SimulationEngine, inputs: &[ComponentId], value: u64) {
+        for (bit, &input) in inputs.iter().enumerate() {
+            engine.drive(input, 0, value & (1 << bit) != 0).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ripple_adder() {
+        let mut engine = SimulationEngine::new();
+        let adder = ripple_adder(&mut engine, 3);
+        for (a, b, carry) in [(0, 0, 0), (3, 4, 0), (5, 6, 1), (7, 7, 1)] {
+            set(&mut engine, &adder.a, a);
+            set(&mut engine, &adder.b, b);
+            set(&mut engine, &[adder.carry_in], carry);
+            engine.run_step();
+            let mut sum = adder.sum.clone();
+            sum.push(adder.carry_out);
+            assert_eq!(engine.bus_value(&sum).unwrap(), a + b + carry);
+        }
+    }
+
+    #[test]
+    fn test_multiplexer_and_demultiplexer() {
+        let mut engine = SimulationEngine::new();
+        let mux = multiplexer(&mut engine, 2);
+        let demux = demultiplexer(&mut engine, 2);
+        set(&mut engine, &mux.data, 0b0110);
+        set(&mut engine, &[demux.data], 1);
+
+        for select in 0..4 {
+            set(&mut engine, &mux.select, select);
+            set(&mut engine, &demux.select, select);
+            engine.run_step();
+            assert_eq!(
+                engine.is_on_at(mux.out.0, mux.out.1),
+                0b0110 & (1 << select) != 0
+            );
+            assert_eq!(engine.bus_value(&demux.outputs).unwrap(), 1 << select);
+        }
+    }
+
+    #[test]
+    fn test_decoder_and_encoder() {
+        let mut engine = SimulationEngine::new();
+        let decoder = decoder(&mut engine, 3);
+        let encoder = encoder(&mut engine, 3);
+        for (&line, &input) in decoder.outputs.iter().zip(&encoder.inputs) {
+            wire(&mut engine, line, input, 0);
+        }
+
+        for value in 0..8 {
+            set(&mut engine, &decoder.inputs, value);
+            engine.run_step();
+            assert_eq!(engine.bus_value(&decoder.outputs).unwrap(), 1 << value);
+            assert_eq!(engine.bus_value(&encoder.outputs).unwrap(), value);
+            assert!(engine.is_on(encoder.valid.0));
+        }
+    }
+
+    #[test]
+    fn test_register() {
+        let mut engine = SimulationEngine::new();
+        let register = register(&mut engine, 4);
+        set(&mut engine, &register.data, 0b1011);
+        set(&mut engine, &[register.load], 1);
+        engine.run_steps(2);
+        assert_eq!(engine.bus_value(&register.outputs).unwrap(), 0b1011);
+
+        // keeps its value while not loading
+        set(&mut engine, &[register.load], 0);
+        set(&mut engine, &register.data, 0b0100);
+        engine.run_steps(3);
+        assert_eq!(engine.bus_value(&register.outputs).unwrap(), 0b1011);
+    }
+
+    #[test]
+    fn test_counter() {
+        let mut engine = SimulationEngine::new();
+        let counter = counter(&mut engine, 3);
+        set(&mut engine, &[counter.enable], 1);
+        engine.run_steps(6);
+        assert_eq!(engine.bus_value(&counter.outputs).unwrap(), 5);
+        engine.run_steps(4);
+        assert_eq!(engine.bus_value(&counter.outputs).unwrap(), 1);
+
+        set(&mut engine, &[counter.reset], 1);
+        engine.run_steps(2);
+        assert_eq!(engine.bus_value(&counter.outputs).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_shift_register() {
+        let mut engine = SimulationEngine::new();
+        let shift_register = shift_register(&mut engine, 4);
+        set(&mut engine, &[shift_register.shift], 1);
+        for bit in [1, 1, 0, 1] {
+            set(&mut engine, &[shift_register.data], bit);
+            engine.run_step();
+        }
+        set(&mut engine, &[shift_register.shift], 0);
+        engine.run_steps(3);
+        // the last bit driven is shifted in by the tick after it
+        assert_eq!(engine.bus_value(&shift_register.outputs).unwrap(), 0b1101);
+    }
+
+    #[test]
+    fn test_comparator() {
+        let mut engine = SimulationEngine::new();
+        let comparator = comparator(&mut engine, 3);
+        for a in 0..8 {
+            for b in 0..8 {
+                set(&mut engine, &comparator.a, a);
+                set(&mut engine, &comparator.b, b);
+                engine.run_step();
+                let on = |(id, output): Pin| engine.is_on_at(id, output);
+                assert_eq!(on(comparator.equal), a == b);
+                assert_eq!(on(comparator.less), a < b);
+                assert_eq!(on(comparator.greater), a > b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_alu() {
+        let mut engine = SimulationEngine::new();
+        let alu = alu(&mut engine, 4);
+        let cases = [
+            (AluOp::Add, 9, 5, 14, false),
+            (AluOp::Add, 9, 8, 1, true),
+            (AluOp::Sub, 9, 5, 4, true),
+            (AluOp::Sub, 5, 9, 12, false),
+            (AluOp::Sub, 7, 7, 0, true),
+            (AluOp::And, 0b1100, 0b1010, 0b1000, false),
+            (AluOp::Or, 0b1100, 0b1010, 0b1110, false),
+        ];
+        for (op, a, b, result, carry) in cases {
+            set(&mut engine, &alu.op, op as u64);
+            set(&mut engine, &alu.a, a);
+            set(&mut engine, &alu.b, b);
+            engine.run_step();
+            assert_eq!(
+                engine.bus_value(&alu.result).unwrap(),
+                result,
+                "{op:?} {a} {b}"
+            );
+            assert_eq!(engine.is_on_at(alu.carry.0, alu.carry.1), carry);
+            assert_eq!(engine.is_on(alu.zero.0), result == 0);
+        }
+    }
+}
@@ -0,0 +1,420 @@
+//! Test vectors for circuits.
+//!
+//! ```text
+//! # the ports, resolved by name when running
+//! inputs a b carry_in
+//! outputs sum carry_out
+//! # optional, ticks to run for each vector before checking its outputs, defaults to 1
+//! ticks 2
+//!
+//! # `x` means don't care: inputs keep their last value and outputs aren't checked
+//! 0 0 0 | 0 0
+//! 1 1 0 | 0 1
+//! 1 1 1 | 1 x
+//! ```
+
+use std::{error::Error, fmt};
+
+use crate::{ComponentId, SimulationEngine};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Testbench {
+    pub inputs: Vec<String>,
+    pub outputs: Vec<String>,
+    pub ticks_per_vector: u64,
+    pub vectors: Vec<Vector>,
+}
+
+/// A row of the testbench, `None` stands for don't care.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vector {
+    pub inputs: Vec<Option<bool>>,
+    pub expected: Vec<Option<bool>>,
+    pub line: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub tick: u64,
+    /// Index of the failing vector.
+    pub vector: usize,
+    pub line: usize,
+    pub signal: String,
+    pub expected: bool,
+    pub actual: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestReport {
+    pub total: usize,
+    pub failed: usize,
+    pub first_mismatch: Option<Mismatch>,
+    /// Indexes of the vectors that failed.
+    pub failed_vectors: Vec<usize>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.failed == 0
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} vectors passed",
+            self.total - self.failed,
+            self.total
+        )?;
+        if let Some(mismatch) = &self.first_mismatch {
+            write!(
+                f,
+                ", first mismatch at tick {} (line {}): expected `{}` to be {}, got {}",
+                mismatch.tick,
+                mismatch.line,
+                mismatch.signal,
+                mismatch.expected as u8,
+                mismatch.actual as u8,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestbenchError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl TestbenchError {
+    fn new(line: usize, message: &str) -> Self {
+        Self {
+            line,
+            message: message.to_owned(),
+        }
+    }
+}
+
+impl fmt::Display for TestbenchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for TestbenchError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownSignal(pub String);
+
+impl fmt::Display for UnknownSignal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown signal `{}`", self.0)
+    }
+}
+
+impl Error for UnknownSignal {}
+
+impl Testbench {
+    pub fn parse(text: &str) -> Result<Self, TestbenchError> {
+        let mut testbench = Testbench {
+            inputs: vec![],
+            outputs: vec![],
+            ticks_per_vector: 1,
+            vectors: vec![],
+        };
+        let mut header: (Option<Vec<String>>, Option<Vec<String>>) = (None, None);
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let err = |message: &str| TestbenchError::new(line_number, message);
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            match words.next().unwrap() {
+                "inputs" => header.0 = Some(words.map(str::to_owned).collect()),
+                "outputs" => header.1 = Some(words.map(str::to_owned).collect()),
+                "ticks" => match words.collect::<Vec<_>>()[..] {
+                    [ticks] => {
+                        testbench.ticks_per_vector = ticks
+                            .parse()
+                            .ok()
+                            .filter(|&ticks| ticks > 0)
+                            .ok_or_else(|| err("expected a positive number of ticks"))?;
+                    }
+                    _ => return Err(err("expected `ticks <N>`")),
+                },
+                _ => {
+                    let (Some(inputs), Some(outputs)) = &header else {
+                        return Err(err("`inputs` and `outputs` must come before the vectors"));
+                    };
+                    let (input_values, expected_values) = line
+                        .split_once('|')
+                        .ok_or_else(|| err("expected `|` between inputs and outputs"))?;
+                    let bits = |values: &str, count: usize, what: &str| {
+                        let bits = values
+                            .split_whitespace()
+                            .map(|value| match value {
+                                "0" => Ok(Some(false)),
+                                "1" => Ok(Some(true)),
+                                "x" | "X" | "-" => Ok(None),
+                                _ => Err(err(&format!("invalid value `{value}`"))),
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        if bits.len() != count {
+                            return Err(err(&format!(
+                                "expected {count} {what} values, found {}",
+                                bits.len()
+                            )));
+                        }
+                        Ok(bits)
+                    };
+                    testbench.vectors.push(Vector {
+                        inputs: bits(input_values, inputs.len(), "input")?,
+                        expected: bits(expected_values, outputs.len(), "output")?,
+                        line: line_number,
+                    });
+                }
+            }
+        }
+
+        let (Some(inputs), Some(outputs)) = header else {
+            return Err(TestbenchError::new(
+                text.lines().count().max(1),
+                "missing `inputs` or `outputs`",
+            ));
+        };
+        testbench.inputs = inputs;
+        testbench.outputs = outputs;
+        Ok(testbench)
+    }
+
+    /// Drives the inputs of each vector and checks the outputs after running its ticks.
+    ///
+    /// `signal` resolves names to a component output. Inputs are put back as they were before
+    /// the run at the end, driven to the same value or released.
+    pub fn run(
+        &self,
+        engine: &mut SimulationEngine,
+        signal: impl Fn(&str) -> Option<(ComponentId, usize)>,
+    ) -> Result<TestReport, UnknownSignal> {
+        let resolve = |names: &[String]| {
+            names
+                .iter()
+                .map(|name| {
+                    signal(name)
+                        .filter(|&(id, output)| {
+                            engine
+                                .component(id)
+                                .is_ok_and(|component| output < component.kind.arity().1)
+                        })
+                        .ok_or_else(|| UnknownSignal(name.clone()))
+                })
+                .collect::<Result<Vec<_>, _>>()
+        };
+        let inputs = resolve(&self.inputs)?;
+        let outputs = resolve(&self.outputs)?;
+
+        let earlier: Vec<Option<bool>> = inputs
+            .iter()
+            .map(|&(id, output)| engine.driven_value(id, output))
+            .collect();
+        let report = self.run_vectors(engine, &inputs, &outputs);
+        for (&(id, output), value) in inputs.iter().zip(earlier) {
+            match value {
+                Some(value) => engine
+                    .drive(id, output, value)
+                    .expect("inputs were checked"),
+                None => engine.release(id, output),
+            }
+        }
+        report
+    }
+
+    fn run_vectors(
+        &self,
+        engine: &mut SimulationEngine,
+        inputs: &[(ComponentId, usize)],
+        outputs: &[(ComponentId, usize)],
+    ) -> Result<TestReport, UnknownSignal> {
+        let mut report = TestReport {
+            total: self.vectors.len(),
+            failed: 0,
+            first_mismatch: None,
+            failed_vectors: vec![],
+        };
+
+        for (index, vector) in self.vectors.iter().enumerate() {
+            for ((&(id, output), value), name) in
+                inputs.iter().zip(&vector.inputs).zip(&self.inputs)
+            {
+                if let Some(value) = *value {
+                    engine
+                        .drive(id, output, value)
+                        .map_err(|_| UnknownSignal(name.clone()))?;
+                }
+            }
+            engine.run_steps(self.ticks_per_vector);
+
+            let mut failed = false;
+            for ((&(id, output), expected), name) in
+                outputs.iter().zip(&vector.expected).zip(&self.outputs)
+            {
+                let actual = engine.is_on_at(id, output);
+                let Some(expected) = *expected else {
+                    continue;
+                };
+                if actual != expected {
+                    failed = true;
+                    report.first_mismatch.get_or_insert_with(|| Mismatch {
+                        tick: engine.current_tick(),
+                        vector: index,
+                        line: vector.line,
+                        signal: name.clone(),
+                        expected,
+                        actual,
+                    });
+                }
+            }
+            if failed {
+                report.failed += 1;
+                report.failed_vectors.push(index);
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hdl;
+
+    const FULL_ADDER: &str = "
+        inputs[3] = delay;
+        adder = full_adder;
+        inputs[0] -> adder.in0;
+        inputs[1] -> adder.in1;
+        inputs[2] -> adder.in2;
+    ";
+
+    fn run(testbench: &str) -> TestReport {
+        let mut netlist = hdl::compile(FULL_ADDER).unwrap();
+        let testbench = Testbench::parse(testbench).unwrap();
+        netlist.run_testbench(&testbench).unwrap()
+    }
+
+    #[test]
+    fn test_full_adder_passes() {
+        let report = run("
+            inputs inputs[0] inputs[1] inputs[2]
+            outputs adder.sum adder.carry
+            0 0 0 | 0 0
+            1 0 0 | 1 0
+            x 1 x | 0 1
+            0 x x | 1 x
+            1 1 1 | 1 1
+        ");
+        assert!(report.passed());
+        assert_eq!(report.to_string(), "5/5 vectors passed");
+    }
+
+    #[test]
+    fn test_first_mismatch() {
+        let report = run("
+            inputs inputs[0] inputs[1] inputs[2]
+            outputs adder.sum adder.carry
+            ticks 3
+            0 0 0 | 0 0
+            1 1 0 | 1 1
+            1 1 1 | 0 1
+        ");
+        assert!(!report.passed());
+        assert_eq!(report.failed, 2);
+        assert_eq!(
+            report.first_mismatch,
+            Some(Mismatch {
+                tick: 6,
+                vector: 1,
+                line: 6,
+                signal: "adder.sum".into(),
+                expected: true,
+                actual: false,
+            })
+        );
+        assert_eq!(
+            report.to_string(),
+            "1/3 vectors passed, first mismatch at tick 6 (line 6): expected `adder.sum` to be 1, got 0"
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let error = |text: &str| Testbench::parse(text).unwrap_err().to_string();
+
+        assert_eq!(
+            error("0 | 1"),
+            "line 1: `inputs` and `outputs` must come before the vectors"
+        );
+        assert_eq!(
+            error("inputs a\noutputs b\n0 1"),
+            "line 3: expected `|` between inputs and outputs"
+        );
+        assert_eq!(
+            error("inputs a\noutputs b\n0 0 | 1"),
+            "line 3: expected 1 input values, found 2"
+        );
+        assert_eq!(
+            error("inputs a\noutputs b\n0 | 2"),
+            "line 3: invalid value `2`"
+        );
+        assert_eq!(
+            error("inputs a\nticks 0"),
+            "line 2: expected a positive number of ticks"
+        );
+        assert_eq!(error("inputs a\n"), "line 1: missing `inputs` or `outputs`");
+    }
+
+    #[test]
+    fn test_unknown_signal() {
+        let mut netlist = hdl::compile(FULL_ADDER).unwrap();
+        let testbench = Testbench::parse("inputs nope\noutputs adder.sum\n0 | 0").unwrap();
+        let error = netlist.run_testbench(&testbench).unwrap_err();
+        assert_eq!(error, UnknownSignal("nope".into()));
+    }
+
+    #[test]
+    fn test_bad_output_is_unknown() {
+        let mut netlist = hdl::compile(FULL_ADDER).unwrap();
+        let adder = netlist.components["adder"];
+        let testbench = Testbench::parse("inputs a\noutputs b\n0 | 0").unwrap();
+        let signals = [("a", (adder, 0)), ("b", (adder, 2))];
+        let signal = |name: &str| signals.iter().find(|(n, _)| *n == name).map(|&(_, s)| s);
+        let error = testbench.run(&mut netlist.engine, signal).unwrap_err();
+        assert_eq!(error, UnknownSignal("b".into()));
+    }
+
+    #[test]
+    fn test_drives_are_restored() {
+        let mut netlist = hdl::compile(FULL_ADDER).unwrap();
+        let first = netlist.components["inputs[0]"];
+        let second = netlist.components["inputs[1]"];
+        netlist.engine.drive(first, 0, true).unwrap();
+
+        let testbench = Testbench::parse(
+            "
+            inputs inputs[0] inputs[1]
+            outputs adder.sum
+            0 1 | 1
+            ",
+        )
+        .unwrap();
+        netlist.run_testbench(&testbench).unwrap();
+        assert_eq!(netlist.engine.driven_value(first, 0), Some(true));
+        assert_eq!(netlist.engine.driven_value(second, 0), None);
+    }
+}
@@ -0,0 +1,128 @@
+//! Components that take ticks to update their outputs, to show propagation delays and glitches.
+
+use crate::{ComponentId, Logic, SimulationEngine, recording::Event};
+
+/// How long components take to update their outputs, see [`SimulationEngine::set_timing_mode`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimingMode {
+    /// Outputs follow the inputs within the tick, only delays take one.
+    #[default]
+    ZeroDelay,
+    /// Every component takes `ticks` ticks to update its outputs, computed from the values of
+    /// the tick before. Delays still take one.
+    UnitDelay { ticks: u32 },
+}
+
+impl SimulationEngine {
+    pub fn timing_mode(&self) -> TimingMode {
+        self.timing_mode
+    }
+
+    /// Drops the values on their way to the outputs, the outputs keep their current values.
+    pub fn set_timing_mode(&mut self, mode: TimingMode) {
+        if let TimingMode::UnitDelay { ticks } = mode {
+            assert_ne!(ticks, 0, "components take at least one tick");
+        }
+        self.in_flight.clear();
+        self.unstable.clear();
+        self.timing_mode = mode;
+        self.record(&[], |_| Event::TimingMode(mode));
+    }
+
+    /// The outputs of every component but delays, from the values of the tick before.
+    pub(crate) fn evaluate_gates(&mut self) -> Vec<(ComponentId, Vec<Logic>)> {
+        let ids: Vec<ComponentId> = self
+            .nodes
+            .iter()
+            .filter(|(_, component)| !component.kind.is_delay())
+            .map(|(id, _)| id)
+            .collect();
+        ids.into_iter()
+            .map(|id| {
+                let incoming_edges = self.incoming_to(id).collect();
+                (id, self.evaluate(id, incoming_edges))
+            })
+            .collect()
+    }
+
+    /// Queues the values behind the ones in flight, and updates the outputs with the values
+    /// that arrive.
+    pub(crate) fn propagate_gates(&mut self, values: Vec<(ComponentId, Vec<Logic>)>, ticks: u32) {
+        for (id, values) in values {
+            let values = match ticks {
+                1 => values,
+                _ => {
+                    let queue = self.in_flight.entry(id).or_default();
+                    queue.push_back(values);
+                    if queue.len() < ticks as usize {
+                        continue;
+                    }
+                    queue.pop_front().unwrap()
+                }
+            };
+            for (output, value) in values.into_iter().enumerate() {
+                let value = self.apply_overrides(id, output, value);
+                self.update_output(id, output, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ComponentKind::*;
+
+    #[test]
+    fn test_ripple() {
+        let mut sim = SimulationEngine::default();
+        sim.set_timing_mode(TimingMode::UnitDelay { ticks: 1 });
+        let [input, first, second, last] = sim.add_array_wired([Delay, Not, Not, Not]);
+        let values = |sim: &SimulationEngine| [input, first, second, last].map(|id| sim.is_on(id));
+        sim.run_step();
+        // every `not` saw an off input
+        assert_eq!(values(&sim), [false, true, true, true]);
+        sim.run_step();
+        assert_eq!(values(&sim), [false, true, false, false]);
+        sim.run_step();
+        assert_eq!(values(&sim), [false, true, false, true]);
+        sim.run_step();
+        assert!(sim.last_changes().is_empty());
+
+        sim.set_value(input, true);
+        sim.run_step();
+        assert!(sim.is_off(first) && sim.is_off(second) && sim.is_on(last));
+
+        sim.set_timing_mode(TimingMode::UnitDelay { ticks: 3 });
+        sim.set_value(input, false);
+        sim.run_steps(2);
+        assert!(sim.is_off(first));
+        sim.run_step();
+        assert!(sim.is_on(first));
+        assert_eq!(sim.last_changes(), [(first, 0), (second, 0)]);
+
+        let (copy, _) = SimulationEngine::load(&sim.save()).unwrap();
+        assert_eq!(copy.save(), sim.save());
+        assert_eq!(copy.in_flight, sim.in_flight);
+    }
+
+    #[test]
+    fn test_glitch() {
+        let mut sim = SimulationEngine::default();
+        sim.set_timing_mode(TimingMode::UnitDelay { ticks: 1 });
+        let input = sim.add(Delay);
+        let [not, and] = sim.add_array([Not, And(2)]);
+        sim.wire(input, not, 0, 0).unwrap();
+        sim.wire(input, and, 0, 0).unwrap();
+        sim.wire(not, and, 0, 1).unwrap();
+        sim.run_steps(3);
+        assert!(sim.is_off(and));
+
+        // `and` sees the input rise before the `not` falls
+        sim.set_value(input, true);
+        sim.run_step();
+        assert!(sim.is_on(and));
+        sim.run_step();
+        assert!(sim.is_off(and));
+    }
+}